@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+
+use egui::{Align, CtxRef, Key, ScrollArea, Window};
+use server::{chat_text, ChatMessage, CHAT_HISTORY};
+
+#[derive(Default)]
+pub struct ChatWindow {
+    pub open: bool,
+    messages: VecDeque<ChatMessage>,
+    input: String,
+    focus: bool,
+}
+
+impl ChatWindow {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.focus = self.open;
+    }
+
+    pub fn set_history(&mut self, messages: Vec<ChatMessage>) {
+        self.messages = messages.into();
+    }
+
+    pub fn receive(&mut self, message: ChatMessage) {
+        self.messages.push_back(message);
+        if self.messages.len() > CHAT_HISTORY {
+            self.messages.pop_front();
+        }
+    }
+
+    /// Renders the window and returns the message to send when the user submitted one.
+    pub fn render(&mut self, ctx: &CtxRef) -> Option<String> {
+        let mut open = self.open;
+        let mut submitted = None;
+        Window::new("Chat").open(&mut open).show(ctx, |ui| {
+            ScrollArea::from_max_height(300.0).show(ui, |ui| {
+                for message in self.messages.iter() {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.weak(time_of_day(message.time));
+                        ui.strong(&message.name);
+                        ui.label(&message.text);
+                    });
+                }
+                ui.scroll_to_cursor(Align::BOTTOM);
+            });
+            let response = ui.text_edit_singleline(&mut self.input);
+            if self.focus {
+                response.request_focus();
+                self.focus = false;
+            }
+            if response.lost_focus() && ui.input().key_pressed(Key::Enter) {
+                if let Some(text) = chat_text(&self.input) {
+                    submitted = Some(text.to_owned());
+                }
+                self.input.clear();
+                response.request_focus();
+            }
+        });
+        self.open = open;
+        submitted
+    }
+}
+
+/// Formats a time in seconds since the Unix epoch as hours and minutes in UTC.
+fn time_of_day(time: u64) -> String {
+    let minutes = time / 60;
+    format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+}
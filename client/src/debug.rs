@@ -12,7 +12,7 @@ use egui::{
 };
 use log::{set_max_level, Level, LevelFilter};
 use net::{ConnectionStats, ConnectionStatsDiff};
-use server::{Connection, Request};
+use server::{Connection, Request, SaveReport};
 
 use crate::renderer::RenderTimestamps;
 
@@ -32,6 +32,7 @@ pub struct DebugContext {
     log_scroll_to_bottom: bool,
     log_limit_entries: bool,
     log_entry_limit: usize,
+    last_save: Option<(Instant, SaveReport)>,
 }
 
 #[derive(Default)]
@@ -39,6 +40,7 @@ pub struct DebugWindows {
     pub frame_times: bool,
     pub information: bool,
     pub log: bool,
+    pub players: bool,
 }
 
 impl DebugContext {
@@ -46,6 +48,10 @@ impl DebugContext {
         self.frame_start = Instant::now();
     }
 
+    pub fn saved(&mut self, report: SaveReport) {
+        self.last_save = Some((Instant::now(), report));
+    }
+
     pub fn log(&mut self, level: Level, target: String, args: String) {
         self.log_records.push_back((level, target, args));
         if self.log_limit_entries && self.log_entry_limit < self.log_records.len() {
@@ -99,6 +105,24 @@ impl DebugContext {
                     self.stats_diff.rx.datagrams,
                 ));
             }
+            match &self.last_save {
+                Some((time, report)) if report.failed => {
+                    ui.colored_label(
+                        Color32::RED,
+                        format!("Saving failed {}s ago", time.elapsed().as_secs()),
+                    );
+                }
+                Some((time, report)) => {
+                    ui.label(format!(
+                        "Saved {}s ago in {} ms",
+                        time.elapsed().as_secs(),
+                        report.duration.as_millis()
+                    ));
+                }
+                None => {
+                    ui.label("Not saved yet");
+                }
+            }
         });
     }
 
@@ -244,10 +268,22 @@ impl DebugContext {
         });
     }
 
-    pub fn render(&mut self, ctx: &CtxRef, windows: &mut DebugWindows) {
+    fn render_players(&mut self, ctx: &CtxRef, open: &mut bool, players: &[&str]) {
+        Window::new("Players").open(open).show(ctx, |ui| {
+            ui.label(format!("{} players in view", players.len()));
+            ScrollArea::from_max_height(600.0).show(ui, |ui| {
+                for name in players {
+                    ui.label(*name);
+                }
+            });
+        });
+    }
+
+    pub fn render(&mut self, ctx: &CtxRef, windows: &mut DebugWindows, players: &[&str]) {
         self.render_information(ctx, &mut windows.information);
         self.render_frame_times(ctx, &mut windows.frame_times);
         self.render_log(ctx, &mut windows.log);
+        self.render_players(ctx, &mut windows.players, players);
     }
 }
 
@@ -269,6 +305,7 @@ impl Default for DebugContext {
             log_scroll_to_bottom: true,
             log_limit_entries: false,
             log_entry_limit: 20,
+            last_save: None,
         }
     }
 }
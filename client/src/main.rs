@@ -14,6 +14,7 @@ use ::winit::{
     window::{Fullscreen, Window, WindowBuilder},
 };
 use ash_window::{create_surface, enumerate_required_extensions};
+use chat::ChatWindow;
 use context::Context;
 use debug::DebugWindows;
 use eyre::{eyre, Context as EyreContext};
@@ -29,12 +30,16 @@ use scene::{ControlState, Object, Transform};
 use semver::Version;
 use server::Orientation;
 use server::SelfUpdate;
-use server::{Connection, Player, Position, Push, Request, Service, Setup, Update, UpdateBatch};
+use server::{
+    ChunkBatch, ChunkCoord, Connection, Player, Position, Push, Request, Service, Setup, Update,
+    UpdateBatch, MAX_SPEED,
+};
 use structopt::StructOpt;
 use tokio::{runtime::Runtime, spawn, task::JoinHandle};
 use util::{handle::HandleFlow, iterator::MaxOkFilterMap};
 use uuid::Uuid;
 
+mod chat;
 mod context;
 mod cull;
 mod debug;
@@ -64,8 +69,10 @@ struct Application {
     server: Option<Server<Service>>,
     connection: Connection<Request>,
     windows: DebugWindows,
+    chat: ChatWindow,
     uuid: Uuid,
-    other_players: HashMap<Uuid, (Player, usize)>,
+    other_players: HashMap<Uuid, (Player, String, usize)>,
+    chunks: HashMap<ChunkCoord, Vec<Object>>,
     handle: Option<JoinHandle<()>>,
 }
 
@@ -149,8 +156,10 @@ impl Application {
             server,
             connection,
             windows: DebugWindows::default(),
+            chat: ChatWindow::default(),
             uuid: Uuid::nil(),
             other_players: HashMap::new(),
+            chunks: HashMap::new(),
             handle: Some(handle),
         })
     }
@@ -209,6 +218,19 @@ impl Application {
                                             self.windows.log = !self.windows.log;
                                         }
                                     }
+                                    VirtualKeyCode::F4 => {
+                                        if input.state == ElementState::Pressed {
+                                            self.windows.players = !self.windows.players;
+                                        }
+                                    }
+                                    VirtualKeyCode::Return => {
+                                        if input.state == ElementState::Pressed {
+                                            self.chat.toggle();
+                                            if self.chat.open {
+                                                self.set_grab(false)?;
+                                            }
+                                        }
+                                    }
                                     VirtualKeyCode::F9 => {
                                         if input.state == ElementState::Pressed {
                                             self.vsync = !self.vsync;
@@ -266,26 +288,17 @@ impl Application {
                 if let Event::UserEvent(message) = event {
                     match message {
                         ApplicationMessage::Push(push) => match push {
-                            Push::Setup(Setup(uuid, players, positions)) => {
+                            Push::Setup(Setup(uuid, players, chat)) => {
                                 self.uuid = uuid;
+                                self.chat.set_history(chat);
                                 self.other_players.clear();
-                                let mut static_group = Vec::new();
-                                for pos in positions {
-                                    static_group.push(Object {
-                                        model: self.context.cube_model,
-                                        transform: Transform {
-                                            translation: Vector3::new(pos.x, pos.y, pos.z),
-                                            scale: Vector3::new(0.3, 0.3, 0.3),
-                                            rotation: UnitQuaternion::identity(),
-                                        },
-                                    });
-                                }
+                                self.chunks.clear();
                                 let mut player_group = Vec::new();
-                                for player in players {
+                                for (player, name) in players {
                                     let uuid = Uuid::from_u128(player.uuid);
                                     if self.uuid != uuid {
                                         self.other_players
-                                            .insert(uuid, (player, player_group.len()));
+                                            .insert(uuid, (player, name, player_group.len()));
                                         player_group.push(Object {
                                             model: self.context.cube_model,
                                             transform: Transform {
@@ -307,17 +320,46 @@ impl Application {
                                     }
                                 }
                                 self.context.scene.groups.clear();
-                                self.context.scene.groups.push(static_group);
+                                self.context.scene.groups.push(Vec::new());
                                 self.context.scene.groups.push(player_group);
                             }
-                            Push::Updates(UpdateBatch(updates, after_index)) => {
-                                for update in &updates[after_index..] {
+                            Push::Chat(message) => self.chat.receive(message),
+                            Push::Saved(report) => self.context.debug.saved(report),
+                            Push::Correction(position) => {
+                                self.context.scene.camera.translation =
+                                    Translation3::new(position.x, position.y, position.z);
+                            }
+                            Push::Chunks(ChunkBatch(load, unload)) => {
+                                for coord in unload {
+                                    self.chunks.remove(&coord);
+                                }
+                                for (coord, chunk) in load {
+                                    let objects = chunk
+                                        .positions(coord)
+                                        .map(|pos| Object {
+                                            model: self.context.cube_model,
+                                            transform: Transform {
+                                                translation: Vector3::new(pos.x, pos.y, pos.z),
+                                                scale: Vector3::new(0.3, 0.3, 0.3),
+                                                rotation: UnitQuaternion::identity(),
+                                            },
+                                        })
+                                        .collect();
+                                    self.chunks.insert(coord, objects);
+                                }
+                                self.context.scene.groups[0] =
+                                    self.chunks.values().flatten().copied().collect();
+                            }
+                            Push::Updates(UpdateBatch(_, updates)) => {
+                                for update in &updates {
                                     match update {
-                                        Update::NewPlayer(player) => {
+                                        Update::NewPlayer(player, name) => {
+                                            let uuid = Uuid::from_u128(player.uuid);
+                                            self.remove_player(uuid);
                                             let player_group = &mut self.context.scene.groups[1];
                                             self.other_players.insert(
-                                                Uuid::from_u128(player.uuid),
-                                                (*player, player_group.len()),
+                                                uuid,
+                                                (*player, name.clone(), player_group.len()),
                                             );
                                             player_group.push(Object {
                                                 model: self.context.cube_model,
@@ -333,11 +375,11 @@ impl Application {
                                             })
                                         }
                                         Update::Player(uuid, pos, orientation) => {
-                                            if self.uuid != *uuid {
+                                            if let Some((player, _, index)) =
+                                                self.other_players.get_mut(uuid)
+                                            {
                                                 let player_group =
                                                     &mut self.context.scene.groups[1];
-                                                let (player, index) =
-                                                    self.other_players.get_mut(uuid).unwrap();
                                                 player.position = *pos;
                                                 player_group[*index].transform.rotation =
                                                     (Rotation3::from_axis_angle(
@@ -359,6 +401,9 @@ impl Application {
                                                     );
                                             }
                                         }
+                                        Update::OutOfView(uuid) | Update::PlayerLeft(uuid) => {
+                                            self.remove_player(*uuid)
+                                        }
                                     }
                                 }
                             }
@@ -381,12 +426,27 @@ impl Application {
         self.connection.send(Request::Shutdown).await?;
         self.handle.take().unwrap().await?;
         if let Some(server) = self.server.as_mut() {
-            server.service().stop().await;
+            server.service().stop().await?;
             server.close();
         }
         Ok(())
     }
 
+    /// Removes the avatar of another player, moving the last avatar into its place.
+    fn remove_player(&mut self, uuid: Uuid) {
+        if let Some((_, _, index)) = self.other_players.remove(&uuid) {
+            let player_group = &mut self.context.scene.groups[1];
+            player_group.swap_remove(index);
+            if index < player_group.len() {
+                for (_, _, other) in self.other_players.values_mut() {
+                    if *other == player_group.len() {
+                        *other = index;
+                    }
+                }
+            }
+        }
+    }
+
     fn is_setup(&self) -> bool {
         !self.uuid.is_nil()
     }
@@ -396,10 +456,20 @@ impl Application {
             self.update().await;
             self.context.debug.begin_frame();
             let ctx = self.context.egui.begin();
-            self.context.debug.render(&ctx, &mut self.windows);
+            let mut players: Vec<_> = self
+                .other_players
+                .values()
+                .map(|(_, name, _)| name.as_str())
+                .collect();
+            players.sort_unstable();
+            self.context.debug.render(&ctx, &mut self.windows, &players);
+            let message = self.chat.render(&ctx);
             self.context
                 .egui
                 .end(if self.grab { None } else { Some(&self.window) })?;
+            if let Some(text) = message {
+                let _ = self.connection.send(Request::Chat(text)).await;
+            }
             let result = self.renderer.render(&self.device, &mut self.context);
             let (resize, timestamps) = match result {
                 Ok(result) => (result.suboptimal, result.timestamps),
@@ -447,7 +517,7 @@ impl Application {
         let now = Instant::now();
         let duration = now.duration_since(self.last_update);
         self.last_update = now;
-        let distance = duration.as_secs_f32() * 10.0;
+        let distance = duration.as_secs_f32() * MAX_SPEED;
         self.time += duration.as_secs_f32();
         let mut translation = Vector3::<f32>::zeros();
         if self.control_state.forward {
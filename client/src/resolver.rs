@@ -1,7 +1,10 @@
-use std::{io, sync::Arc};
+use std::sync::Arc;
 
 use net::{ResolveSuccess, Verification};
-use server::{create_world, AuthenticationError, CreateServiceError, Service};
+use server::{
+    create_world, AuthConfig, AuthenticationError, CreateServiceError, CreateWorldError,
+    GeneratorSettings, ServerConfig, Service,
+};
 use thiserror::Error;
 
 pub enum Resolver {
@@ -24,7 +27,7 @@ pub enum Resolver {
 #[derive(Debug, Error)]
 pub enum ResolveError {
     #[error("could not create world")]
-    CreateWorld(#[source] io::Error),
+    CreateWorld(#[source] CreateWorldError),
     #[error("could not create service")]
     CreateService(#[source] CreateServiceError),
     #[error(transparent)]
@@ -35,15 +38,20 @@ impl Resolver {
     pub async fn resolve(self) -> Result<ResolveSuccess<Service>, ResolveError> {
         Ok(match self {
             Resolver::Create { token, port } => {
-                create_world().map_err(ResolveError::CreateWorld)?;
+                create_world("world.db", &GeneratorSettings::default(), None)
+                    .map_err(ResolveError::CreateWorld)?;
                 net::Resolver::Local {
-                    service: Arc::new(Service::new().map_err(ResolveError::CreateService)?),
+                    service: Arc::new(
+                        Service::new(None, local_config()).map_err(ResolveError::CreateService)?,
+                    ),
                     token,
                     port,
                 }
             }
             Resolver::Open { token, port } => net::Resolver::Local {
-                service: Arc::new(Service::new().map_err(ResolveError::CreateService)?),
+                service: Arc::new(
+                    Service::new(None, local_config()).map_err(ResolveError::CreateService)?,
+                ),
                 token,
                 port,
             },
@@ -67,3 +75,15 @@ impl Resolver {
         .await?)
     }
 }
+
+/// The configuration of servers the client hosts itself, where the world lies in the current
+/// directory and anyone may join.
+fn local_config() -> ServerConfig {
+    ServerConfig {
+        auth: AuthConfig {
+            insecure: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
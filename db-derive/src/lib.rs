@@ -0,0 +1,123 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields};
+
+/// Derives `db::Object` for a struct whose fields all implement `db::Field`.
+///
+/// Fields are serialized in declaration order. The format is a fingerprint of the struct name and
+/// of the name and `Field::describe` of every field, so databases written by a different layout
+/// are rejected instead of being misread. The generated code refers to the database crate as `db`.
+#[proc_macro_derive(Object)]
+pub fn derive_object(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Derives `db::Layout` for a `Pod` struct whose fields all implement `db::Layout`.
+///
+/// The struct is described by the name and `Layout::describe` of every field in declaration order,
+/// or only by the layouts for a tuple struct. The name of the struct itself is left out, so that
+/// renaming a type does not invalidate databases that store it.
+#[proc_macro_derive(Layout)]
+pub fn derive_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_layout(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand_layout(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let (fields, open, close) = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => (&fields.named, "{", "}"),
+            Fields::Unnamed(fields) => (&fields.unnamed, "(", ")"),
+            Fields::Unit => {
+                return Err(Error::new(
+                    input.span(),
+                    "Layout can not be derived for unit structs",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Layout can only be derived for structs",
+            ))
+        }
+    };
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let descriptions = fields.iter().map(|field| {
+        let ty = &field.ty;
+        match &field.ident {
+            Some(ident) => {
+                let ident = format!("{}: ", ident);
+                quote! { format!("{}{}", #ident, <#ty as db::Layout>::describe()) }
+            }
+            None => quote! { <#ty as db::Layout>::describe() },
+        }
+    });
+    Ok(quote! {
+        impl #impl_generics db::Layout for #name #type_generics #where_clause {
+            fn describe() -> String {
+                let fields: &[String] = &[#(#descriptions),*];
+                format!("{}{}{}", #open, fields.join(", "), #close)
+            }
+        }
+    })
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "Object can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Object can only be derived for structs",
+            ))
+        }
+    };
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+    let descriptions = fields.iter().map(|field| {
+        let ident = field.ident.as_ref().unwrap().to_string();
+        let ty = &field.ty;
+        quote! { (#ident, <#ty as db::Field>::describe()) }
+    });
+    let type_name = name.to_string();
+    Ok(quote! {
+        impl #impl_generics db::Object for #name #type_generics #where_clause {
+            fn format() -> db::Format {
+                db::fingerprint(#type_name, &[#(#descriptions),*])
+            }
+
+            fn serialize(&mut self, mut writer: impl std::io::Write) -> std::io::Result<()> {
+                #(db::Field::serialize(&self.#idents, &mut writer)?;)*
+                Ok(())
+            }
+
+            fn deserialize(
+                mut reader: impl std::io::Read,
+                database: db::DatabaseRef,
+            ) -> std::io::Result<Self> {
+                Ok(Self {
+                    #(#idents: db::Field::deserialize(&mut reader, database.clone())?,)*
+                })
+            }
+        }
+    })
+}
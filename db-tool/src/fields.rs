@@ -0,0 +1,140 @@
+use std::{alloc::Layout, convert::TryInto, fmt::Write, mem::align_of, str::FromStr};
+
+/// The layout of a plain old data type, given as a comma-separated list of primitive fields
+/// like `u32,f32,f32` laid out as with `#[repr(C)]`. `xN` stands for N bytes that are shown in
+/// hex.
+pub struct Fields {
+    fields: Vec<(usize, Kind)>,
+    layout: Layout,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Bytes(usize),
+}
+
+impl Fields {
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Formats a value with this layout. Single fields are shown bare, others as a tuple.
+    pub fn format(&self, bytes: &[u8]) -> String {
+        let values: Vec<_> = self
+            .fields
+            .iter()
+            .map(|(offset, kind)| kind.format(&bytes[*offset..*offset + kind.size()]))
+            .collect();
+        if values.len() == 1 {
+            values.into_iter().next().unwrap()
+        } else {
+            format!("({})", values.join(", "))
+        }
+    }
+}
+
+impl FromStr for Fields {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = Vec::new();
+        let mut size = 0;
+        let mut align = 1;
+        for field in s.split(',') {
+            let kind = field.trim().parse::<Kind>()?;
+            size = (size + kind.align() - 1) / kind.align() * kind.align();
+            fields.push((size, kind));
+            size += kind.size();
+            align = align.max(kind.align());
+        }
+        let layout = Layout::from_size_align(size, align)
+            .map_err(|error| error.to_string())?
+            .pad_to_align();
+        Ok(Self { fields, layout })
+    }
+}
+
+impl Kind {
+    fn size(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::F32 => 4,
+            Self::U64 | Self::I64 | Self::F64 => 8,
+            Self::U128 | Self::I128 => 16,
+            Self::Bytes(len) => len,
+        }
+    }
+
+    fn align(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 | Self::Bytes(_) => 1,
+            Self::U16 | Self::I16 => align_of::<u16>(),
+            Self::U32 | Self::I32 | Self::F32 => align_of::<u32>(),
+            Self::U64 | Self::I64 | Self::F64 => align_of::<u64>(),
+            Self::U128 | Self::I128 => align_of::<u128>(),
+        }
+    }
+
+    fn format(self, bytes: &[u8]) -> String {
+        match self {
+            Self::U8 => bytes[0].to_string(),
+            Self::U16 => u16::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::U32 => u32::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::U64 => u64::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::U128 => u128::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::I8 => (bytes[0] as i8).to_string(),
+            Self::I16 => i16::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::I32 => i32::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::I64 => i64::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::I128 => i128::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::F32 => f32::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::F64 => f64::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            Self::Bytes(_) => {
+                let mut hex = String::with_capacity(2 + 2 * bytes.len());
+                hex.push_str("0x");
+                for byte in bytes {
+                    write!(hex, "{:02x}", byte).unwrap();
+                }
+                hex
+            }
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            "u64" => Self::U64,
+            "u128" => Self::U128,
+            "i8" => Self::I8,
+            "i16" => Self::I16,
+            "i32" => Self::I32,
+            "i64" => Self::I64,
+            "i128" => Self::I128,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            _ => match s.strip_prefix('x').map(str::parse) {
+                Some(Ok(len)) if len > 0 => Self::Bytes(len),
+                _ => return Err(format!("unknown field type '{}'", s)),
+            },
+        })
+    }
+}
@@ -0,0 +1,44 @@
+const LINE_LEN: usize = 16;
+
+/// Prints bytes in the usual hexdump format, starting with the given offset. Repeated lines
+/// are collapsed into a `*`.
+pub fn hexdump(offset: usize, bytes: &[u8]) {
+    let mut previous: Option<&[u8]> = None;
+    let mut collapsed = false;
+    for (index, line) in bytes.chunks(LINE_LEN).enumerate() {
+        if previous == Some(line) {
+            if !collapsed {
+                println!("*");
+                collapsed = true;
+            }
+            continue;
+        }
+        previous = Some(line);
+        collapsed = false;
+        let mut hex = String::with_capacity(3 * LINE_LEN + 1);
+        for (i, byte) in line.iter().enumerate() {
+            if i == LINE_LEN / 2 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let text: String = line
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!(
+            "{:08x}  {:<width$} |{}|",
+            offset + index * LINE_LEN,
+            hex,
+            text,
+            width = 3 * LINE_LEN + 1
+        );
+    }
+    println!("{:08x}", offset + bytes.len());
+}
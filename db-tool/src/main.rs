@@ -0,0 +1,220 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::PathBuf,
+};
+
+use db::{FreeListInfo, Inspector, SnapshotInfo};
+use fields::Fields;
+use hexdump::hexdump;
+use server::{export_world, import_world, world_format};
+use structopt::StructOpt;
+
+mod fields;
+mod hexdump;
+
+#[derive(StructOpt)]
+struct Options {
+    /// Secret the world database was encrypted with
+    #[structopt(long, env("WOSIM_WORLD_SECRET"), hide_env_values = true)]
+    world_secret: Option<String>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Prints the format and both snapshot slots of the header
+    Header {
+        #[structopt(default_value = "world.db")]
+        path: PathBuf,
+    },
+    /// Prints the free lists of the allocator
+    FreeLists {
+        #[structopt(default_value = "world.db")]
+        path: PathBuf,
+    },
+    /// Hexdumps the root file, which holds the roots of the collections
+    Root {
+        #[structopt(default_value = "world.db")]
+        path: PathBuf,
+    },
+    /// Prints the entries of the tree at a root page
+    Tree {
+        root: u32,
+        /// Fields of the key, e.g. "u128"
+        #[structopt(long)]
+        key: Fields,
+        /// Fields of the value, e.g. "u32,f32"
+        #[structopt(long)]
+        value: Fields,
+        #[structopt(default_value = "world.db")]
+        path: PathBuf,
+    },
+    /// Prints the elements of the vector at a root page
+    Vec {
+        root: u32,
+        len: usize,
+        /// Fields of an element, e.g. "f32,f32,f32"
+        #[structopt(long)]
+        element: Fields,
+        #[structopt(default_value = "world.db")]
+        path: PathBuf,
+    },
+    /// Hexdumps pages as they are stored, after decryption
+    Hexdump {
+        page: u32,
+        #[structopt(long, default_value = "1")]
+        count: u32,
+        #[structopt(default_value = "world.db")]
+        path: PathBuf,
+    },
+    /// Writes the collections of a world as JSON
+    Export {
+        #[structopt(default_value = "world.db")]
+        path: PathBuf,
+        /// Writes to a file instead of stdout
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Creates a world database from exported JSON
+    Import { input: PathBuf, path: PathBuf },
+}
+
+#[derive(Debug)]
+enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Json(error)
+    }
+}
+
+impl Command {
+    fn run(self, secret: Option<&[u8]>) -> Result<(), Error> {
+        match self {
+            Command::Header { path } => {
+                let inspector = Inspector::open(path, secret)?;
+                let format = inspector.format();
+                let kind = if *format == world_format() {
+                    "world"
+                } else {
+                    "unknown"
+                };
+                println!("format     {} ({})", hex(&format[..16]), kind);
+                println!("encrypted  {}", inspector.is_encrypted());
+                println!("pages      {}", inspector.page_count());
+                match inspector.current() {
+                    Some(version) => println!("current    {}", version),
+                    None => println!("current    none"),
+                }
+                for (index, snapshot) in inspector.snapshots().iter().enumerate() {
+                    print_snapshot(index, snapshot);
+                }
+            }
+            Command::FreeLists { path } => {
+                let inspector = Inspector::open(path, secret)?;
+                let [previous, current] = inspector.free_lists()?;
+                print_free_list("previous", &previous);
+                print_free_list("current", &current);
+            }
+            Command::Root { path } => hexdump(0, &Inspector::open(path, secret)?.root()?),
+            Command::Tree {
+                root,
+                key,
+                value,
+                path,
+            } => {
+                let inspector = Inspector::open(path, secret)?;
+                let mut len = 0;
+                inspector.tree(root, key.layout(), value.layout(), |k, v| {
+                    println!("{} => {}", key.format(k), value.format(v));
+                    len += 1;
+                })?;
+                println!("{} entries", len);
+            }
+            Command::Vec {
+                root,
+                len,
+                element,
+                path,
+            } => {
+                let inspector = Inspector::open(path, secret)?;
+                inspector.vec(root, len, element.layout(), |index, bytes| {
+                    println!("{}\t{}", index, element.format(bytes))
+                })?;
+            }
+            Command::Hexdump { page, count, path } => {
+                let inspector = Inspector::open(path, secret)?;
+                for nr in page..page.saturating_add(count) {
+                    let page = inspector.page(nr)?;
+                    println!("page {}", nr);
+                    hexdump(nr as usize * page.len(), &page);
+                }
+            }
+            Command::Export { path, output } => {
+                let data = export_world(path, secret)?;
+                let mut writer: Box<dyn Write> = match output {
+                    Some(output) => Box::new(BufWriter::new(File::create(output)?)),
+                    None => Box::new(io::stdout()),
+                };
+                serde_json::to_writer_pretty(&mut writer, &data)?;
+                writeln!(writer)?;
+            }
+            Command::Import { input, path } => {
+                let data = serde_json::from_reader(BufReader::new(File::open(input)?))?;
+                import_world(path, data, secret)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_snapshot(index: usize, snapshot: &SnapshotInfo) {
+    let validity = if snapshot.valid { "valid" } else { "invalid" };
+    println!(
+        "slot {}     version {} ({})",
+        index, snapshot.version, validity
+    );
+    println!("  time     {}", snapshot.time);
+    println!("  oldest   {}", snapshot.oldest);
+    println!(
+        "  root     page {}, {} bytes",
+        snapshot.root_nr, snapshot.root_len
+    );
+    println!(
+        "  history  page {}, {} bytes",
+        snapshot.history_nr, snapshot.history_len
+    );
+    println!("  pages    {}", snapshot.last_page + 1);
+}
+
+fn print_free_list(name: &str, list: &FreeListInfo) {
+    println!(
+        "{}: root {}, front {}, back {}",
+        name, list.root, list.front, list.back
+    );
+    for line in list.entries.chunks(16) {
+        let line: Vec<_> = line.iter().map(u32::to_string).collect();
+        println!("  {}", line.join(" "));
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn main() -> Result<(), Error> {
+    let options = Options::from_args();
+    let secret = options.world_secret.as_ref().map(String::as_bytes);
+    options.command.run(secret)
+}
@@ -4,6 +4,7 @@ use bytemuck::{Pod, Zeroable};
 
 use crate::{
     free_list::FreeList,
+    history::Pins,
     page::{PageNr, Pager},
 };
 
@@ -20,6 +21,21 @@ impl AllocatorState {
         swap(&mut self.previous_free, &mut self.current_free);
         self.current_free.reset_front();
     }
+
+    pub fn last_page(&self) -> PageNr {
+        self.last_page
+    }
+
+    /// The free list of the previous snapshot, then that of the current one.
+    pub fn free_lists(&self) -> [&FreeList; 2] {
+        [&self.previous_free, &self.current_free]
+    }
+
+    pub unsafe fn free_pages<'a>(&'a self, pager: &'a Pager) -> impl Iterator<Item = PageNr> + 'a {
+        self.previous_free
+            .entries(pager)
+            .chain(self.current_free.entries(pager))
+    }
 }
 
 impl Default for AllocatorState {
@@ -34,15 +50,21 @@ impl Default for AllocatorState {
 
 pub struct Allocator<'a> {
     state: MutexGuard<'a, AllocatorState>,
+    pins: MutexGuard<'a, Pins>,
     pager: &'a Pager,
     append: Vec<u32>,
     prepend: Vec<u32>,
 }
 
 impl<'a> Allocator<'a> {
-    pub fn new(state: MutexGuard<'a, AllocatorState>, pager: &'a Pager) -> Self {
+    pub fn new(
+        state: MutexGuard<'a, AllocatorState>,
+        pins: MutexGuard<'a, Pins>,
+        pager: &'a Pager,
+    ) -> Self {
         Self {
             state,
+            pins,
             pager,
             append: Vec::new(),
             prepend: Vec::new(),
@@ -50,15 +72,20 @@ impl<'a> Allocator<'a> {
     }
 
     pub fn allocate(&mut self) -> PageNr {
-        let nr = if let Some(nr) = self.append.pop() {
-            nr
-        } else if let Some(nr) = unsafe { self.state.current_free.pop_back(self.pager) } {
-            nr
-        } else if let Some(nr) = unsafe { self.state.previous_free.pop_back(self.pager) } {
-            nr
-        } else {
-            self.state.last_page += 1;
-            self.state.last_page
+        let nr = loop {
+            let nr = if let Some(nr) = self.append.pop() {
+                nr
+            } else if let Some(nr) = unsafe { self.state.current_free.pop_back(self.pager) } {
+                nr
+            } else if let Some(nr) = unsafe { self.state.previous_free.pop_back(self.pager) } {
+                nr
+            } else {
+                self.state.last_page += 1;
+                self.state.last_page
+            };
+            if !self.pins.hold(nr) {
+                break nr;
+            }
         };
         unsafe { self.pager.enable_write(nr) };
         nr
@@ -77,11 +104,12 @@ impl<'a> Allocator<'a> {
 
     pub unsafe fn reallocate(&mut self, nr: PageNr) -> PageNr {
         self.prepend.push(nr);
-        if let Some(new_nr) = self.state.current_free.shift_front(self.pager) {
-            self.pager.enable_write(new_nr);
-            new_nr
-        } else {
-            self.allocate()
+        match self.state.current_free.shift_front(self.pager) {
+            Some(new_nr) if !self.pins.hold(new_nr) => {
+                self.pager.enable_write(new_nr);
+                new_nr
+            }
+            _ => self.allocate(),
         }
     }
 
@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    panic::{catch_unwind, AssertUnwindSafe},
+    process::exit,
+    time::Duration,
+};
+
+use db::{Database, DatabaseRef, FaultyDisk, Object, Retention, Tree, Unsynced};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use structopt::StructOpt;
+
+extern crate wosim_db as db;
+
+/// Runs random workloads on a database and its write-ahead log on a simulated disk, cuts the
+/// power at random points and checks that the database recovers everything that was reported
+/// durable: the durable snapshot and the records logged on top of it.
+#[derive(StructOpt)]
+struct Options {
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+    #[structopt(long, default_value = "100")]
+    crashes: usize,
+    /// Writes unsynchronized sectors in any order instead of dropping them
+    #[structopt(long)]
+    reorder: bool,
+}
+
+#[derive(Object)]
+struct Workload {
+    values: db::Vec<u64>,
+    entries: Tree<u64, [u64; 8]>,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+struct Model {
+    values: Vec<u64>,
+    entries: BTreeMap<u64, [u64; 8]>,
+}
+
+impl Workload {
+    fn new(database: DatabaseRef) -> Self {
+        Self {
+            values: db::Vec::new(database.clone()),
+            entries: Tree::new(database),
+        }
+    }
+
+    fn model(&self) -> Model {
+        Model {
+            values: self.values.read().iter().copied().collect(),
+            entries: self
+                .entries
+                .read()
+                .iter()
+                .map(|(key, value)| (*key, *value))
+                .collect(),
+        }
+    }
+
+    fn mutate(&mut self, model: &mut Model, rng: &mut StdRng) {
+        let value: u64 = rng.gen();
+        match rng.gen_range(0..6) {
+            0 | 1 => {
+                self.values.write().push(value);
+                model.values.push(value);
+            }
+            2 if !model.values.is_empty() => {
+                let index = rng.gen_range(0..model.values.len());
+                self.values.write()[index] = value;
+                model.values[index] = value;
+            }
+            3 => {
+                self.values.write().pop();
+                model.values.pop();
+            }
+            4 => {
+                let key = value % 4096;
+                self.entries.write().insert(key, [value; 8]);
+                model.entries.insert(key, [value; 8]);
+            }
+            _ => {
+                let key = value % 4096;
+                self.entries.write().remove(&key);
+                model.entries.remove(&key);
+            }
+        }
+    }
+}
+
+/// A point in the history of the workload: a snapshot version and the number of log records
+/// appended on top of it.
+type Point = (u64, u64);
+
+struct Driver {
+    rng: StdRng,
+    disk: FaultyDisk,
+    database: Database<Workload>,
+    model: Model,
+    states: BTreeMap<Point, Model>,
+    records: u64,
+    acknowledged: Point,
+}
+
+impl Driver {
+    fn new(seed: u64, unsynced: Unsynced) -> io::Result<Self> {
+        let disk = FaultyDisk::new(seed, unsynced);
+        let mut database = Database::create_faulty(&disk, Workload::new)?;
+        database.set_retention(Retention {
+            count: 3,
+            interval: Duration::from_secs(0),
+        });
+        database.snapshot()?;
+        drop(database);
+        let database = Database::open_faulty(&disk)?;
+        let version = database_version(&database);
+        let mut states = BTreeMap::new();
+        states.insert((version, 0), Model::default());
+        Ok(Self {
+            rng: StdRng::seed_from_u64(seed),
+            disk,
+            database,
+            model: Model::default(),
+            states,
+            records: 0,
+            acknowledged: (version, 0),
+        })
+    }
+
+    /// Runs the workload, which takes snapshots and logs changes on top of them, until the power
+    /// fails.
+    fn run(&mut self) -> io::Result<()> {
+        let abort = self.rng.gen_bool(0.8);
+        if abort {
+            let writes = self.disk.writes() + self.rng.gen_range(0..2000);
+            self.disk.abort_at(Some(writes));
+        }
+        while self.disk.is_powered() {
+            for _ in 0..self.rng.gen_range(0..500) {
+                self.database.mutate(&mut self.model, &mut self.rng);
+            }
+            if !abort && self.rng.gen_bool(0.1) {
+                self.disk.power_loss()?;
+                break;
+            }
+            // A failed append or snapshot may still reach the disk, so its state is recorded
+            // up front but only acknowledged once it succeeded.
+            let version = database_version(&self.database);
+            let log = self.rng.gen_bool(0.5);
+            let (point, result) = if log {
+                let point = (version, self.records + 1);
+                self.states.insert(point, self.model.clone());
+                (point, self.database.log())
+            } else {
+                let point = (version + 1, 0);
+                self.states.insert(point, self.model.clone());
+                (point, self.database.snapshot())
+            };
+            match result {
+                Ok(()) => {}
+                Err(_) if !self.disk.is_powered() => break,
+                Err(error) => return Err(error),
+            }
+            self.records = point.1;
+            if log {
+                self.acknowledged = point;
+            }
+            let durable = (self.database.durable_version(), 0);
+            self.acknowledged = self.acknowledged.max(durable);
+        }
+        self.disk.abort_at(None);
+        Ok(())
+    }
+
+    /// Opens the database again, finds the snapshot or log record it recovered and checks that
+    /// nothing acknowledged as durable got lost.
+    fn recover(&mut self) -> Result<Point, String> {
+        let database = catch_unwind(AssertUnwindSafe(|| Database::open_faulty(&self.disk)))
+            .map_err(|_| "panicked while opening".to_owned())?
+            .map_err(|error| format!("could not open: {}", error))?;
+        self.database = database;
+        let version = database_version(&self.database);
+        let model = catch_unwind(AssertUnwindSafe(|| self.database.model()))
+            .map_err(|_| format!("panicked while reading version {}", version))?;
+        let point = self
+            .states
+            .range((version, 0)..=(version, u64::MAX))
+            .rev()
+            .find(|(_, state)| **state == model)
+            .map(|(point, _)| *point)
+            .ok_or_else(|| {
+                format!(
+                    "version {} differs from its snapshot and all records logged on top",
+                    version
+                )
+            })?;
+        if point < self.acknowledged {
+            return Err(format!(
+                "recovered {:?} but {:?} was acknowledged as durable",
+                point, self.acknowledged
+            ));
+        }
+        self.states.split_off(&(point.0, point.1 + 1));
+        self.records = point.1;
+        self.acknowledged = point;
+        self.model = model;
+        Ok(point)
+    }
+}
+
+fn database_version(database: &Database<Workload>) -> u64 {
+    database.versions().last().map_or(0, |v| v.version)
+}
+
+fn main() -> io::Result<()> {
+    let options = Options::from_args();
+    let unsynced = if options.reorder {
+        Unsynced::Reorder
+    } else {
+        Unsynced::Drop
+    };
+    let mut driver = Driver::new(options.seed, unsynced)?;
+    for crash in 0..options.crashes {
+        driver.run()?;
+        let writes = driver.disk.writes();
+        match driver.recover() {
+            Ok((version, records)) => println!(
+                "crash {} after {} writes recovered version {} with {} log records",
+                crash, writes, version, records
+            ),
+            Err(error) => {
+                eprintln!(
+                    "seed {} crash {} after {} writes: {}",
+                    options.seed, crash, writes, error
+                );
+                exit(1);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use bytemuck::Pod;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    lock::{Access, Lock},
+    object::{short_type_name, Field, Layout},
+    page::PageNr,
+    record::Slot,
+    reference::DatabaseRef,
+    tree::{Tree, TreeGuard},
+};
+
+pub struct Blob<K: Pod + Ord, T: Serialize + DeserializeOwned> {
+    slots: Tree<K, Slot>,
+    database: DatabaseRef,
+    _phantom: PhantomData<T>,
+}
+
+pub struct BlobGuard<
+    'a,
+    R: Deref<Target = PageNr> + Access,
+    K: Pod + Ord,
+    T: Serialize + DeserializeOwned,
+> {
+    slots: TreeGuard<'a, R, K, Slot>,
+    database: &'a DatabaseRef,
+    _phantom: PhantomData<T>,
+}
+
+pub type ReadBlobGuard<'a, K, T> = BlobGuard<'a, &'a PageNr, K, T>;
+pub type WriteBlobGuard<'a, K, T> = BlobGuard<'a, &'a mut PageNr, K, T>;
+
+impl<K: Pod + Ord, T: Serialize + DeserializeOwned> Blob<K, T> {
+    pub fn new(database: DatabaseRef) -> Self {
+        Self {
+            slots: Tree::new(database.clone()),
+            database,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        Ok(Self {
+            slots: Tree::deserialize(reader, database.clone())?,
+            database,
+            _phantom: PhantomData,
+        })
+    }
+
+    pub fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.slots.serialize(writer)
+    }
+
+    pub fn read(&self) -> ReadBlobGuard<'_, K, T> {
+        BlobGuard {
+            slots: self.slots.read(),
+            database: &self.database,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn write(&mut self) -> WriteBlobGuard<'_, K, T> {
+        BlobGuard {
+            slots: self.slots.write(),
+            database: &self.database,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, R: Deref<Target = PageNr> + Access, K: Pod + Ord, T: Serialize + DeserializeOwned>
+    BlobGuard<'a, R, K, T>
+{
+    pub fn get(&self, key: &K) -> io::Result<Option<T>> {
+        match self.slots.get(key) {
+            Some(slot) => slot.read(self.database).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.slots.get(key).is_some()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.slots.iter().map(|(key, _)| *key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<(K, T)>> + '_ {
+        self.slots
+            .iter()
+            .map(move |(key, slot)| slot.read(self.database).map(|value| (*key, value)))
+    }
+}
+
+impl<
+        'a,
+        R: DerefMut<Target = PageNr> + Access<Lock = Lock<'a>>,
+        K: Pod + Ord,
+        T: Serialize + DeserializeOwned,
+    > BlobGuard<'a, R, K, T>
+{
+    pub fn insert(&mut self, key: K, value: &T) -> io::Result<()> {
+        let mut slot = self.slots.get(&key).copied().unwrap_or_default();
+        slot.write(value, self.database)?;
+        self.slots.insert(key, slot);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool {
+        match self.slots.remove(key) {
+            Some(mut slot) => {
+                slot.free(self.database);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let keys: Vec<K> = self.keys().collect();
+        for key in keys.iter() {
+            self.remove(key);
+        }
+    }
+}
+
+impl<K: Pod + Ord, T: Serialize + DeserializeOwned> Drop for Blob<K, T> {
+    fn drop(&mut self) {
+        let lock = self.database.lock();
+        if !lock.is_closing() {
+            drop(lock);
+            self.write().clear()
+        }
+    }
+}
+
+impl<K: Layout + Ord, T: Serialize + DeserializeOwned> Field for Blob<K, T> {
+    fn describe() -> String {
+        format!("Blob<{}, {}>", K::describe(), short_type_name::<T>())
+    }
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        Blob::serialize(self, writer)
+    }
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        Blob::deserialize(reader, database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{
+        testing::{Single, TestDir},
+        Database,
+    };
+
+    use super::*;
+
+    #[test]
+    fn values_survive_reopening() {
+        let dir = TestDir::new();
+        let mut database = dir.create(Blob::<u64, String>::new);
+        {
+            let mut blob = database.write();
+            for key in 0..50 {
+                blob.insert(key, &"x".repeat(key as usize * 211)).unwrap();
+            }
+            assert!(blob.remove(&7));
+            assert!(!blob.remove(&7));
+        }
+        database.snapshot().unwrap();
+        drop(database);
+        let database = Database::<Single<Blob<u64, String>>>::open(dir.path()).unwrap();
+        let blob = database.read();
+        assert!(!blob.contains_key(&7));
+        assert_eq!(blob.get(&7).unwrap(), None);
+        assert_eq!(blob.get(&49).unwrap(), Some("x".repeat(49 * 211)));
+        let keys: std::vec::Vec<_> = blob.keys().collect();
+        let expected: std::vec::Vec<_> = (0..50).filter(|key| *key != 7).collect();
+        assert_eq!(keys, expected);
+        for value in blob.iter() {
+            let (key, value) = value.unwrap();
+            assert_eq!(value.len(), key as usize * 211);
+        }
+    }
+
+    #[test]
+    fn overwrite_replaces_value() {
+        let dir = TestDir::new();
+        let mut database = dir.create(Blob::<u64, String>::new);
+        {
+            let mut blob = database.write();
+            blob.insert(1, &"long".repeat(5000)).unwrap();
+            blob.insert(1, &"short".to_owned()).unwrap();
+            blob.insert(2, &"a".to_owned()).unwrap();
+            blob.insert(2, &"b".repeat(9000)).unwrap();
+        }
+        database.snapshot().unwrap();
+        drop(database);
+        let database = Database::<Single<Blob<u64, String>>>::open(dir.path()).unwrap();
+        let blob = database.read();
+        assert_eq!(blob.get(&1).unwrap().as_deref(), Some("short"));
+        assert_eq!(blob.get(&2).unwrap(), Some("b".repeat(9000)));
+        assert_eq!(blob.keys().count(), 2);
+    }
+
+    #[test]
+    fn corrupt_value_is_an_error() {
+        let dir = TestDir::new();
+        let mut database = dir.create(Blob::<u64, String>::new);
+        let mut blob = database.write();
+        blob.insert(1, &"valid".to_owned()).unwrap();
+        let mut slot = *blob.slots.get(&1).unwrap();
+        slot.write(&vec![0xffu8, 0xfe], blob.database).unwrap();
+        blob.slots.insert(1, slot);
+        assert_eq!(blob.get(&1).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(blob.iter().next().unwrap().is_err());
+    }
+}
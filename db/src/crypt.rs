@@ -0,0 +1,677 @@
+use std::{
+    alloc::{alloc_zeroed, handle_alloc_error, Layout},
+    cell::UnsafeCell,
+    convert::TryInto,
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    ptr::slice_from_raw_parts_mut,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+use bytemuck::{cast_ref, Pod, Zeroable};
+use chacha20poly1305::{
+    aead::{Aead, AeadInPlace, NewAead, Payload},
+    Key, Tag, XChaCha20Poly1305, XNonce,
+};
+use log::error;
+use rand::{rngs::OsRng, RngCore};
+use scrypt::Params;
+
+use crate::{
+    header::HeaderPage,
+    mmap::MappedBitset,
+    page::{Page, PageNr, NULL_PAGE_NR, PAGE_SIZE},
+};
+
+const SCRYPT: u32 = 1;
+
+const NONCE_SIZE: usize = 24;
+
+const TAG_SIZE: usize = 16;
+
+/// Every page is stored in a slot of its own, followed by the nonce and the tag of its
+/// ciphertext. The header page is stored in the clear, because it holds the parameters needed to
+/// derive the key, followed by a tag that authenticates it.
+const SLOT_SIZE: usize = PAGE_SIZE + 64;
+
+/// Pages are stored in groups, each preceded by two slots with the versions the pages of the
+/// group were last written with: one written by snapshots with even versions and one by those
+/// with odd versions. A page only decrypts under the version its group expects, so an older
+/// copy of a page cannot be passed off as the current one.
+const GROUP_LEN: usize = PAGE_SIZE / 8;
+
+/// Number of pages allocated at once in memory.
+const CHUNK_LEN: usize = 256;
+
+/// How the key of an encrypted database is derived from the secret it is opened with.
+/// Unencrypted databases leave all of it zeroed.
+#[derive(Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+#[repr(C)]
+pub struct KeyDerivation {
+    function: u32,
+    log_n: u32,
+    r: u32,
+    p: u32,
+    salt: [u8; 32],
+    /// Encrypted zeros, to tell a wrong secret apart from corrupted pages.
+    check: [u8; 32],
+    _padding: [u8; 48],
+}
+
+impl KeyDerivation {
+    pub fn is_encrypted(&self) -> bool {
+        self.function != 0
+    }
+}
+
+#[derive(Clone)]
+pub struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+    /// Derives a new key with a random salt.
+    fn generate(secret: &[u8]) -> io::Result<(Self, KeyDerivation)> {
+        let mut derivation = KeyDerivation {
+            function: SCRYPT,
+            log_n: 15,
+            r: 8,
+            p: 1,
+            ..KeyDerivation::zeroed()
+        };
+        OsRng.fill_bytes(&mut derivation.salt);
+        let cipher = Self::derive(secret, &derivation)?;
+        let check = cipher
+            .0
+            .encrypt(&XNonce::default(), &[0u8; TAG_SIZE][..])
+            .unwrap();
+        derivation.check.copy_from_slice(&check);
+        Ok((cipher, derivation))
+    }
+
+    fn open(secret: &[u8], derivation: &KeyDerivation) -> io::Result<Self> {
+        if !derivation.is_encrypted() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "database is not encrypted",
+            ));
+        }
+        let cipher = Self::derive(secret, derivation)?;
+        match cipher.0.decrypt(&XNonce::default(), &derivation.check[..]) {
+            Ok(zeros) if zeros == [0u8; TAG_SIZE] => Ok(cipher),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "wrong secret for encrypted database",
+            )),
+        }
+    }
+
+    fn derive(secret: &[u8], derivation: &KeyDerivation) -> io::Result<Self> {
+        if derivation.function != SCRYPT || derivation.log_n > u8::MAX as u32 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "unknown key derivation",
+            ));
+        }
+        let params = Params::new(derivation.log_n as u8, derivation.r, derivation.p)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "invalid key derivation"))?;
+        let mut key = Key::default();
+        scrypt::scrypt(secret, &derivation.salt, &params, &mut key).unwrap();
+        let cipher = XChaCha20Poly1305::new(&key);
+        key.iter_mut().for_each(|byte| *byte = 0);
+        Ok(Self(cipher))
+    }
+
+    /// Nonces start with the page number and the snapshot version, the rest is random, so that
+    /// pages written more than once under the same version never share a nonce.
+    fn nonce(nr: PageNr, version: u64) -> XNonce {
+        let mut nonce = XNonce::default();
+        nonce[..4].copy_from_slice(&nr.to_le_bytes());
+        nonce[4..12].copy_from_slice(&version.to_le_bytes());
+        OsRng.fill_bytes(&mut nonce[12..]);
+        nonce
+    }
+
+    /// Pages are bound to their number and the version of the snapshot they were written for.
+    fn page_data(nr: PageNr, version: u64) -> [u8; 12] {
+        let mut data = [0; 12];
+        data[..4].copy_from_slice(&nr.to_le_bytes());
+        data[4..].copy_from_slice(&version.to_le_bytes());
+        data
+    }
+
+    /// Version tables are bound to their group and the version of the snapshot they belong to.
+    fn table_data(group: usize, version: u64) -> [u8; 16] {
+        let mut data = [0; 16];
+        data[..8].copy_from_slice(&(group as u64).to_le_bytes());
+        data[8..].copy_from_slice(&version.to_le_bytes());
+        data
+    }
+
+    fn seal_slot(&self, nonce: XNonce, data: &[u8], content: &[u8], slot: &mut [u8]) {
+        let (ciphertext, trailer) = slot.split_at_mut(PAGE_SIZE);
+        ciphertext.copy_from_slice(content);
+        let tag = self
+            .0
+            .encrypt_in_place_detached(&nonce, data, ciphertext)
+            .unwrap();
+        trailer[..NONCE_SIZE].copy_from_slice(&nonce);
+        trailer[NONCE_SIZE..NONCE_SIZE + TAG_SIZE].copy_from_slice(&tag);
+    }
+
+    fn open_slot(&self, data: &[u8], slot: &[u8], content: &mut [u8]) -> bool {
+        let (ciphertext, trailer) = slot.split_at(PAGE_SIZE);
+        content.copy_from_slice(ciphertext);
+        let nonce = XNonce::from_slice(&trailer[..NONCE_SIZE]);
+        let tag = Tag::from_slice(&trailer[NONCE_SIZE..NONCE_SIZE + TAG_SIZE]);
+        self.0
+            .decrypt_in_place_detached(nonce, data, content, tag)
+            .is_ok()
+    }
+
+    /// Computes the tag of the header page, which is stored in the clear. The tag covers all of
+    /// it, including the snapshot states.
+    fn sign_header(&self, header: &Page, trailer: &mut [u8]) {
+        let nonce = Self::nonce(NULL_PAGE_NR, 0);
+        let tag = self
+            .0
+            .encrypt_in_place_detached(&nonce, &header[..], &mut [])
+            .unwrap();
+        trailer[..NONCE_SIZE].copy_from_slice(&nonce);
+        trailer[NONCE_SIZE..NONCE_SIZE + TAG_SIZE].copy_from_slice(&tag);
+    }
+
+    fn verify_header(&self, header: &Page, trailer: &[u8]) -> io::Result<()> {
+        let nonce = XNonce::from_slice(&trailer[..NONCE_SIZE]);
+        let tag = Tag::from_slice(&trailer[NONCE_SIZE..NONCE_SIZE + TAG_SIZE]);
+        self.0
+            .decrypt_in_place_detached(nonce, &header[..], &mut [], tag)
+            .map_err(|_| {
+                io::Error::new(ErrorKind::InvalidData, "database header was tampered with")
+            })
+    }
+
+    /// Encrypts a message that does not belong to a page, like a log record. The nonce is
+    /// prepended to the ciphertext.
+    pub fn seal(&self, version: u64, message: &[u8]) -> Vec<u8> {
+        let nonce = Self::nonce(NULL_PAGE_NR, version);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.0
+                .encrypt(
+                    &nonce,
+                    Payload {
+                        msg: message,
+                        aad: &version.to_le_bytes(),
+                    },
+                )
+                .unwrap(),
+        );
+        sealed
+    }
+
+    /// Decrypts a message sealed under `version`, if it is intact.
+    pub fn unseal(&self, version: u64, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_SIZE {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        self.0
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &version.to_le_bytes(),
+                },
+            )
+            .ok()
+    }
+}
+
+/// A database file whose pages are encrypted. Instead of being mapped, pages are decrypted into
+/// memory the first time they are accessed and stay there. Written pages are encrypted when the
+/// snapshot they belong to is flushed.
+#[derive(Clone)]
+pub struct EncryptedFile(Arc<Inner>);
+
+struct Inner {
+    file: Mutex<File>,
+    cipher: Cipher,
+    derivation: KeyDerivation,
+    chunks: RwLock<Vec<Arc<Chunk>>>,
+    /// The version each page was last written with, zero for pages never written.
+    versions: Mutex<Vec<u64>>,
+    /// Why a page could not be read, once that happened.
+    failure: Mutex<Option<String>>,
+}
+
+struct Chunk {
+    pages: Box<[UnsafeCell<Page>]>,
+    loaded: Vec<AtomicBool>,
+    loading: Mutex<()>,
+}
+
+unsafe impl Sync for Chunk {}
+
+impl EncryptedFile {
+    /// Sets up encryption for an empty file. The key derivation still has to be written to the
+    /// header.
+    pub fn create(file: File, secret: &[u8]) -> io::Result<Self> {
+        let (cipher, derivation) = Cipher::generate(secret)?;
+        let chunk = Chunk::new();
+        chunk.loaded[0].store(true, Ordering::Relaxed);
+        Ok(Self::new(
+            file,
+            cipher,
+            derivation,
+            vec![Arc::new(chunk)],
+            Vec::new(),
+        ))
+    }
+
+    /// Checks the tag of the header and decrypts all pages its last snapshot refers to, so that
+    /// pages that were tampered with or replaced by older copies fail here instead of on first
+    /// access.
+    pub fn open(mut file: File, secret: &[u8]) -> io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        let chunks: Vec<_> = (0..(len / SLOT_SIZE).max(1))
+            .step_by(CHUNK_LEN)
+            .map(|_| Arc::new(Chunk::new()))
+            .collect();
+        let header = unsafe { &mut *chunks[0].pages[0].get() };
+        let mut trailer = [0; SLOT_SIZE - PAGE_SIZE];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header[..])?;
+        file.read_exact(&mut trailer)?;
+        chunks[0].loaded[0].store(true, Ordering::Relaxed);
+        let header_page = cast_ref::<Page, HeaderPage>(header);
+        header_page.header.check_version()?;
+        let derivation = header_page.header.key_derivation();
+        let cipher = Cipher::open(secret, &derivation)?;
+        cipher.verify_header(header, &trailer)?;
+        let versions = match header_page.header.current() {
+            Some(state) if state.version != 0 => {
+                let groups = state.allocator.last_page() as usize / GROUP_LEN + 1;
+                Self::read_versions(&mut file, &cipher, groups, state.version)?
+            }
+            _ => Vec::new(),
+        };
+        let file = Self::new(file, cipher, derivation, chunks, versions);
+        file.load_written()?;
+        Ok(file)
+    }
+
+    fn new(
+        file: File,
+        cipher: Cipher,
+        derivation: KeyDerivation,
+        chunks: Vec<Arc<Chunk>>,
+        versions: Vec<u64>,
+    ) -> Self {
+        Self(Arc::new(Inner {
+            file: Mutex::new(file),
+            cipher,
+            derivation,
+            chunks: RwLock::new(chunks),
+            versions: Mutex::new(versions),
+            failure: Mutex::new(None),
+        }))
+    }
+
+    fn load_written(&self) -> io::Result<()> {
+        let versions = self.0.versions.lock().unwrap().clone();
+        let chunks = self.chunks(versions.len());
+        for (index, version) in versions.iter().enumerate().skip(1) {
+            if *version == 0 {
+                continue;
+            }
+            let chunk = &chunks[index / CHUNK_LEN];
+            let offset = index % CHUNK_LEN;
+            self.read(index as PageNr, unsafe { &mut *chunk.pages[offset].get() })?;
+            chunk.loaded[offset].store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    fn read_versions(
+        file: &mut File,
+        cipher: &Cipher,
+        groups: usize,
+        version: u64,
+    ) -> io::Result<Vec<u64>> {
+        let mut versions = vec![0; groups * GROUP_LEN];
+        let mut slot = vec![0; SLOT_SIZE];
+        let mut content = vec![0; PAGE_SIZE];
+        for (group, versions) in versions.chunks_exact_mut(GROUP_LEN).enumerate() {
+            file.seek(SeekFrom::Start(table_offset(group, version)))?;
+            file.read_exact(&mut slot)?;
+            if !cipher.open_slot(&Cipher::table_data(group, version), &slot, &mut content) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("page versions of group {} could not be decrypted", group),
+                ));
+            }
+            for (entry, bytes) in versions.iter_mut().zip(content.chunks_exact(8)) {
+                *entry = u64::from_le_bytes(bytes.try_into().unwrap());
+            }
+        }
+        Ok(versions)
+    }
+
+    pub fn cipher(&self) -> &Cipher {
+        &self.0.cipher
+    }
+
+    pub fn key_derivation(&self) -> KeyDerivation {
+        self.0.derivation
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.chunks.read().unwrap().len() * CHUNK_LEN * PAGE_SIZE
+    }
+
+    /// Encrypts the written pages and writes them to the file, without waiting for them to be
+    /// durable.
+    pub fn flush(&self, written: &mut MappedBitset, version: u64) -> io::Result<()> {
+        let chunks = self.0.chunks.read().unwrap().clone();
+        written.grow(written.len())?;
+        let len = written.len().min(chunks.len() * CHUNK_LEN);
+        let mut file = self.0.file.lock().unwrap();
+        let mut slot = vec![0; SLOT_SIZE];
+        for index in 1..len {
+            let chunk = &chunks[index / CHUNK_LEN];
+            let offset = index % CHUNK_LEN;
+            if !unsafe { written.get(index) } || !chunk.loaded[offset].load(Ordering::Acquire) {
+                continue;
+            }
+            let nr = index as PageNr;
+            let page = unsafe { &*chunk.pages[offset].get() };
+            let nonce = Cipher::nonce(nr, version);
+            let data = Cipher::page_data(nr, version);
+            self.0.cipher.seal_slot(nonce, &data, &page[..], &mut slot);
+            file.seek(SeekFrom::Start(page_offset(nr)))?;
+            file.write_all(&slot)?;
+            let mut versions = self.0.versions.lock().unwrap();
+            if versions.len() <= index {
+                versions.resize(index + 1, 0);
+            }
+            versions[index] = version;
+        }
+        Ok(())
+    }
+
+    /// Writes the versions of all pages for the snapshot with the given version, without
+    /// waiting for them to be durable. They go to the slots of the other parity than those the
+    /// durable header refers to.
+    pub fn flush_versions(&self, version: u64) -> io::Result<()> {
+        let versions = self.0.versions.lock().unwrap().clone();
+        let mut file = self.0.file.lock().unwrap();
+        let mut slot = vec![0; SLOT_SIZE];
+        let mut content = vec![0; PAGE_SIZE];
+        for (group, versions) in versions.chunks(GROUP_LEN).enumerate() {
+            content.iter_mut().for_each(|byte| *byte = 0);
+            for (bytes, entry) in content.chunks_exact_mut(8).zip(versions) {
+                bytes.copy_from_slice(&entry.to_le_bytes());
+            }
+            let nonce = Cipher::nonce(NULL_PAGE_NR, version);
+            let data = Cipher::table_data(group, version);
+            self.0.cipher.seal_slot(nonce, &data, &content, &mut slot);
+            file.seek(SeekFrom::Start(table_offset(group, version)))?;
+            file.write_all(&slot)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the header page and its tag to the file, without waiting for them to be durable.
+    pub fn flush_header(&self) -> io::Result<()> {
+        let chunk = self.0.chunks.read().unwrap()[0].clone();
+        let mut slot = vec![0; SLOT_SIZE];
+        let header = unsafe { &*chunk.pages[0].get() };
+        let (content, trailer) = slot.split_at_mut(PAGE_SIZE);
+        content.copy_from_slice(&header[..]);
+        self.0.cipher.sign_header(header, trailer);
+        let mut file = self.0.file.lock().unwrap();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&slot)
+    }
+
+    /// Fails once a page could not be decrypted, so that nothing derived from the zeros it was
+    /// read as gets persisted.
+    pub fn check(&self) -> io::Result<()> {
+        match &*self.0.failure.lock().unwrap() {
+            Some(failure) => Err(io::Error::new(ErrorKind::InvalidData, failure.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn poison(&self, error: io::Error) {
+        error!("{}", error);
+        let mut failure = self.0.failure.lock().unwrap();
+        if failure.is_none() {
+            *failure = Some(error.to_string());
+        }
+    }
+
+    pub fn sync(&self) -> io::Result<()> {
+        self.0.file.lock().unwrap().sync_data()
+    }
+
+    fn chunks(&self, min_len: usize) -> Vec<Arc<Chunk>> {
+        let mut chunks = self.0.chunks.write().unwrap();
+        while chunks.len() * CHUNK_LEN < min_len {
+            chunks.push(Arc::new(Chunk::new()));
+        }
+        chunks.clone()
+    }
+
+    /// Reads a page as it was written with the version the last snapshot expects. Pages that
+    /// were never written read as zeros.
+    pub fn read(&self, nr: PageNr, page: &mut Page) -> io::Result<()> {
+        let version = self
+            .0
+            .versions
+            .lock()
+            .unwrap()
+            .get(nr as usize)
+            .copied()
+            .unwrap_or(0);
+        let mut slot = Vec::with_capacity(SLOT_SIZE);
+        let mut file = self.0.file.lock().unwrap();
+        file.seek(SeekFrom::Start(page_offset(nr)))?;
+        (&mut *file).take(SLOT_SIZE as u64).read_to_end(&mut slot)?;
+        drop(file);
+        let erased = slot.iter().all(|byte| *byte == 0);
+        if erased && version == 0 {
+            *page = Page::zeroed();
+            return Ok(());
+        }
+        slot.resize(SLOT_SIZE, 0);
+        let data = Cipher::page_data(nr, version);
+        if erased || version == 0 || !self.0.cipher.open_slot(&data, &slot, &mut page[..]) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "page {} could not be decrypted under version {}",
+                    nr, version
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn page_offset(nr: PageNr) -> u64 {
+    let nr = nr as usize;
+    let slot = 1 + nr / GROUP_LEN * (GROUP_LEN + 2) + 2 + nr % GROUP_LEN;
+    (slot * SLOT_SIZE) as u64
+}
+
+fn table_offset(group: usize, version: u64) -> u64 {
+    let slot = 1 + group * (GROUP_LEN + 2) + (version % 2) as usize;
+    (slot * SLOT_SIZE) as u64
+}
+
+impl Chunk {
+    fn new() -> Self {
+        let layout = Layout::array::<UnsafeCell<Page>>(CHUNK_LEN).unwrap();
+        let pages = unsafe {
+            let ptr = alloc_zeroed(layout).cast::<UnsafeCell<Page>>();
+            if ptr.is_null() {
+                handle_alloc_error(layout)
+            }
+            Box::from_raw(slice_from_raw_parts_mut(ptr, CHUNK_LEN))
+        };
+        Self {
+            pages,
+            loaded: (0..CHUNK_LEN).map(|_| AtomicBool::new(false)).collect(),
+            loading: Mutex::new(()),
+        }
+    }
+}
+
+/// The pages of an encrypted file as seen by a pager.
+#[derive(Clone)]
+pub struct EncryptedPages {
+    file: EncryptedFile,
+    chunks: Vec<Arc<Chunk>>,
+}
+
+impl EncryptedPages {
+    pub fn new(file: EncryptedFile) -> Self {
+        let chunks = file.0.chunks.read().unwrap().clone();
+        Self { file, chunks }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len() * CHUNK_LEN
+    }
+
+    pub fn grow(&mut self, min_len: usize) {
+        if self.len() < min_len {
+            self.chunks = self.file.chunks(min_len);
+        }
+    }
+
+    /// Decrypts the page on first access. Pages that snapshots refer to were decrypted when the
+    /// file was opened, so this only reads pages that were never written. One that cannot be
+    /// decrypted anyway reads as zeros and poisons the file, see `EncryptedFile::check`.
+    pub fn get(&self, index: usize) -> *mut Page {
+        self.load(index, false)
+    }
+
+    /// Like `get`, but for pages that are about to be overwritten, which may have been written
+    /// partially before.
+    pub fn get_mut(&self, index: usize) -> *mut Page {
+        self.load(index, true)
+    }
+
+    fn load(&self, index: usize, overwrite: bool) -> *mut Page {
+        let chunk = &self.chunks[index / CHUNK_LEN];
+        let offset = index % CHUNK_LEN;
+        let page = chunk.pages[offset].get();
+        if !chunk.loaded[offset].load(Ordering::Acquire) {
+            let _loading = chunk.loading.lock().unwrap();
+            if !chunk.loaded[offset].load(Ordering::Relaxed) {
+                match self.file.read(index as PageNr, unsafe { &mut *page }) {
+                    Ok(()) => {}
+                    Err(_) if overwrite => unsafe { *page = Page::zeroed() },
+                    Err(error) => {
+                        unsafe { *page = Page::zeroed() };
+                        self.file.poison(error);
+                    }
+                }
+                chunk.loaded[offset].store(true, Ordering::Release);
+            }
+        }
+        page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use crate::{
+        testing::{Single, TestDir},
+        Database, Retention, Tree,
+    };
+
+    use super::*;
+
+    type Content = Single<Tree<u32, [u32; 64]>>;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn create(dir: &TestDir) -> Database<Content> {
+        Database::create_encrypted(dir.path(), SECRET, |database| Single(Tree::new(database)))
+            .unwrap()
+    }
+
+    fn open(dir: &TestDir) -> io::Result<Database<Content>> {
+        Database::open_encrypted(dir.path(), SECRET)
+    }
+
+    /// Rewrites the tree a few times, so that freed pages are reused under newer versions.
+    fn rewrite(database: &mut Database<Content>, rounds: u32) {
+        database.set_retention(Retention {
+            count: 1,
+            interval: Duration::from_secs(0),
+        });
+        for round in 0..rounds {
+            let mut tree = database.write();
+            for key in 0..500 {
+                tree.insert(key, [round; 64]);
+            }
+            drop(tree);
+            database.snapshot().unwrap();
+        }
+    }
+
+    #[test]
+    fn reject_rolled_back_pages() {
+        let dir = TestDir::new();
+        let mut database = create(&dir);
+        rewrite(&mut database, 2);
+        drop(database);
+        let old = fs::read(dir.path()).unwrap();
+        let mut database = open(&dir).unwrap();
+        rewrite(&mut database, 6);
+        drop(database);
+        let mut new = fs::read(dir.path()).unwrap();
+        let mut rolled_back = 0;
+        for nr in 1..(old.len() / SLOT_SIZE) as PageNr {
+            let start = page_offset(nr) as usize;
+            let end = start + SLOT_SIZE;
+            if end <= old.len() && end <= new.len() && old[start..end] != new[start..end] {
+                new[start..end].copy_from_slice(&old[start..end]);
+                rolled_back += 1;
+            }
+        }
+        assert!(rolled_back > 0);
+        fs::write(dir.path(), new).unwrap();
+        let failed = match open(&dir) {
+            Ok(database) => database.check().is_err(),
+            Err(_) => true,
+        };
+        assert!(failed);
+    }
+
+    #[test]
+    fn reject_tampered_header() {
+        let dir = TestDir::new();
+        let mut database = create(&dir);
+        rewrite(&mut database, 2);
+        drop(database);
+        let mut bytes = fs::read(dir.path()).unwrap();
+        let mut page = Page::zeroed();
+        page.copy_from_slice(&bytes[..PAGE_SIZE]);
+        let header = &mut bytemuck::cast_mut::<Page, HeaderPage>(&mut page).header;
+        let mut state = header.current().unwrap();
+        state.oldest += 1;
+        header.snapshot(state);
+        bytes[..PAGE_SIZE].copy_from_slice(&page[..]);
+        fs::write(dir.path(), bytes).unwrap();
+        assert!(open(&dir).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use std::mem::size_of;
 use bytemuck::{cast_mut, cast_ref};
 
 use crate::{
-    lock::Lock,
+    lock::{Lock, ReadLock},
     page::{Page, PageNr, NULL_PAGE_NR, PAGE_SIZE},
 };
 
@@ -11,6 +11,9 @@ type IndirectPage = [PageNr; FAN_OUT as usize];
 
 const FAN_OUT: usize = PAGE_SIZE / size_of::<PageNr>();
 
+/// The number of pages the page table of a file or vector can address.
+pub const MAX_PAGES: usize = FAN_OUT * FAN_OUT;
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum PageLevel {
     L0,
@@ -53,7 +56,7 @@ impl PageLookup {
         root_nr: PageNr,
         pages: usize,
         index: usize,
-        lock: &'a Lock,
+        lock: &'a ReadLock,
     ) -> &'a Page {
         let page_nr = match *self {
             Self::Immutable(key, page_nr) => {
@@ -120,7 +123,7 @@ impl PageLookup {
     }
 }
 
-fn find_page(page_nr: PageNr, index: PageIndex, lock: &Lock) -> PageNr {
+fn find_page(page_nr: PageNr, index: PageIndex, lock: &ReadLock) -> PageNr {
     if index.is_indirect() {
         find_page(
             cast_ref::<Page, IndirectPage>(unsafe { lock.page(page_nr) })[index.index()],
@@ -132,6 +135,26 @@ fn find_page(page_nr: PageNr, index: PageIndex, lock: &Lock) -> PageNr {
     }
 }
 
+/// Like `find_page`, but passes every page number on the way to `check` before it is followed, so
+/// that a corrupted page table is reported instead of read.
+pub fn find_page_checked<E>(
+    root_nr: PageNr,
+    pages: usize,
+    index: usize,
+    lock: &ReadLock,
+    check: impl Fn(PageNr) -> Result<(), E>,
+) -> Result<PageNr, E> {
+    let mut page_nr = root_nr;
+    let mut index = PageIndex::new(index, pages);
+    check(page_nr)?;
+    while index.is_indirect() {
+        page_nr = cast_ref::<Page, IndirectPage>(unsafe { lock.page(page_nr) })[index.index()];
+        check(page_nr)?;
+        index = index.child();
+    }
+    Ok(page_nr)
+}
+
 fn find_page_mut(page_nr: &mut PageNr, index: PageIndex, lock: &Lock) -> PageNr {
     let page = unsafe { lock.page_mut(page_nr) };
     if index.is_indirect() {
@@ -159,7 +182,7 @@ impl PageLevel {
             0 => None,
             1 => Some(Self::L0),
             2..=2048 => Some(Self::L1),
-            2049..=4194304 => Some(Self::L2),
+            2049..=MAX_PAGES => Some(Self::L2),
             _ => panic!(),
         }
     }
@@ -1,11 +1,24 @@
 use std::{
-    fs::OpenOptions,
+    fs::{self, OpenOptions},
     io::{self, Seek, SeekFrom},
     ops::{Deref, DerefMut},
     path::Path,
+    sync::mpsc::{channel, Receiver},
 };
 
-use crate::{file::File, object::Object, raw::RawDatabase, reference::DatabaseRef};
+#[cfg(feature = "fault-injection")]
+use crate::fault::FaultyDisk;
+use crate::{
+    crypt::EncryptedFile,
+    file::File,
+    history::{History, Retention, Version},
+    mmap::MappedFile,
+    object::Object,
+    raw::RawDatabase,
+    reference::DatabaseRef,
+    storage::Storage,
+    wal::LogFile,
+};
 
 pub struct Database<T: Object> {
     file: File,
@@ -14,10 +27,91 @@ pub struct Database<T: Object> {
 }
 
 impl<T: Object> Database<T> {
+    /// Opens the database at `path` and replays its write-ahead log, which lives next to it.
     pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let wal = Self::open_wal(&path)?;
+        Self::open_storage(Storage::Mapped(MappedFile::new(file)?), Some(wal), None)
+    }
+
+    /// Opens an encrypted database with the secret it was created with. Its pages are decrypted
+    /// into memory as they are accessed instead of being mapped.
+    pub fn open_encrypted(path: impl AsRef<Path>, secret: &[u8]) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let wal = Self::open_wal(&path)?;
+        let data = Storage::Encrypted(EncryptedFile::open(file, secret)?);
+        Self::open_storage(data, Some(wal), None)
+    }
+
+    /// Opens the database and its write-ahead log on a simulated disk, restoring its power.
+    #[cfg(feature = "fault-injection")]
+    pub fn open_faulty(disk: &FaultyDisk) -> io::Result<Self> {
+        let (file, wal, attachment) = disk.attach()?;
+        let data = Storage::Mapped(MappedFile::faulty(file, disk, attachment)?);
+        let wal = LogFile::faulty(wal, disk, attachment);
+        Self::open_storage(data, Some(wal), None)
+    }
+
+    /// Opens a retained version from a private copy of the database. Changes to the returned
+    /// database never reach the file at `path`.
+    pub fn open_version(path: impl AsRef<Path>, version: u64) -> io::Result<Self> {
+        let data = Storage::Mapped(MappedFile::new(Self::copy(path)?)?);
+        Self::open_storage(data, None, Some(version))
+    }
+
+    /// Like `open_version`, for encrypted databases.
+    pub fn open_version_encrypted(
+        path: impl AsRef<Path>,
+        version: u64,
+        secret: &[u8],
+    ) -> io::Result<Self> {
+        let data = Storage::Encrypted(EncryptedFile::open(Self::copy(path)?, secret)?);
+        Self::open_storage(data, None, Some(version))
+    }
+
+    /// Makes a retained version the current state of the database at `path`. Versions newer
+    /// than the restored one are discarded.
+    pub fn restore(path: impl AsRef<Path>, version: u64) -> io::Result<()> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Self::restore_storage(Storage::Mapped(MappedFile::new(file)?), version)
+    }
+
+    /// Like `restore`, for encrypted databases.
+    pub fn restore_encrypted(
+        path: impl AsRef<Path>,
+        version: u64,
+        secret: &[u8],
+    ) -> io::Result<()> {
         let file = OpenOptions::new().read(true).write(true).open(path)?;
-        let (raw, header) = RawDatabase::open(file, &T::format())?;
+        Self::restore_storage(
+            Storage::Encrypted(EncryptedFile::open(file, secret)?),
+            version,
+        )
+    }
+
+    fn restore_storage(data: Storage, version: u64) -> io::Result<()> {
+        let (raw, _) = RawDatabase::open(data, None, &T::format())?;
         let database = DatabaseRef::new(raw);
+        History::load(&database.lock())?;
+        database.restore(version)?;
+        Ok(())
+    }
+
+    fn copy(path: impl AsRef<Path>) -> io::Result<fs::File> {
+        let mut source = fs::File::open(path)?;
+        let mut file = tempfile::tempfile()?;
+        io::copy(&mut source, &mut file)?;
+        Ok(file)
+    }
+
+    fn open_storage(data: Storage, wal: Option<LogFile>, version: Option<u64>) -> io::Result<Self> {
+        let (raw, header) = RawDatabase::open(data, wal, &T::format())?;
+        let database = DatabaseRef::new(raw);
+        History::load(&database.lock())?;
+        let header = match version {
+            Some(version) => database.restore(version)?,
+            None => header,
+        };
         let file = File::from_header(header, database.clone());
         let content = T::deserialize(&mut file.read(), database.clone())?;
         Ok(Self {
@@ -31,12 +125,54 @@ impl<T: Object> Database<T> {
         path: impl AsRef<Path>,
         constructor: impl FnOnce(DatabaseRef) -> T,
     ) -> io::Result<Self> {
-        let file = OpenOptions::new()
+        let file = Self::create_file(&path)?;
+        let wal = Self::open_wal(&path)?;
+        Self::create_storage(
+            Storage::Mapped(MappedFile::new(file)?),
+            Some(wal),
+            constructor,
+        )
+    }
+
+    /// Creates a database whose pages and log are encrypted with a key derived from `secret`.
+    /// Only the header, which records how to derive the key, is stored in the clear.
+    pub fn create_encrypted(
+        path: impl AsRef<Path>,
+        secret: &[u8],
+        constructor: impl FnOnce(DatabaseRef) -> T,
+    ) -> io::Result<Self> {
+        let file = Self::create_file(&path)?;
+        let wal = Self::open_wal(&path)?;
+        let data = Storage::Encrypted(EncryptedFile::create(file, secret)?);
+        Self::create_storage(data, Some(wal), constructor)
+    }
+
+    /// Creates a database and its write-ahead log on a simulated disk.
+    #[cfg(feature = "fault-injection")]
+    pub fn create_faulty(
+        disk: &FaultyDisk,
+        constructor: impl FnOnce(DatabaseRef) -> T,
+    ) -> io::Result<Self> {
+        let (file, wal, attachment) = disk.attach()?;
+        let data = Storage::Mapped(MappedFile::faulty(file, disk, attachment)?);
+        let wal = LogFile::faulty(wal, disk, attachment);
+        Self::create_storage(data, Some(wal), constructor)
+    }
+
+    fn create_file(path: impl AsRef<Path>) -> io::Result<fs::File> {
+        OpenOptions::new()
             .read(true)
             .write(true)
             .create_new(true)
-            .open(path)?;
-        let raw = RawDatabase::create(file, &T::format())?;
+            .open(path)
+    }
+
+    fn create_storage(
+        data: Storage,
+        wal: Option<LogFile>,
+        constructor: impl FnOnce(DatabaseRef) -> T,
+    ) -> io::Result<Self> {
+        let raw = RawDatabase::create(data, wal, &T::format())?;
         let database = DatabaseRef::new(raw);
         let file = File::new(database.clone());
         let content = constructor(database.clone());
@@ -47,13 +183,70 @@ impl<T: Object> Database<T> {
         })
     }
 
+    /// Takes a snapshot, which becomes durable in the background. Returning only means that the
+    /// previous snapshot is durable; see `durable_version`.
     pub fn snapshot(&mut self) -> io::Result<()> {
+        self.check()?;
+        self.serialize()?;
+        self.database.snapshot(self.file.header())
+    }
+
+    /// Makes the changes since the last snapshot durable by appending them to the write-ahead
+    /// log. This is much cheaper than a snapshot, so it can run often to bound data loss on a
+    /// crash. Databases opened with `open_version` have no log and ignore this.
+    pub fn log(&mut self) -> io::Result<()> {
+        self.check()?;
+        self.serialize()?;
+        self.database.log(self.file.header())
+    }
+
+    /// Fails once a page of an encrypted database could not be decrypted, because it was
+    /// tampered with or replaced by an older copy. Such pages read as zeros, so snapshots and
+    /// logs refuse to persist anything from then on.
+    pub fn check(&self) -> io::Result<()> {
+        self.database.check()
+    }
+
+    fn serialize(&mut self) -> io::Result<()> {
         let mut writer = self.file.write();
         self.content.serialize(&mut writer)?;
         let size = writer.seek(SeekFrom::Current(0))?;
         writer.set_len(size);
-        drop(writer);
-        self.database.snapshot(self.file.header())
+        Ok(())
+    }
+
+    fn open_wal(path: impl AsRef<Path>) -> io::Result<LogFile> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path.as_ref().with_extension("wal"))
+            .map(LogFile::new)
+    }
+
+    /// The version of the newest snapshot that survives a crash. The database recovers this
+    /// snapshot or a newer one, with the changes logged on top of it since.
+    pub fn durable_version(&self) -> u64 {
+        self.database.durable_version()
+    }
+
+    pub fn versions(&self) -> Vec<Version> {
+        let lock = self.database.lock();
+        let history = lock.history();
+        history.versions(lock.state())
+    }
+
+    /// Applies from the next snapshot on. The retention is stored in the database.
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.database.lock().history().set_retention(retention)
+    }
+
+    pub fn subscribe(&self) -> Receiver<u64> {
+        let (sender, receiver) = channel();
+        self.database
+            .lock()
+            .on_snapshot(Box::new(move |version| sender.send(version).is_ok()));
+        receiver
     }
 }
 
@@ -0,0 +1,188 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+const SECTOR_SIZE: usize = 4096;
+
+/// A simulated disk for the files of a database. Writes to a file only reach the disk when the
+/// file is synchronized, one sector at a time, and the power can fail at any of these sector
+/// writes, leaving all files of the disk behind. After a power failure the database has to be
+/// opened again from the disk.
+#[derive(Clone)]
+pub struct FaultyDisk(Arc<Mutex<Disk>>);
+
+/// What happens to writes that did not reach the disk yet when the power fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unsynced {
+    /// Synchronization writes sectors in order and unsynchronized sectors are lost.
+    Drop,
+    /// Synchronization writes sectors in any order and each unsynchronized sector may or may
+    /// not have been written back before the power failed.
+    Reorder,
+}
+
+/// The files on a simulated disk.
+#[derive(Clone, Copy)]
+pub(crate) enum DiskFile {
+    Data = 0,
+    Log = 1,
+}
+
+struct Disk {
+    rng: StdRng,
+    unsynced: Unsynced,
+    files: [Contents; 2],
+    powered: bool,
+    attachment: u64,
+    writes: u64,
+    abort_at: Option<u64>,
+}
+
+#[derive(Default)]
+struct Contents {
+    durable: Vec<u8>,
+    volatile: Option<File>,
+}
+
+impl FaultyDisk {
+    pub fn new(seed: u64, unsynced: Unsynced) -> Self {
+        Self(Arc::new(Mutex::new(Disk {
+            rng: StdRng::seed_from_u64(seed),
+            unsynced,
+            files: Default::default(),
+            powered: false,
+            attachment: 0,
+            writes: 0,
+            abort_at: None,
+        })))
+    }
+
+    /// Lets the power fail right before the sector write with the given number.
+    pub fn abort_at(&self, write: Option<u64>) {
+        self.disk().abort_at = write;
+    }
+
+    /// The number of sector writes so far.
+    pub fn writes(&self) -> u64 {
+        self.disk().writes
+    }
+
+    pub fn is_powered(&self) -> bool {
+        self.disk().powered
+    }
+
+    /// Lets the power fail now.
+    pub fn power_loss(&self) -> io::Result<()> {
+        let mut disk = self.disk();
+        if disk.powered {
+            disk.fail()?;
+        }
+        Ok(())
+    }
+
+    /// Restores the power and returns the data file and the log file with the contents of the
+    /// disk. Files of earlier attachments can no longer be synchronized.
+    pub(crate) fn attach(&self) -> io::Result<(File, File, u64)> {
+        let mut disk = self.disk();
+        let mut files = Vec::new();
+        for contents in &mut disk.files {
+            let mut file = tempfile::tempfile()?;
+            file.write_all(&contents.durable)?;
+            contents.volatile = Some(file.try_clone()?);
+            files.push(file);
+        }
+        disk.powered = true;
+        disk.attachment += 1;
+        let log = files.pop().unwrap();
+        let data = files.pop().unwrap();
+        Ok((data, log, disk.attachment))
+    }
+
+    pub(crate) fn sync(&self, attachment: u64, file: DiskFile) -> io::Result<()> {
+        let mut disk = self.disk();
+        if disk.attachment != attachment || !disk.powered {
+            return Err(power_failure());
+        }
+        disk.sync(file as usize)
+    }
+
+    fn disk(&self) -> MutexGuard<'_, Disk> {
+        self.0.lock().unwrap()
+    }
+}
+
+impl Disk {
+    fn sync(&mut self, file: usize) -> io::Result<()> {
+        let volatile = self.files[file].read_volatile()?;
+        let mut sectors = self.files[file].changed(&volatile);
+        if let Unsynced::Reorder = self.unsynced {
+            sectors.shuffle(&mut self.rng);
+        }
+        self.files[file].durable.resize(volatile.len(), 0);
+        for sector in sectors {
+            if self.abort_at == Some(self.writes) {
+                self.fail()?;
+                return Err(power_failure());
+            }
+            self.writes += 1;
+            self.files[file].write(&volatile, sector);
+        }
+        Ok(())
+    }
+
+    /// Writes back the unsynchronized sectors of all files that survive the power failure.
+    fn fail(&mut self) -> io::Result<()> {
+        for contents in &mut self.files {
+            let volatile = contents.read_volatile()?;
+            if let Unsynced::Reorder = self.unsynced {
+                for sector in contents.changed(&volatile) {
+                    if self.rng.gen() {
+                        contents.write(&volatile, sector);
+                    }
+                }
+            }
+            contents.volatile = None;
+        }
+        self.powered = false;
+        Ok(())
+    }
+}
+
+impl Contents {
+    fn read_volatile(&mut self) -> io::Result<Vec<u8>> {
+        let file = self.volatile.as_mut().unwrap();
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn changed(&self, volatile: &[u8]) -> Vec<usize> {
+        volatile
+            .chunks(SECTOR_SIZE)
+            .enumerate()
+            .filter(|(i, sector)| {
+                let start = i * SECTOR_SIZE;
+                self.durable.get(start..start + sector.len()) != Some(*sector)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn write(&mut self, volatile: &[u8], sector: usize) {
+        let start = sector * SECTOR_SIZE;
+        let end = (start + SECTOR_SIZE).min(volatile.len());
+        if self.durable.len() < end {
+            self.durable.resize(end, 0);
+        }
+        self.durable[start..end].copy_from_slice(&volatile[start..end]);
+    }
+}
+
+fn power_failure() -> io::Error {
+    io::Error::new(ErrorKind::Other, "simulated power failure")
+}
@@ -0,0 +1,176 @@
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+};
+
+use crate::reference::DatabaseRef;
+
+/// An entry of a change feed. Every change received before `Snapshot(version)` is part of that
+/// snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<C> {
+    Change(C),
+    Snapshot(u64),
+}
+
+/// A change of a `Vec`. Like all changes, they are delivered when the write guard is dropped. A
+/// `Resize` to the final length comes first, followed by one `Set` carrying the final value for
+/// each index written through the guard, in index order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VecChange<T> {
+    Resize { len: usize },
+    Set { index: usize, value: T },
+}
+
+/// A change of a `Tree`. Like all changes, they are delivered when the write guard is dropped,
+/// in the order they were made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeChange<K, V> {
+    Insert { key: K, value: V },
+    Remove { key: K },
+    Clear,
+}
+
+pub(crate) struct Feed<C> {
+    senders: Mutex<Vec<Sender<Event<C>>>>,
+}
+
+impl<C: Clone> Feed<C> {
+    pub fn new() -> Self {
+        Self {
+            senders: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self, database: &DatabaseRef) -> Receiver<Event<C>>
+    where
+        C: Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let snapshots = sender.clone();
+        database.lock().on_snapshot(Box::new(move |version| {
+            snapshots.send(Event::Snapshot(version)).is_ok()
+        }));
+        self.senders.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn is_active(&self) -> bool {
+        !self.senders.lock().unwrap().is_empty()
+    }
+
+    fn emit(&self, changes: &[C]) {
+        self.senders.lock().unwrap().retain(|sender| {
+            changes
+                .iter()
+                .all(|change| sender.send(Event::Change(change.clone())).is_ok())
+        });
+    }
+}
+
+/// Changes made through a write guard, which are emitted when it is dropped.
+pub(crate) struct Pending<'a, C: Clone> {
+    feed: &'a Feed<C>,
+    changes: Vec<C>,
+}
+
+impl<'a, C: Clone> Pending<'a, C> {
+    /// Returns `None` if nobody is subscribed, so that changes need not be recorded.
+    pub fn new(feed: &'a Feed<C>) -> Option<Self> {
+        if feed.is_active() {
+            Some(Self {
+                feed,
+                changes: Vec::new(),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn push(&mut self, change: C) {
+        self.changes.push(change)
+    }
+
+    pub fn last_mut(&mut self) -> Option<&mut C> {
+        self.changes.last_mut()
+    }
+}
+
+impl<'a, C: Clone> Drop for Pending<'a, C> {
+    fn drop(&mut self) {
+        if !self.changes.is_empty() {
+            self.feed.emit(&self.changes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testing::TestDir, Entry, Tree, Vec};
+
+    use super::*;
+
+    #[test]
+    fn vec_changes_are_coalesced() {
+        let dir = TestDir::new();
+        let mut vec = dir.create(Vec::<u32>::new);
+        vec.write().push(1);
+        let receiver = vec.0.subscribe();
+        let mut guard = vec.write();
+        for value in 2..200 {
+            guard.push(value);
+        }
+        guard[150] = 7;
+        guard[1] = 5;
+        guard[1] = 6;
+        guard.pop();
+        assert!(receiver.try_recv().is_err());
+        drop(guard);
+        let mut expected = vec![Event::Change(VecChange::Resize { len: 198 })];
+        expected.extend((1..198).map(|index| {
+            let value = match index {
+                1 => 6,
+                150 => 7,
+                _ => index as u32 + 1,
+            };
+            Event::Change(VecChange::Set { index, value })
+        }));
+        assert_eq!(receiver.try_iter().collect::<std::vec::Vec<_>>(), expected);
+        vec.snapshot().unwrap();
+        assert_eq!(receiver.try_recv(), Ok(Event::Snapshot(1)));
+    }
+
+    #[test]
+    fn tree_changes_keep_their_order() {
+        let dir = TestDir::new();
+        let mut tree = dir.create(Tree::<u32, u32>::new);
+        let receiver = tree.0.subscribe();
+        let mut guard = tree.write();
+        guard.insert(1, 10);
+        guard.insert(2, 20);
+        guard.remove(&1);
+        guard.remove(&3);
+        if let Entry::Vacant(entry) = guard.entry(&1) {
+            *entry.insert(11) = 12;
+        }
+        if let Entry::Vacant(entry) = guard.entry(&3) {
+            *entry.insert(30) += 1;
+        }
+        assert_eq!(guard.get(&1), Some(&12));
+        assert!(receiver.try_recv().is_err());
+        drop(guard);
+        tree.write().clear();
+        let expected: std::vec::Vec<_> = vec![
+            TreeChange::Insert { key: 1, value: 10 },
+            TreeChange::Insert { key: 2, value: 20 },
+            TreeChange::Remove { key: 1 },
+            TreeChange::Insert { key: 1, value: 12 },
+            TreeChange::Insert { key: 3, value: 31 },
+            TreeChange::Clear,
+        ]
+        .into_iter()
+        .map(Event::Change)
+        .collect();
+        assert_eq!(receiver.try_iter().collect::<std::vec::Vec<_>>(), expected);
+    }
+}
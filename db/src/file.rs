@@ -5,7 +5,8 @@ use std::{
 
 use crate::{
     cursor::{reallocate, PageLookup},
-    lock::Lock,
+    lock::{Access, AsReadLock, Lock},
+    object::Field,
     page::{PageNr, PAGE_SIZE},
     reference::DatabaseRef,
 };
@@ -64,25 +65,26 @@ impl File {
     }
 
     pub fn read(&self) -> ReadFileGuard<'_> {
-        ReadFileGuard {
-            header: &self.header,
-            lock: self.database.lock(),
-            pos: 0,
-            lookup: PageLookup::Invalid,
-        }
+        FileGuard::new(&self.header, self.database.read_lock())
     }
 
     pub fn write(&mut self) -> WriteFileGuard<'_> {
-        WriteFileGuard {
-            header: &mut self.header,
-            lock: self.database.lock(),
+        FileGuard::new(&mut self.header, self.database.lock())
+    }
+}
+
+impl<H: Deref<Target = FileHeader> + Access> FileGuard<H> {
+    pub(crate) fn new(header: H, lock: H::Lock) -> Self {
+        Self {
+            header,
             pos: 0,
             lookup: PageLookup::Invalid,
+            lock,
         }
     }
 }
 
-impl<'a, H: DerefMut<Target = FileHeader>> FileGuard<'a, H> {
+impl<'a, H: DerefMut<Target = FileHeader> + Access<Lock = Lock<'a>>> FileGuard<H> {
     pub fn set_len(&mut self, size: u64) {
         let current_pages = self.header.pages();
         self.header.len = size;
@@ -92,7 +94,7 @@ impl<'a, H: DerefMut<Target = FileHeader>> FileGuard<'a, H> {
     }
 }
 
-impl<'a, H: Deref<Target = FileHeader>> Seek for FileGuard<'a, H> {
+impl<H: Deref<Target = FileHeader> + Access> Seek for FileGuard<H> {
     fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
         match pos {
             io::SeekFrom::Start(pos) => self.pos = pos,
@@ -103,7 +105,7 @@ impl<'a, H: Deref<Target = FileHeader>> Seek for FileGuard<'a, H> {
     }
 }
 
-impl<'a, H: Deref<Target = FileHeader>> Read for FileGuard<'a, H> {
+impl<H: Deref<Target = FileHeader> + Access> Read for FileGuard<H> {
     fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
         let len = buf
             .len()
@@ -114,9 +116,12 @@ impl<'a, H: Deref<Target = FileHeader>> Read for FileGuard<'a, H> {
             let offset = self.pos as usize % PAGE_SIZE;
             let n = buf.len().min(PAGE_SIZE - offset);
             let (a, b) = buf.split_at_mut(n);
-            let page = self
-                .lookup
-                .get(self.header.root, self.header.pages(), index, &self.lock);
+            let page = self.lookup.get(
+                self.header.root,
+                self.header.pages(),
+                index,
+                self.lock.read_lock(),
+            );
             a.copy_from_slice(&page[offset..offset + n]);
             self.pos += n as u64;
             buf = b;
@@ -125,7 +130,7 @@ impl<'a, H: Deref<Target = FileHeader>> Read for FileGuard<'a, H> {
     }
 }
 
-impl<'a, H: DerefMut<Target = FileHeader>> Write for FileGuard<'a, H> {
+impl<'a, H: DerefMut<Target = FileHeader> + Access<Lock = Lock<'a>>> Write for FileGuard<H> {
     fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
         let len = buf.len();
         let size = self.pos + buf.len() as u64;
@@ -156,15 +161,15 @@ impl<'a, H: DerefMut<Target = FileHeader>> Write for FileGuard<'a, H> {
     }
 }
 
-pub struct FileGuard<'a, H: Deref<Target = FileHeader>> {
+pub struct FileGuard<H: Deref<Target = FileHeader> + Access> {
     header: H,
     pos: u64,
     lookup: PageLookup,
-    lock: Lock<'a>,
+    lock: H::Lock,
 }
 
-pub type ReadFileGuard<'a> = FileGuard<'a, &'a FileHeader>;
-pub type WriteFileGuard<'a> = FileGuard<'a, &'a mut FileHeader>;
+pub type ReadFileGuard<'a> = FileGuard<&'a FileHeader>;
+pub type WriteFileGuard<'a> = FileGuard<&'a mut FileHeader>;
 
 impl Drop for File {
     fn drop(&mut self) {
@@ -175,3 +180,17 @@ impl Drop for File {
         }
     }
 }
+
+impl Field for File {
+    fn describe() -> String {
+        "File".to_owned()
+    }
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        File::serialize(self, writer)
+    }
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        File::deserialize(reader, database)
+    }
+}
@@ -22,6 +22,18 @@ pub struct FreeList {
 }
 
 impl FreeList {
+    pub fn root(&self) -> PageNr {
+        self.root
+    }
+
+    pub fn front(&self) -> u32 {
+        self.front
+    }
+
+    pub fn back(&self) -> u32 {
+        self.back
+    }
+
     pub unsafe fn shift_front(&mut self, pager: &Pager) -> Option<PageNr> {
         if self.front < self.back {
             let nr = self.get(self.front, pager);
@@ -34,6 +46,10 @@ impl FreeList {
         }
     }
 
+    pub unsafe fn entries<'a>(&'a self, pager: &'a Pager) -> impl Iterator<Item = PageNr> + 'a {
+        (0..self.back).map(move |index| self.get(index, pager))
+    }
+
     pub fn reset_front(&mut self) {
         self.front = 0;
     }
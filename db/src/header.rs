@@ -1,47 +1,105 @@
 use std::{
     convert::TryInto,
     io::{self, ErrorKind},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use bytemuck::{bytes_of, Pod, Zeroable};
+use bytemuck::{bytes_of, cast_mut, Pod, Zeroable};
 use sha3::{Digest, Sha3_512};
 
 use crate::{
     allocator::AllocatorState,
+    crypt::KeyDerivation,
+    file::FileHeader,
     page::{Page, PageNr},
+    storage::{Pages, Storage},
 };
 
 pub type Format = [u8; 256];
 
+/// Version of the layout of the header and pages, increased whenever they change
+/// incompatibly. Databases of other versions are rejected.
+pub const FORMAT_VERSION: u64 = 3;
+
 #[derive(Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 pub struct Header {
+    version: u64,
     format: Format,
     snapshots: [Snapshot; 2],
+    key_derivation: KeyDerivation,
 }
 
 impl Header {
-    pub fn new(format: Format) -> Self {
+    pub fn new(format: Format, key_derivation: KeyDerivation) -> Self {
         Self {
+            version: FORMAT_VERSION,
             format,
             snapshots: [
                 Snapshot::new(State::default()),
                 Snapshot::new(State::default()),
             ],
+            key_derivation,
         }
     }
 
+    pub fn key_derivation(&self) -> KeyDerivation {
+        self.key_derivation
+    }
+
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    /// The states in both snapshot slots and whether each one is intact.
+    pub fn snapshots(&self) -> [(State, bool); 2] {
+        [
+            (self.snapshots[0].state, self.valid_snapshot(0).is_some()),
+            (self.snapshots[1].state, self.valid_snapshot(1).is_some()),
+        ]
+    }
+
+    /// The state a database opened from this header starts from.
+    pub fn current(&self) -> Option<State> {
+        self.last_snapshot().map(|s| s.state)
+    }
+
     pub fn snapshot(&mut self, state: State) {
         self.snapshots[(state.version % 2) as usize] = Snapshot::new(state);
     }
 
-    pub fn validate(&self, format: &Format) -> io::Result<State> {
+    pub fn check_version(&self) -> io::Result<()> {
+        if self.version == FORMAT_VERSION {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "database format version {} is not supported, expected {}",
+                    self.version, FORMAT_VERSION
+                ),
+            ))
+        }
+    }
+
+    pub fn validate(&self, format: &Format, key_derivation: &KeyDerivation) -> io::Result<State> {
+        self.check_version()?;
         if self.format != *format {
             return Err(io::Error::new(
                 ErrorKind::InvalidData,
                 "database format mismatch",
             ));
         }
+        if self.key_derivation != *key_derivation {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                if self.key_derivation.is_encrypted() {
+                    "database is encrypted"
+                } else {
+                    "database is not encrypted"
+                },
+            ));
+        }
         self.last_snapshot()
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Corrupted database"))
             .map(|s| s.state)
@@ -71,12 +129,9 @@ impl Header {
 #[repr(C)]
 pub struct HeaderPage {
     pub header: Header,
-    _padding9: [u8; 16],
-    _padding0: [u8; 32],
+    _padding0: [u8; 8],
     _padding1: [u8; 16],
-    _padding2: [u8; 32],
     _padding3: [u8; 64],
-    _padding4: [u8; 128],
     _padding5: [u8; 256],
     _padding6: [u8; 1024],
     _padding7: [u8; 2048],
@@ -85,22 +140,63 @@ pub struct HeaderPage {
 
 assert_eq_size!(HeaderPage, Page);
 
+impl HeaderPage {
+    pub fn snapshot(data: &Storage, state: State) -> io::Result<()> {
+        let pages = Pages::new(data.clone())?;
+        let page = unsafe { &mut *pages.get(0) };
+        cast_mut::<Page, HeaderPage>(page).header.snapshot(state);
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, Default, Pod, Zeroable)]
 #[repr(C)]
 pub struct State {
     pub version: u64,
+    pub time: u64,
+    pub oldest: u64,
     pub allocator: AllocatorState,
     pub root_nr: PageNr,
     pub root_len: u64,
+    pub history_nr: PageNr,
+    pub _padding: u32,
+    pub history_len: u64,
 }
 
 impl State {
-    pub fn new(version: u64, allocator: AllocatorState, root_nr: PageNr, root_len: u64) -> Self {
+    pub fn new(
+        version: u64,
+        allocator: AllocatorState,
+        root: FileHeader,
+        history: FileHeader,
+    ) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
         Self {
             version,
+            time,
+            oldest: 0,
             allocator,
-            root_nr,
-            root_len,
+            root_nr: root.root,
+            root_len: root.len,
+            history_nr: history.root,
+            _padding: 0,
+            history_len: history.len,
+        }
+    }
+
+    pub fn root(&self) -> FileHeader {
+        FileHeader {
+            root: self.root_nr,
+            len: self.root_len,
+        }
+    }
+
+    pub fn history(&self) -> FileHeader {
+        FileHeader {
+            root: self.history_nr,
+            len: self.history_len,
         }
     }
 
@@ -138,3 +234,36 @@ impl Snapshot {
 }
 
 pub type Checksum = [u8; 64];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_other_versions() {
+        let format = [1; 256];
+        let key_derivation = KeyDerivation::zeroed();
+        let mut header = Header::new(format, key_derivation);
+        assert!(header.validate(&format, &key_derivation).is_ok());
+        assert!(header.validate(&[2; 256], &key_derivation).is_err());
+        header.version = FORMAT_VERSION - 1;
+        let error = header.validate(&format, &key_derivation).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn keep_long_histories() {
+        let history = FileHeader {
+            root: 3,
+            len: u32::MAX as u64 + 5,
+        };
+        let mut header = Header::new([0; 256], KeyDerivation::zeroed());
+        header.snapshot(State::new(
+            1,
+            AllocatorState::default(),
+            FileHeader { root: 2, len: 7 },
+            history,
+        ));
+        assert_eq!(header.current().unwrap().history().len, history.len);
+    }
+}
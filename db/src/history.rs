@@ -0,0 +1,421 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    mem::{replace, take},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytemuck::{bytes_of, bytes_of_mut, cast_slice, cast_slice_mut, Zeroable};
+
+use crate::{
+    file::{FileGuard, FileHeader},
+    header::State,
+    lock::{AsReadLock, Lock},
+    page::PageNr,
+};
+
+/// Controls which snapshots are kept restorable. The default retains nothing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Maximum number of retained snapshots. Older ones are released first.
+    pub count: usize,
+    /// Minimum time between two retained snapshots. Zero retains every snapshot.
+    pub interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Version {
+    pub version: u64,
+    pub time: SystemTime,
+}
+
+#[derive(Default)]
+pub struct History {
+    retention: Retention,
+    checkpoints: Vec<State>,
+    header: FileHeader,
+    written_held: usize,
+    dirty: bool,
+}
+
+/// Pages of retained snapshots. The allocator sets pinned pages aside as held instead of reusing
+/// them until no retained snapshot refers to them anymore. The pins of the previous history stay
+/// in effect as well, because the previous snapshot still refers to it until the next one is
+/// durable.
+#[derive(Default)]
+pub struct Pins {
+    pinned: Vec<bool>,
+    previous: Vec<bool>,
+    held: Vec<PageNr>,
+}
+
+impl History {
+    /// Loads the history of the current state. A restored state may name checkpoints older than
+    /// `State::oldest`, whose pages were released in the meantime, so they are dropped. The held
+    /// pages of a replayed write-ahead log replace those of the snapshot.
+    pub fn load(lock: &Lock) -> io::Result<()> {
+        let state = lock.state();
+        let (mut history, held) = Self::read(state.history(), lock)?;
+        history.written_held = held.len();
+        let logged = lock.take_logged_held();
+        let len = history.checkpoints.len();
+        history
+            .checkpoints
+            .retain(|checkpoint| checkpoint.version >= state.oldest);
+        history.dirty = history.checkpoints.len() != len || logged.is_some();
+        let held = logged.unwrap_or(held);
+        let pinned = history.pinned(lock)?;
+        *lock.pins() = Pins {
+            pinned,
+            previous: Vec::new(),
+            held,
+        };
+        *lock.history() = history;
+        Ok(())
+    }
+
+    /// Retains the last snapshot if the retention asks for it, releases expired snapshots and
+    /// writes the history that the next snapshot will refer to.
+    pub fn commit(lock: &Lock) -> io::Result<FileHeader> {
+        let mut history = lock.history();
+        let last = lock.state();
+        let mut changed = history.dirty;
+        if history.retains(&last) {
+            history.checkpoints.push(last);
+            changed = true;
+        }
+        let expired = history
+            .checkpoints
+            .len()
+            .saturating_sub(history.retention.count);
+        if expired > 0 {
+            history.checkpoints.drain(..expired);
+            changed = true;
+        }
+        if changed {
+            let pinned = history.pinned(lock)?;
+            let released = lock.pins().repin(pinned);
+            for nr in released {
+                unsafe { lock.deallocate(nr) }
+            }
+        }
+        if !changed && lock.pins().held.len() == history.written_held {
+            return Ok(history.header);
+        }
+        let mut header = history.header;
+        loop {
+            let held = lock.pins().held.clone();
+            let bytes = history.encode(&held);
+            let mut writer = FileGuard::new(&mut header, lock.clone());
+            writer.set_len(bytes.len() as u64);
+            writer.write_all(&bytes)?;
+            drop(writer);
+            if lock.pins().held.len() == held.len() {
+                history.written_held = held.len();
+                history.dirty = false;
+                break;
+            }
+        }
+        history.header = header;
+        Ok(header)
+    }
+
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.dirty |= self.retention != retention;
+        self.retention = retention;
+    }
+
+    pub fn oldest(&self) -> Option<u64> {
+        self.checkpoints.first().map(|state| state.version)
+    }
+
+    pub fn find(&self, version: u64) -> io::Result<State> {
+        self.checkpoints
+            .iter()
+            .find(|state| state.version == version)
+            .copied()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "version is not retained"))
+    }
+
+    pub fn versions(&self, current: State) -> Vec<Version> {
+        self.checkpoints
+            .iter()
+            .chain(Some(&current).filter(|state| state.version > 0))
+            .map(|state| Version {
+                version: state.version,
+                time: UNIX_EPOCH + Duration::from_secs(state.time),
+            })
+            .collect()
+    }
+
+    fn retains(&self, state: &State) -> bool {
+        self.retention.count > 0
+            && state.version > 0
+            && self.checkpoints.last().map_or(true, |last| {
+                state.time >= last.time + self.retention.interval.as_secs()
+            })
+    }
+
+    fn read(header: FileHeader, lock: &Lock) -> io::Result<(Self, Vec<PageNr>)> {
+        let mut history = Self {
+            header,
+            ..Self::default()
+        };
+        let mut held = Vec::new();
+        if header.len > 0 {
+            let mut reader = FileGuard::new(&header, lock.read_lock().clone());
+            let mut fields = [0u64; 4];
+            reader.read_exact(bytes_of_mut(&mut fields))?;
+            history.retention = Retention {
+                count: fields[0] as usize,
+                interval: Duration::from_secs(fields[1]),
+            };
+            history.checkpoints = vec![State::zeroed(); fields[2] as usize];
+            reader.read_exact(cast_slice_mut(&mut history.checkpoints))?;
+            held = vec![0; fields[3] as usize];
+            reader.read_exact(cast_slice_mut(&mut held))?;
+        }
+        Ok((history, held))
+    }
+
+    /// Pages in use by any retained state: everything allocated at the time except the pages on
+    /// the free lists and the pages that were held for older states.
+    fn pinned(&self, lock: &Lock) -> io::Result<Vec<bool>> {
+        let mut pinned = Vec::new();
+        for state in self.checkpoints.iter() {
+            let last_page = state.allocator.last_page() as usize;
+            let mut free = vec![false; last_page + 1];
+            for nr in unsafe { state.allocator.free_pages(lock.pager()) } {
+                free[nr as usize] = true;
+            }
+            for nr in Self::read(state.history(), lock)?.1 {
+                free[nr as usize] = true;
+            }
+            if pinned.len() < free.len() {
+                pinned.resize(free.len(), false);
+            }
+            for (pinned, free) in pinned.iter_mut().zip(free).skip(2) {
+                *pinned |= !free;
+            }
+        }
+        Ok(pinned)
+    }
+
+    fn encode(&self, held: &[PageNr]) -> Vec<u8> {
+        if self.retention == Retention::default() && self.checkpoints.is_empty() && held.is_empty()
+        {
+            return Vec::new();
+        }
+        let fields = [
+            self.retention.count as u64,
+            self.retention.interval.as_secs(),
+            self.checkpoints.len() as u64,
+            held.len() as u64,
+        ];
+        let mut bytes = bytes_of(&fields).to_vec();
+        bytes.extend_from_slice(cast_slice(&self.checkpoints));
+        bytes.extend_from_slice(cast_slice(held));
+        bytes
+    }
+}
+
+impl Pins {
+    pub fn held(&self) -> &[PageNr] {
+        &self.held
+    }
+
+    pub fn hold(&mut self, nr: PageNr) -> bool {
+        let pinned = self.is_pinned(nr);
+        if pinned {
+            self.held.push(nr);
+        }
+        pinned
+    }
+
+    fn is_pinned(&self, nr: PageNr) -> bool {
+        let index = nr as usize;
+        self.pinned.get(index).copied().unwrap_or(false)
+            || self.previous.get(index).copied().unwrap_or(false)
+    }
+
+    fn repin(&mut self, pinned: Vec<bool>) -> Vec<PageNr> {
+        self.previous = replace(&mut self.pinned, pinned);
+        let (held, released) = take(&mut self.held)
+            .into_iter()
+            .partition(|nr| self.is_pinned(*nr));
+        self.held = held;
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        testing::{Single, TestDir},
+        Database, Len, Vec,
+    };
+
+    use super::*;
+
+    type Values = Database<Single<Vec<u64>>>;
+
+    /// Replaces the content with values that are all `value`, spread over several pages.
+    fn fill(database: &mut Values, value: u64) {
+        let mut vec = database.write();
+        vec.resize(0, 0);
+        for _ in 0..5000 {
+            vec.push(value);
+        }
+    }
+
+    fn create(dir: &TestDir, retention: Retention) -> Values {
+        let mut database = dir.create(Vec::<u64>::new);
+        database.set_retention(retention);
+        database
+    }
+
+    fn versions(database: &Values) -> std::vec::Vec<u64> {
+        database
+            .versions()
+            .iter()
+            .map(|version| version.version)
+            .collect()
+    }
+
+    fn check(database: &Values, value: u64) {
+        let vec = database.read();
+        assert_eq!(vec.len(), 5000);
+        assert!(vec.iter().all(|stored| *stored == value));
+    }
+
+    fn open_version(dir: &TestDir, version: u64) -> io::Result<Values> {
+        Database::open_version(dir.path(), version)
+    }
+
+    #[test]
+    fn count_keeps_newest_snapshots() {
+        let dir = TestDir::new();
+        let mut database = create(
+            &dir,
+            Retention {
+                count: 2,
+                interval: Duration::from_secs(0),
+            },
+        );
+        for version in 1..=5 {
+            fill(&mut database, version);
+            database.snapshot().unwrap();
+        }
+        assert_eq!(versions(&database), [3, 4, 5]);
+        drop(database);
+        for version in [1, 2, 6] {
+            let error = open_version(&dir, version).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+        }
+        for version in 3..=5 {
+            check(&open_version(&dir, version).unwrap(), version);
+        }
+    }
+
+    #[test]
+    fn interval_skips_snapshots() {
+        let dir = TestDir::new();
+        let mut database = create(
+            &dir,
+            Retention {
+                count: 10,
+                interval: Duration::from_secs(3600),
+            },
+        );
+        for version in 1..=5 {
+            fill(&mut database, version);
+            database.snapshot().unwrap();
+        }
+        assert_eq!(versions(&database), [1, 5]);
+    }
+
+    #[test]
+    fn retained_pages_are_not_reused() {
+        let dir = TestDir::new();
+        let mut database = create(
+            &dir,
+            Retention {
+                count: 1,
+                interval: Duration::from_secs(3600),
+            },
+        );
+        fill(&mut database, 1);
+        database.snapshot().unwrap();
+        for version in 2..=20 {
+            fill(&mut database, version);
+            database.snapshot().unwrap();
+        }
+        assert_eq!(versions(&database), [1, 20]);
+        drop(database);
+        check(&open_version(&dir, 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn open_version_never_writes_to_the_file() {
+        let dir = TestDir::new();
+        let mut database = create(
+            &dir,
+            Retention {
+                count: 5,
+                interval: Duration::from_secs(0),
+            },
+        );
+        for version in 1..=3 {
+            fill(&mut database, version);
+            database.snapshot().unwrap();
+        }
+        drop(database);
+        let read = || {
+            let wal = fs::read(dir.path().with_extension("wal")).unwrap();
+            (fs::read(dir.path()).unwrap(), wal)
+        };
+        let files = read();
+        let mut old = open_version(&dir, 1).unwrap();
+        check(&old, 1);
+        fill(&mut old, 42);
+        old.snapshot().unwrap();
+        old.log().unwrap();
+        drop(old);
+        assert!(read() == files);
+        let database = Values::open(dir.path()).unwrap();
+        check(&database, 3);
+        assert_eq!(versions(&database), [1, 2, 3]);
+    }
+
+    #[test]
+    fn restore_discards_newer_versions() {
+        let dir = TestDir::new();
+        let mut database = create(
+            &dir,
+            Retention {
+                count: 5,
+                interval: Duration::from_secs(0),
+            },
+        );
+        for version in 1..=4 {
+            fill(&mut database, version);
+            database.snapshot().unwrap();
+        }
+        drop(database);
+        Values::restore(dir.path(), 2).unwrap();
+        let mut database = Values::open(dir.path()).unwrap();
+        check(&database, 2);
+        assert_eq!(versions(&database), [1, 5]);
+        fill(&mut database, 6);
+        database.snapshot().unwrap();
+        assert_eq!(versions(&database), [1, 5, 6]);
+        drop(database);
+        for version in [2, 3, 4] {
+            let error = open_version(&dir, version).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::NotFound);
+        }
+        check(&open_version(&dir, 1).unwrap(), 1);
+        check(&open_version(&dir, 5).unwrap(), 2);
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    io::{self, Read, Write},
+    ops::{Deref, DerefMut, Index},
+};
+
+use bytemuck::Pod;
+
+use crate::{
+    lock::{Access, Lock},
+    page::PageNr,
+    reference::DatabaseRef,
+    tree::{MultiTree, MultiTreeGuard},
+    vec::{Iter, Len, Vec, VecGuard, VecHeader},
+};
+
+pub type KeyFn<T, K> = fn(&T) -> K;
+
+pub struct IndexedVec<T: Pod, K: Pod + Ord> {
+    values: Vec<T>,
+    index: MultiTree<K, usize>,
+    key: KeyFn<T, K>,
+}
+
+pub struct IndexedVecGuard<
+    'a,
+    T: Pod,
+    K: Pod + Ord,
+    H: Deref<Target = VecHeader> + Access,
+    R: Deref<Target = PageNr> + Access,
+> {
+    values: VecGuard<'a, T, H>,
+    index: MultiTreeGuard<'a, R, K, usize>,
+    key: KeyFn<T, K>,
+}
+
+pub type ReadIndexedVecGuard<'a, T, K> = IndexedVecGuard<'a, T, K, &'a VecHeader, &'a PageNr>;
+pub type WriteIndexedVecGuard<'a, T, K> =
+    IndexedVecGuard<'a, T, K, &'a mut VecHeader, &'a mut PageNr>;
+
+impl<T: Pod, K: Pod + Ord> IndexedVec<T, K> {
+    pub fn new(database: DatabaseRef, key: KeyFn<T, K>) -> Self {
+        Self {
+            values: Vec::new(database.clone()),
+            index: MultiTree::new(database),
+            key,
+        }
+    }
+
+    pub fn deserialize(
+        reader: &mut impl Read,
+        database: DatabaseRef,
+        key: KeyFn<T, K>,
+    ) -> io::Result<Self> {
+        let values = Vec::deserialize(reader, database.clone())?;
+        let index = MultiTree::deserialize(reader, database)?;
+        Ok(Self { values, index, key })
+    }
+
+    pub fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.values.serialize(writer)?;
+        self.index.serialize(writer)?;
+        Ok(())
+    }
+
+    pub fn read(&self) -> ReadIndexedVecGuard<'_, T, K> {
+        IndexedVecGuard {
+            values: self.values.read(),
+            index: self.index.read(),
+            key: self.key,
+        }
+    }
+
+    pub fn write(&mut self) -> WriteIndexedVecGuard<'_, T, K> {
+        IndexedVecGuard {
+            values: self.values.write(),
+            index: self.index.write(),
+            key: self.key,
+        }
+    }
+}
+
+impl<
+        'a,
+        T: Pod,
+        K: Pod + Ord,
+        H: Deref<Target = VecHeader> + Access,
+        R: Deref<Target = PageNr> + Access,
+    > IndexedVecGuard<'a, T, K, H, R>
+{
+    pub fn find(&self, key: &K) -> impl Iterator<Item = usize> + '_ {
+        self.index.get(key)
+    }
+
+    pub fn iter(&self) -> Iter<'_, VecGuard<'a, T, H>> {
+        self.values.iter()
+    }
+}
+
+impl<
+        'a,
+        T: Pod,
+        K: Pod + Ord,
+        H: DerefMut<Target = VecHeader> + Access<Lock = Lock<'a>>,
+        R: DerefMut<Target = PageNr> + Access<Lock = Lock<'a>>,
+    > IndexedVecGuard<'a, T, K, H, R>
+{
+    pub fn push(&mut self, value: T) -> usize {
+        let index = self.values.len();
+        self.values.push(value);
+        self.index.insert((self.key)(&value), index);
+        index
+    }
+
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        let old = self.values[index];
+        let (old_key, new_key) = ((self.key)(&old), (self.key)(&value));
+        if old_key != new_key {
+            self.index.remove(&old_key, &index);
+            self.index.insert(new_key, index);
+        }
+        self.values[index] = value;
+        old
+    }
+
+    pub fn update(&mut self, index: usize, f: impl FnOnce(&mut T)) {
+        let mut value = self.values[index];
+        f(&mut value);
+        self.set(index, value);
+    }
+
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let last = self.values.len() - 1;
+        let value = self.values[index];
+        self.index.remove(&(self.key)(&value), &index);
+        if index != last {
+            let moved = self.values[last];
+            let key = (self.key)(&moved);
+            self.index.remove(&key, &last);
+            self.index.insert(key, index);
+            self.values[index] = moved;
+        }
+        self.values.pop();
+        value
+    }
+}
+
+impl<
+        'a,
+        T: Pod,
+        K: Pod + Ord,
+        H: Deref<Target = VecHeader> + Access,
+        R: Deref<Target = PageNr> + Access,
+    > Len for IndexedVecGuard<'a, T, K, H, R>
+{
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+}
+
+impl<
+        'a,
+        T: Pod,
+        K: Pod + Ord,
+        H: Deref<Target = VecHeader> + Access,
+        R: Deref<Target = PageNr> + Access,
+    > Index<usize> for IndexedVecGuard<'a, T, K, H, R>
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.values[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{header::Format, object::Object, testing::TestDir, Database};
+
+    use super::*;
+
+    type Value = [u32; 2];
+
+    fn first(value: &Value) -> u32 {
+        value[0]
+    }
+
+    struct Indexed(IndexedVec<Value, u32>);
+
+    impl Object for Indexed {
+        fn format() -> Format {
+            [0; 256]
+        }
+
+        fn serialize(&mut self, mut writer: impl Write) -> io::Result<()> {
+            self.0.serialize(&mut writer)
+        }
+
+        fn deserialize(mut reader: impl Read, database: DatabaseRef) -> io::Result<Self> {
+            IndexedVec::deserialize(&mut reader, database, first).map(Self)
+        }
+    }
+
+    /// Checks that the index maps every key to exactly the positions of the values with it.
+    fn check(indexed: &IndexedVec<Value, u32>) {
+        let guard = indexed.read();
+        let mut expected: BTreeMap<u32, std::vec::Vec<usize>> = BTreeMap::new();
+        for (index, value) in guard.iter().enumerate() {
+            expected.entry(first(value)).or_default().push(index);
+        }
+        for (key, indices) in &expected {
+            assert_eq!(&guard.find(key).collect::<std::vec::Vec<_>>(), indices);
+        }
+        assert_eq!(guard.index.iter().count(), guard.len());
+    }
+
+    #[test]
+    fn index_follows_changes() {
+        let dir = TestDir::new();
+        let mut database = Database::create(dir.path(), |database| {
+            Indexed(IndexedVec::new(database, first))
+        })
+        .unwrap();
+        {
+            let mut vec = database.0.write();
+            for i in 0..3000 {
+                assert_eq!(vec.push([i % 10, i]), i as usize);
+            }
+        }
+        check(&database.0);
+        {
+            let mut vec = database.0.write();
+            assert_eq!(vec.set(5, [42, 5]), [5, 5]);
+            assert_eq!(vec.set(6, [6, 60]), [6, 6]);
+            vec.update(7, |value| value[0] = 43);
+        }
+        check(&database.0);
+        assert_eq!(
+            database.0.read().find(&42).collect::<std::vec::Vec<_>>(),
+            [5]
+        );
+        {
+            let mut vec = database.0.write();
+            assert_eq!(vec.swap_remove(0), [0, 0]);
+            assert_eq!(vec[0], [9, 2999]);
+            assert_eq!(vec.swap_remove(vec.len() - 1), [8, 2998]);
+            while vec.len() > 1000 {
+                vec.swap_remove(vec.len() / 2);
+            }
+        }
+        check(&database.0);
+        {
+            let mut vec = database.0.write();
+            while vec.len() > 0 {
+                vec.swap_remove(0);
+            }
+            for i in 0..500 {
+                vec.push([i % 7, i]);
+            }
+        }
+        check(&database.0);
+        database.snapshot().unwrap();
+        drop(database);
+        let database = Database::<Indexed>::open(dir.path()).unwrap();
+        check(&database.0);
+        assert_eq!(database.0.read().len(), 500);
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    alloc::Layout,
+    fs,
+    io::{self, ErrorKind, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use bytemuck::{cast_ref, Zeroable};
+
+use crate::{
+    crypt::EncryptedFile,
+    cursor::{find_page_checked, MAX_PAGES},
+    file::{FileGuard, FileHeader},
+    free_list::FreeList,
+    header::{Format, Header, HeaderPage, State},
+    mmap::{MappedBitset, MappedFile},
+    page::{Page, PageNr, NULL_PAGE_NR, PAGE_SIZE},
+    storage::Storage,
+    tree::node::{order, second_offset, NodePage},
+    view::View,
+};
+
+/// Read-only access to the raw structures of a database file, for debugging tools. It opens the
+/// file read-only and ignores the write-ahead log, so it shows the last snapshot and does not
+/// disturb a database that is open elsewhere.
+pub struct Inspector {
+    header: Header,
+    data: Storage,
+    view: View,
+}
+
+/// The contents of one of the two snapshot slots in the header.
+#[derive(Clone, Copy, Debug)]
+pub struct SnapshotInfo {
+    pub version: u64,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub oldest: u64,
+    pub root_nr: PageNr,
+    pub root_len: u64,
+    pub history_nr: PageNr,
+    pub history_len: u64,
+    pub last_page: PageNr,
+    /// Whether the checksum matches and the slot fits the version.
+    pub valid: bool,
+}
+
+/// One of the free lists of the allocator. Its entries are the page numbers in `0..back`.
+#[derive(Clone, Debug)]
+pub struct FreeListInfo {
+    pub root: PageNr,
+    pub front: u32,
+    pub back: u32,
+    pub entries: Vec<PageNr>,
+}
+
+impl Inspector {
+    /// Opens the database at `path`. Encrypted databases need the secret they were created with.
+    pub fn open(path: impl AsRef<Path>, secret: Option<&[u8]>) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        let data = match secret {
+            Some(secret) => Storage::Encrypted(EncryptedFile::open(file, secret)?),
+            None => Storage::Mapped(MappedFile::read_only(file)?),
+        };
+        let len = data.len() / PAGE_SIZE;
+        let view = View::new(
+            data.clone(),
+            MappedBitset::new(len)?,
+            MappedBitset::new(len)?,
+            0,
+            Arc::default(),
+        );
+        let header = {
+            let pager = view.pager();
+            cast_ref::<Page, HeaderPage>(unsafe { pager.page(NULL_PAGE_NR) }).header
+        };
+        header.check_version()?;
+        if header.key_derivation().is_encrypted() && secret.is_none() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "database is encrypted",
+            ));
+        }
+        Ok(Self { header, data, view })
+    }
+
+    pub fn format(&self) -> &Format {
+        self.header.format()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.header.key_derivation().is_encrypted()
+    }
+
+    /// The number of pages in the file, including the header page.
+    pub fn page_count(&self) -> usize {
+        self.data.len() / PAGE_SIZE
+    }
+
+    pub fn snapshots(&self) -> [SnapshotInfo; 2] {
+        let [first, second] = self.header.snapshots();
+        [Self::snapshot_info(first), Self::snapshot_info(second)]
+    }
+
+    fn snapshot_info((state, valid): (State, bool)) -> SnapshotInfo {
+        SnapshotInfo {
+            version: state.version,
+            time: state.time,
+            oldest: state.oldest,
+            root_nr: state.root_nr,
+            root_len: state.root_len,
+            history_nr: state.history_nr,
+            history_len: state.history_len,
+            last_page: state.allocator.last_page(),
+            valid,
+        }
+    }
+
+    /// The version of the snapshot the database would be opened with.
+    pub fn current(&self) -> Option<u64> {
+        self.header.current().map(|state| state.version)
+    }
+
+    fn state(&self) -> io::Result<State> {
+        self.header
+            .current()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "no valid snapshot"))
+    }
+
+    /// The free lists of the previous and the current snapshot.
+    pub fn free_lists(&self) -> io::Result<[FreeListInfo; 2]> {
+        let state = self.state()?;
+        let pager = self.view.pager();
+        let [previous, current] = state.allocator.free_lists();
+        let info = |list: &FreeList| FreeListInfo {
+            root: list.root(),
+            front: list.front(),
+            back: list.back(),
+            entries: unsafe { list.entries(&pager) }.collect(),
+        };
+        let lists = [info(previous), info(current)];
+        self.data.check()?;
+        Ok(lists)
+    }
+
+    /// Reads a page as it is stored, after decryption.
+    pub fn page(&self, nr: PageNr) -> io::Result<Vec<u8>> {
+        self.check(nr)?;
+        let mut page = Page::zeroed();
+        match &self.data {
+            Storage::Mapped(_) => page = *unsafe { self.view.pager().page(nr) },
+            Storage::Encrypted(file) => file.read(nr, &mut page)?,
+        }
+        Ok(page.to_vec())
+    }
+
+    /// Reads the root file, which holds the serialized root object of the current snapshot.
+    pub fn root(&self) -> io::Result<Vec<u8>> {
+        let header = self.state()?.root();
+        self.read_file(&header)
+    }
+
+    fn read_file(&self, header: &FileHeader) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        FileGuard::new(header, self.view.lock()).read_to_end(&mut bytes)?;
+        self.data.check()?;
+        Ok(bytes)
+    }
+
+    /// Visits the entries of the tree with the given root in order, as raw bytes of keys and
+    /// values with the given layouts.
+    pub fn tree(
+        &self,
+        root: PageNr,
+        key: Layout,
+        value: Layout,
+        mut visit: impl FnMut(&[u8], &[u8]),
+    ) -> io::Result<()> {
+        if key.size() == 0 || value.size() == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "tree entries must not be empty",
+            ));
+        }
+        if root == NULL_PAGE_NR {
+            return Ok(());
+        }
+        self.check(root)?;
+        let mut visited = vec![false; self.page_count()];
+        self.visit_node(root, key, value, &mut visited, &mut visit)?;
+        self.data.check()
+    }
+
+    /// Visits the node `nr`, which must be within the file. Every node is visited at most once,
+    /// so that a corrupted tree with cycles ends as well.
+    fn visit_node(
+        &self,
+        nr: PageNr,
+        key: Layout,
+        value: Layout,
+        visited: &mut [bool],
+        visit: &mut impl FnMut(&[u8], &[u8]),
+    ) -> io::Result<()> {
+        if nr == NULL_PAGE_NR || visited[nr as usize] {
+            return Err(Self::corrupted(nr));
+        }
+        visited[nr as usize] = true;
+        let lock = self.view.lock();
+        let page = unsafe { lock.page(nr) };
+        let len = page.len();
+        if page.is_leaf() {
+            let order = order(key.size(), value.size(), value.align(), 2);
+            if len > order {
+                return Err(Self::corrupted(nr));
+            }
+            let offset = second_offset(key.size(), value.align(), order);
+            for index in 0..len {
+                let key = &page[index * key.size()..(index + 1) * key.size()];
+                let start = offset + index * value.size();
+                visit(key, &page[start..start + value.size()]);
+            }
+        } else {
+            let key_order = order(key.size(), 4, 4, 6);
+            if len == 0 || len > key_order + 1 {
+                return Err(Self::corrupted(nr));
+            }
+            let offset = second_offset(key.size(), 4, key_order);
+            let children: Vec<PageNr> = page[offset..offset + len * 4]
+                .chunks_exact(4)
+                .map(|bytes| PageNr::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect();
+            for child in children {
+                self.check_reference(child)?;
+                self.visit_node(child, key, value, visited, visit)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Visits the elements of the vector with the given root and length, as raw bytes with the
+    /// given layout.
+    pub fn vec(
+        &self,
+        root: PageNr,
+        len: usize,
+        element: Layout,
+        mut visit: impl FnMut(usize, &[u8]),
+    ) -> io::Result<()> {
+        if element.size() == 0 || element.size() > PAGE_SIZE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "vector elements must fit into a page",
+            ));
+        }
+        let per_page = PAGE_SIZE / element.size();
+        let pages = (len + per_page - 1) / per_page;
+        if pages > MAX_PAGES {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "vector is too long",
+            ));
+        }
+        if pages > 0 && root == NULL_PAGE_NR {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "vector has no pages",
+            ));
+        }
+        if pages > 0 {
+            self.check(root)?;
+        }
+        let lock = self.view.lock();
+        for page_index in 0..pages {
+            let nr = find_page_checked(root, pages, page_index, &lock, |nr| {
+                self.check_reference(nr)
+            })?;
+            let page = unsafe { lock.page(nr) };
+            let first = page_index * per_page;
+            for index in first..len.min(first + per_page) {
+                let start = (index - first) * element.size();
+                visit(index, &page[start..start + element.size()]);
+            }
+        }
+        self.data.check()
+    }
+
+    fn check(&self, nr: PageNr) -> io::Result<()> {
+        if (nr as usize) < self.page_count() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("page {} is beyond the end of the file", nr),
+            ))
+        }
+    }
+
+    /// Checks a page number that was read from the file, which only points outside of it or to
+    /// the header if the database is corrupted.
+    fn check_reference(&self, nr: PageNr) -> io::Result<()> {
+        if nr != NULL_PAGE_NR && (nr as usize) < self.page_count() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("reference to page {}, which is not in the file", nr),
+            ))
+        }
+    }
+
+    fn corrupted(nr: PageNr) -> io::Error {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("page {} is no valid tree node", nr),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::TryInto,
+        fs::OpenOptions,
+        io::{Seek, SeekFrom, Write},
+    };
+
+    use crate::{
+        testing::{Single, TestDir},
+        Database, Tree, Vec,
+    };
+
+    use super::*;
+
+    /// Overwrites a page number stored at `offset` in page `nr` of the file.
+    fn corrupt(dir: &TestDir, nr: PageNr, offset: usize, value: PageNr) {
+        let mut file = OpenOptions::new().write(true).open(dir.path()).unwrap();
+        let position = nr as usize * PAGE_SIZE + offset;
+        file.seek(SeekFrom::Start(position as u64)).unwrap();
+        file.write_all(&value.to_ne_bytes()).unwrap();
+    }
+
+    fn root_nr(inspector: &Inspector) -> PageNr {
+        let root = inspector.root().unwrap();
+        PageNr::from_ne_bytes([root[0], root[1], root[2], root[3]])
+    }
+
+    /// Creates a tree whose root is a branch and returns the offset of its first child.
+    fn create_tree(dir: &TestDir) -> (PageNr, usize) {
+        let mut database = dir.create(Tree::<u32, u32>::new);
+        {
+            let mut tree = database.write();
+            for key in 0..3000 {
+                tree.insert(key, key);
+            }
+        }
+        database.snapshot().unwrap();
+        drop(database);
+        let inspector = Inspector::open(dir.path(), None).unwrap();
+        let mut count = 0;
+        let layout = Layout::new::<u32>();
+        inspector
+            .tree(root_nr(&inspector), layout, layout, |_, _| count += 1)
+            .unwrap();
+        assert_eq!(count, 3000);
+        (root_nr(&inspector), second_offset(4, 4, order(4, 4, 4, 6)))
+    }
+
+    fn visit_tree(dir: &TestDir, root: PageNr) -> io::Result<()> {
+        let inspector = Inspector::open(dir.path(), None).unwrap();
+        let layout = Layout::new::<u32>();
+        inspector.tree(root, layout, layout, |_, _| {})
+    }
+
+    #[test]
+    fn inspect_in_place() {
+        let dir = TestDir::new();
+        let mut database = dir.create(Tree::<u32, u32>::new);
+        database.write().insert(1, 2);
+        database.snapshot().unwrap();
+        drop(database);
+        let before = fs::read(dir.path()).unwrap();
+        let inspector = Inspector::open(dir.path(), None).unwrap();
+        assert!(inspector.current().is_some());
+        assert!(!inspector.root().unwrap().is_empty());
+        drop(inspector);
+        assert!(fs::read(dir.path()).unwrap() == before);
+    }
+
+    #[test]
+    fn reject_wrong_secret() {
+        let dir = TestDir::new();
+        let mut database = Database::create_encrypted(dir.path(), b"secret", |database| {
+            Single(Tree::<u32, u32>::new(database))
+        })
+        .unwrap();
+        database.snapshot().unwrap();
+        drop(database);
+        assert!(Inspector::open(dir.path(), Some(b"other")).is_err());
+        assert!(Inspector::open(dir.path(), Some(b"secret")).is_ok());
+    }
+
+    #[test]
+    fn reject_child_beyond_the_file() {
+        let dir = TestDir::new();
+        let (root, children) = create_tree(&dir);
+        corrupt(&dir, root, children + 4, 0xffff_ff00);
+        let error = visit_tree(&dir, root).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reject_cyclic_tree() {
+        let dir = TestDir::new();
+        let (root, children) = create_tree(&dir);
+        corrupt(&dir, root, children, root);
+        let error = visit_tree(&dir, root).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reject_vec_page_beyond_the_file() {
+        let dir = TestDir::new();
+        let mut database = dir.create(Vec::<u64>::new);
+        {
+            let mut vec = database.write();
+            for value in 0..5000 {
+                vec.push(value);
+            }
+        }
+        database.snapshot().unwrap();
+        drop(database);
+        let visit = |dir: &TestDir| {
+            let inspector = Inspector::open(dir.path(), None).unwrap();
+            let root = root_nr(&inspector);
+            let mut sum = 0;
+            inspector
+                .vec(root, 5000, Layout::new::<u64>(), |_, bytes| {
+                    sum += u64::from_ne_bytes(bytes.try_into().unwrap())
+                })
+                .map(|()| (root, sum))
+        };
+        let (root, sum) = visit(&dir).unwrap();
+        assert_eq!(sum, (0..5000).sum());
+        for nr in [0xffff_ff00, NULL_PAGE_NR] {
+            corrupt(&dir, root, 3 * 4, nr);
+            assert_eq!(visit(&dir).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+    }
+}
@@ -1,26 +1,55 @@
 mod allocator;
+mod blob;
+mod crypt;
 mod cursor;
 mod database;
+#[cfg(feature = "fault-injection")]
+mod fault;
+mod feed;
 mod file;
 mod free_list;
 mod header;
+mod history;
+mod indexed;
+mod inspect;
 mod lock;
 mod mmap;
 mod object;
 mod page;
 mod raw;
+mod record;
 mod reference;
+mod spatial;
+mod storage;
 mod sync;
+#[cfg(test)]
+mod testing;
 mod tree;
 mod vec;
+mod view;
+mod wal;
 
 #[macro_use]
 extern crate static_assertions;
 
+// Lets the tests use the derive macros, which refer to this crate as `db`.
+#[cfg(test)]
+extern crate self as db;
+
+pub use blob::Blob;
 pub use database::Database;
+pub use derive::{Layout, Object};
+#[cfg(feature = "fault-injection")]
+pub use fault::{FaultyDisk, Unsynced};
+pub use feed::{Event, TreeChange, VecChange};
 pub use file::File;
 pub use header::Format;
-pub use object::Object;
+pub use history::{Retention, Version};
+pub use indexed::IndexedVec;
+pub use inspect::{FreeListInfo, Inspector, SnapshotInfo};
+pub use object::{fingerprint, Field, Layout, Object};
+pub use record::RecordLog;
 pub use reference::DatabaseRef;
-pub use tree::{Entry, Tree};
-pub use vec::{Len, Vec};
+pub use spatial::{Point, SpatialIndex};
+pub use tree::{Entry, MultiTree, Tree, TreeView};
+pub use vec::{Len, Vec, VecView};
@@ -1,20 +1,84 @@
+use std::{ops::Deref, sync::MutexGuard};
+
 use crate::{
     allocator::Allocator,
+    header::State,
+    history::{History, Pins},
     page::{Page, PageNr, Pager, NULL_PAGE_NR},
-    raw::RawDatabase,
+    raw::{RawDatabase, SnapshotListener},
+    view::View,
 };
 use atomic_refcell::AtomicRef;
 use bytemuck::Zeroable;
 
-pub struct Lock<'a> {
+/// Reads the pages of the current state of a database or of a view.
+pub struct ReadLock<'a> {
     pager: Pager,
+    source: Source<'a>,
+}
+
+enum Source<'a> {
+    Database(AtomicRef<'a, RawDatabase>),
+    View(&'a View),
+}
+
+/// Reads and writes the pages of a database. Everything a `ReadLock` can do is available
+/// through `Deref`.
+pub struct Lock<'a> {
+    read: ReadLock<'a>,
     database: AtomicRef<'a, RawDatabase>,
 }
 
+/// Chooses the lock of a guard by how it borrows its root: guards that borrow it immutably only
+/// read, so they also work on views.
+pub trait Access {
+    type Lock: AsReadLock;
+}
+
+impl<'a, T> Access for &'a T {
+    type Lock = ReadLock<'a>;
+}
+
+impl<'a, T> Access for &'a mut T {
+    type Lock = Lock<'a>;
+}
+
+/// The read access of a lock.
+pub trait AsReadLock {
+    fn read_lock(&self) -> &ReadLock<'_>;
+}
+
+impl<'a> ReadLock<'a> {
+    pub fn new(database: AtomicRef<'a, RawDatabase>) -> Self {
+        Self {
+            pager: database.pager(),
+            source: Source::Database(database),
+        }
+    }
+
+    pub fn view(view: &'a View) -> Self {
+        Self {
+            pager: view.pager(),
+            source: Source::View(view),
+        }
+    }
+
+    pub unsafe fn page(&self, nr: PageNr) -> &Page {
+        assert_ne!(nr, NULL_PAGE_NR);
+        self.pager.page(nr)
+    }
+
+    pub fn pager(&self) -> &Pager {
+        &self.pager
+    }
+}
+
 impl<'a> Lock<'a> {
     pub fn new(database: AtomicRef<'a, RawDatabase>) -> Self {
-        let pager = database.pager();
-        Self { pager, database }
+        Self {
+            read: ReadLock::new(AtomicRef::clone(&database)),
+            database,
+        }
     }
 
     pub fn allocate(&self) -> PageNr {
@@ -22,11 +86,31 @@ impl<'a> Lock<'a> {
     }
 
     pub fn close(&self) {
-        self.database.close()
+        self.database().close()
     }
 
     pub fn is_closing(&self) -> bool {
-        self.database.is_closing()
+        self.database().is_closing()
+    }
+
+    pub fn on_snapshot(&self, listener: SnapshotListener) {
+        self.database().on_snapshot(listener)
+    }
+
+    pub fn state(&self) -> State {
+        self.database().state()
+    }
+
+    pub fn history(&self) -> MutexGuard<'_, History> {
+        self.database().history()
+    }
+
+    pub fn pins(&self) -> MutexGuard<'_, Pins> {
+        self.database().pins()
+    }
+
+    pub fn take_logged_held(&self) -> Option<Vec<PageNr>> {
+        self.database().take_logged_held()
     }
 
     pub unsafe fn deallocate(&self, nr: PageNr) {
@@ -37,11 +121,6 @@ impl<'a> Lock<'a> {
         self.allocator().reallocate(nr)
     }
 
-    pub unsafe fn page(&self, nr: PageNr) -> &Page {
-        assert_ne!(nr, NULL_PAGE_NR);
-        self.pager.page(nr)
-    }
-
     pub unsafe fn page_mut(&self, nr: &mut PageNr) -> &mut Page {
         if *nr == NULL_PAGE_NR {
             *nr = self.allocate_zeroed();
@@ -70,16 +149,50 @@ impl<'a> Lock<'a> {
         nr
     }
 
+    fn database(&self) -> &RawDatabase {
+        &self.database
+    }
+
     fn allocator(&self) -> Allocator<'_> {
-        Allocator::new(self.database.allocator_state(), &self.pager)
+        Allocator::new(
+            self.database().allocator_state(),
+            self.database().pins(),
+            &self.pager,
+        )
     }
 }
 
-impl<'a> Clone for Lock<'a> {
+impl<'a> Clone for ReadLock<'a> {
     fn clone(&self) -> Self {
-        Self {
-            database: AtomicRef::clone(&self.database),
-            pager: self.database.pager(),
+        match &self.source {
+            Source::Database(database) => Self::new(AtomicRef::clone(database)),
+            Source::View(view) => Self::view(view),
         }
     }
 }
+
+impl<'a> Clone for Lock<'a> {
+    fn clone(&self) -> Self {
+        Self::new(AtomicRef::clone(&self.database))
+    }
+}
+
+impl<'a> Deref for Lock<'a> {
+    type Target = ReadLock<'a>;
+
+    fn deref(&self) -> &ReadLock<'a> {
+        &self.read
+    }
+}
+
+impl<'a> AsReadLock for ReadLock<'a> {
+    fn read_lock(&self) -> &ReadLock<'_> {
+        self
+    }
+}
+
+impl<'a> AsReadLock for Lock<'a> {
+    fn read_lock(&self) -> &ReadLock<'_> {
+        &self.read
+    }
+}
@@ -9,43 +9,115 @@ use std::{
 };
 
 use bytemuck::Pod;
-use memmap2::MmapRaw;
+use memmap2::{MmapMut, MmapOptions, MmapRaw};
+
+#[cfg(feature = "fault-injection")]
+use crate::fault::{DiskFile, FaultyDisk};
 
 #[derive(Clone)]
-pub struct MappedFile(Arc<(Mutex<Arc<MmapRaw>>, File)>);
+pub struct MappedFile(Arc<Inner>);
+
+/// A memory map of a file. Private maps keep their changes in memory, so that they also work
+/// for files that are opened read-only.
+pub enum Mapping {
+    Shared(MmapRaw),
+    Private(MmapMut),
+}
+
+struct Inner {
+    raw: Mutex<Arc<Mapping>>,
+    file: File,
+    #[cfg(feature = "fault-injection")]
+    disk: Option<(FaultyDisk, u64)>,
+}
 
 impl MappedFile {
     pub fn new(file: File) -> io::Result<Self> {
+        let raw = Self::map(&file)?;
+        Ok(Self(Arc::new(Inner {
+            raw,
+            file,
+            #[cfg(feature = "fault-injection")]
+            disk: None,
+        })))
+    }
+
+    /// Maps a file without writing to it, for files that are opened read-only. The file cannot
+    /// grow.
+    pub fn read_only(file: File) -> io::Result<Self> {
+        let map = unsafe { MmapOptions::new().map_copy(&file)? };
+        Ok(Self(Arc::new(Inner {
+            raw: Mutex::new(Arc::new(Mapping::Private(map))),
+            file,
+            #[cfg(feature = "fault-injection")]
+            disk: None,
+        })))
+    }
+
+    /// Maps the data file of a simulated disk, which only receives its contents when
+    /// synchronized.
+    #[cfg(feature = "fault-injection")]
+    pub fn faulty(file: File, disk: &FaultyDisk, attachment: u64) -> io::Result<Self> {
+        let raw = Self::map(&file)?;
+        Ok(Self(Arc::new(Inner {
+            raw,
+            file,
+            disk: Some((disk.clone(), attachment)),
+        })))
+    }
+
+    fn map(file: &File) -> io::Result<Mutex<Arc<Mapping>>> {
         let page_size = page_size::get() as u64;
         if file.metadata()?.len() < page_size {
             file.set_len(page_size)?;
         }
-        let raw = Arc::new(MmapRaw::map_raw(&file)?);
-        Ok(Self(Arc::new((Mutex::new(raw), file))))
+        Ok(Mutex::new(Arc::new(Mapping::Shared(MmapRaw::map_raw(
+            file,
+        )?))))
     }
 
-    fn raw(&self, min_len: usize) -> io::Result<Arc<MmapRaw>> {
-        let mut raw = self.0 .0.lock().unwrap();
+    fn raw(&self, min_len: usize) -> io::Result<Arc<Mapping>> {
+        let mut raw = self.0.raw.lock().unwrap();
         let len = raw.len();
         if len < min_len {
-            self.0 .1.set_len(min_len.max(len * 2) as u64)?;
-            *raw.deref_mut() = Arc::new(MmapRaw::map_raw(&self.0 .1)?)
+            self.0.file.set_len(min_len.max(len * 2) as u64)?;
+            *raw.deref_mut() = Arc::new(Mapping::Shared(MmapRaw::map_raw(&self.0.file)?))
         }
         Ok(raw.clone())
     }
 
     pub fn sync(&self) -> io::Result<()> {
-        self.0 .1.sync_data()
+        #[cfg(feature = "fault-injection")]
+        if let Some((disk, attachment)) = &self.0.disk {
+            return disk.sync(*attachment, DiskFile::Data);
+        }
+        self.0.file.sync_data()
     }
 
     pub fn len(&self) -> usize {
-        self.0 .0.lock().unwrap().len()
+        self.0.raw.lock().unwrap().len()
+    }
+}
+
+impl Mapping {
+    fn as_ptr(&self) -> *const u8 {
+        match self {
+            Self::Shared(raw) => raw.as_ptr(),
+            Self::Private(map) => map.as_ptr(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Shared(raw) => raw.len(),
+            Self::Private(map) => map.len(),
+        }
     }
 }
 
 #[derive(Clone)]
 struct MappedBuffer {
-    raw: Arc<MmapRaw>,
+    raw: Arc<Mapping>,
     file: MappedFile,
 }
 
@@ -55,7 +127,7 @@ impl MappedBuffer {
         Ok(Self { raw, file })
     }
 
-    fn grow(&mut self, min_len: usize) -> io::Result<Arc<MmapRaw>> {
+    fn grow(&mut self, min_len: usize) -> io::Result<Arc<Mapping>> {
         Ok(if self.raw.len() < min_len {
             let mut raw = self.file.raw(min_len)?;
             swap(&mut self.raw, &mut raw);
@@ -65,8 +137,10 @@ impl MappedBuffer {
         })
     }
 
+    /// The length of this buffer's own mapping, which may lag behind the file if a clone grew
+    /// it. Only this much can be accessed through `as_ptr` until the buffer grows as well.
     fn len(&self) -> usize {
-        self.file.len()
+        self.raw.len()
     }
 
     fn as_ptr(&self) -> *const u8 {
@@ -97,7 +171,7 @@ impl<T: Pod> MappedVec<T> {
         self.0.len() / size_of::<T>()
     }
 
-    pub fn grow(&mut self, min_len: usize) -> io::Result<Arc<MmapRaw>> {
+    pub fn grow(&mut self, min_len: usize) -> io::Result<Arc<Mapping>> {
         self.0.grow(min_len * size_of::<T>())
     }
 }
@@ -1,4 +1,11 @@
-use std::io::{self, Read, Write};
+use std::{
+    any::type_name,
+    io::{self, Read, Write},
+    mem::size_of,
+};
+
+use bytemuck::Pod;
+use sha3::{Digest, Sha3_512};
 
 use crate::{header::Format, reference::DatabaseRef};
 
@@ -9,3 +16,173 @@ pub trait Object: Sized {
 
     fn deserialize(reader: impl Read, database: DatabaseRef) -> io::Result<Self>;
 }
+
+pub trait Field: Sized {
+    /// Describes how the field is stored for `fingerprint`. `Pod` values are described by their
+    /// `Layout`, serialized values by the name of their type without its module path.
+    fn describe() -> String;
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()>;
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self>;
+}
+
+/// A `Pod` value that can be described for `fingerprint`.
+///
+/// Structs derive it with `#[derive(Layout)]`, which describes them by the name and layout of
+/// every field, but not by the name of the struct itself.
+pub trait Layout: Pod {
+    fn describe() -> String;
+}
+
+macro_rules! impl_layout {
+    ($($ty:ty),*) => {
+        $(impl Layout for $ty {
+            fn describe() -> String {
+                stringify!($ty).to_owned()
+            }
+        })*
+    };
+}
+
+impl_layout!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Layout for usize {
+    fn describe() -> String {
+        format!("u{}", size_of::<Self>() * 8)
+    }
+}
+
+impl Layout for isize {
+    fn describe() -> String {
+        format!("i{}", size_of::<Self>() * 8)
+    }
+}
+
+impl<T: Layout, const N: usize> Layout for [T; N]
+where
+    [T; N]: Pod,
+{
+    fn describe() -> String {
+        format!("[{}; {}]", T::describe(), N)
+    }
+}
+
+/// The name of `T` with the module path stripped from it and from its type parameters.
+pub(crate) fn short_type_name<T>() -> String {
+    let mut name = String::new();
+    let mut start = 0;
+    let mut chars = type_name::<T>().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            name.truncate(start);
+        } else {
+            name.push(c);
+            if !c.is_alphanumeric() && c != '_' {
+                start = name.len();
+            }
+        }
+    }
+    name
+}
+
+pub fn fingerprint(name: &str, fields: &[(&str, String)]) -> Format {
+    let mut hasher = Sha3_512::new();
+    hasher.update(name);
+    for (field, ty) in fields {
+        hasher.update([0]);
+        hasher.update(field);
+        hasher.update([0]);
+        hasher.update(ty);
+    }
+    let hash = hasher.finalize();
+    let mut format = [0; 256];
+    format[..hash.len()].copy_from_slice(&hash);
+    let name = name.as_bytes();
+    let len = name.len().min(format.len() - hash.len());
+    format[hash.len()..hash.len() + len].copy_from_slice(&name[..len]);
+    format
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use crate::{Blob, Layout, RecordLog, Tree, Vec};
+
+    use super::*;
+
+    #[derive(Clone, Copy, Pod, Zeroable, Layout)]
+    #[repr(C)]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Clone, Copy, Pod, Zeroable, Layout)]
+    #[repr(C)]
+    struct Point {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Clone, Copy, Pod, Zeroable, Layout)]
+    #[repr(C)]
+    struct Swapped {
+        y: f32,
+        x: f32,
+    }
+
+    #[derive(Clone, Copy, Pod, Zeroable, Layout)]
+    #[repr(C)]
+    struct Player {
+        position: Position,
+        health: u32,
+    }
+
+    #[derive(Clone, Copy, Pod, Zeroable, Layout)]
+    #[repr(C)]
+    struct Npc {
+        position: Swapped,
+        health: u32,
+    }
+
+    #[derive(Clone, Copy, Pod, Zeroable, Layout)]
+    #[repr(C)]
+    struct Pair(u32, u32);
+
+    fn format<A: Field, B: Field>() -> Format {
+        fingerprint("World", &[("a", A::describe()), ("b", B::describe())])
+    }
+
+    #[test]
+    fn fingerprint_follows_layout() {
+        let world = format::<Tree<u64, Position>, Vec<u32>>();
+        assert!(world == format::<Tree<u64, Point>, Vec<u32>>());
+        assert!(world != format::<Tree<u64, Swapped>, Vec<u32>>());
+        assert!(world != format::<Tree<u64, [f32; 2]>, Vec<u32>>());
+        assert!(world != format::<Tree<u64, u64>, Vec<u32>>());
+        assert!(world != format::<Vec<u32>, Tree<u64, Position>>());
+        assert!(format::<Vec<Player>, Vec<Pair>>() != format::<Vec<Npc>, Vec<Pair>>());
+        assert!(format::<Vec<Pair>, Vec<u32>>() != format::<Vec<[u32; 2]>, Vec<u32>>());
+    }
+
+    #[test]
+    fn fingerprint_follows_serialized_types() {
+        let world = format::<RecordLog<String>, Blob<u64, String>>();
+        assert!(world == format::<RecordLog<String>, Blob<u64, String>>());
+        assert!(world != format::<RecordLog<u64>, Blob<u64, String>>());
+        assert!(world != format::<RecordLog<String>, Blob<u64, u64>>());
+        assert!(world != format::<RecordLog<String>, Blob<u32, String>>());
+    }
+
+    #[test]
+    fn type_names_drop_module_paths() {
+        assert_eq!(short_type_name::<Position>(), "Position");
+        assert_eq!(
+            short_type_name::<std::collections::BTreeMap<u64, std::vec::Vec<Position>>>(),
+            "BTreeMap<u64, Vec<Position>>"
+        );
+    }
+}
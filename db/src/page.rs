@@ -5,9 +5,11 @@ use std::{
 };
 
 use bytemuck::{Pod, Zeroable};
-use memmap2::MmapRaw;
 
-use crate::mmap::{MappedBitset, MappedFile, MappedVec};
+use crate::{
+    mmap::{MappedBitset, Mapping},
+    storage::{Pages, Storage},
+};
 
 pub const PAGE_SIZE: usize = 8192;
 
@@ -41,19 +43,21 @@ pub struct Pager {
 }
 
 pub struct Inner {
-    pages: MappedVec<Page>,
-    old_pages: Vec<Arc<MmapRaw>>,
+    pages: Pages,
+    old_pages: Vec<Arc<Mapping>>,
     writable: MappedBitset,
+    dirty: MappedBitset,
 }
 
 impl Pager {
-    pub fn new(data: MappedFile, writable: MappedBitset) -> Self {
-        let pages = MappedVec::new(data).unwrap();
+    pub fn new(data: Storage, writable: MappedBitset, dirty: MappedBitset) -> Self {
+        let pages = Pages::new(data).unwrap();
         Self {
             inner: UnsafeCell::new(Inner {
                 pages,
                 old_pages: Vec::new(),
                 writable,
+                dirty,
             }),
         }
     }
@@ -62,7 +66,7 @@ impl Pager {
         let inner = &mut *self.inner.get();
         let index = nr as usize;
         inner.grow(index + 1);
-        &*inner.pages.get(index).get()
+        &*inner.pages.get(index)
     }
 
     #[allow(clippy::mut_from_ref)]
@@ -70,7 +74,8 @@ impl Pager {
         let inner = &mut *self.inner.get();
         let index = nr as usize;
         inner.grow(index + 1);
-        &mut *inner.pages.get(index).get()
+        inner.dirty.set(index);
+        &mut *inner.pages.get_mut(index)
     }
 
     #[allow(clippy::mut_from_ref)]
@@ -79,8 +84,9 @@ impl Pager {
         let from_index = from as usize;
         let to_index = to as usize;
         inner.grow(from_index.max(to_index) + 1);
-        let to_ptr = inner.pages.get(to_index).get();
-        to_ptr.copy_from_nonoverlapping(inner.pages.get(from_index).get(), 1);
+        let to_ptr = inner.pages.get_mut(to_index);
+        to_ptr.copy_from_nonoverlapping(inner.pages.get(from_index), 1);
+        inner.dirty.set(to_index);
         &mut *to_ptr
     }
 
@@ -103,7 +109,8 @@ impl Inner {
     unsafe fn grow(&mut self, min_len: usize) {
         if min_len > self.pages.len() {
             self.writable.grow(min_len).unwrap();
-            self.old_pages.push(self.pages.grow(min_len).unwrap())
+            self.dirty.grow(min_len).unwrap();
+            self.old_pages.extend(self.pages.grow(min_len).unwrap())
         }
     }
 }
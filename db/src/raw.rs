@@ -1,9 +1,9 @@
 use std::{
-    fs::File,
     io,
+    mem::{replace, take},
     sync::{
         atomic::{AtomicBool, Ordering},
-        Mutex, MutexGuard,
+        Arc, Mutex, MutexGuard,
     },
 };
 
@@ -13,83 +13,248 @@ use crate::{
     allocator::AllocatorState,
     file::FileHeader,
     header::{Format, Header, HeaderPage, State},
-    mmap::{MappedBitset, MappedFile},
-    page::{Page, Pager, NULL_PAGE_NR, PAGE_SIZE},
+    history::{History, Pins},
+    mmap::MappedBitset,
+    page::{Page, PageNr, Pager, NULL_PAGE_NR, PAGE_SIZE},
+    storage::Storage,
     sync::Synchronizer,
+    view::{Readers, View},
+    wal::{LogFile, Wal},
 };
 
+pub type SnapshotListener = Box<dyn FnMut(u64) -> bool + Send>;
+
 pub struct RawDatabase {
     allocator_state: Mutex<AllocatorState>,
-    version: u64,
-    data: MappedFile,
+    listeners: Mutex<Vec<SnapshotListener>>,
+    history: Mutex<History>,
+    pins: Mutex<Pins>,
+    logged_held: Mutex<Option<Vec<PageNr>>>,
+    state: State,
+    data: Storage,
     writable: MappedBitset,
+    frozen: Vec<MappedBitset>,
+    dirty: MappedBitset,
+    wal: Option<Wal>,
     synchronizer: Synchronizer,
+    readers: Arc<Readers>,
     closing: AtomicBool,
 }
 
 impl RawDatabase {
     fn new(
-        file: File,
+        data: Storage,
+        wal: Option<LogFile>,
         format: &Format,
         setup_header: impl FnOnce(&mut Header),
     ) -> io::Result<(Self, FileHeader)> {
-        let data = MappedFile::new(file)?;
         let writable = MappedBitset::new(data.len() / PAGE_SIZE)?;
-        let pager = Pager::new(data.clone(), writable.clone());
+        let dirty = MappedBitset::new(data.len() / PAGE_SIZE)?;
+        let pager = Pager::new(data.clone(), writable.clone(), dirty.clone());
         let page = unsafe { pager.page_mut(NULL_PAGE_NR) };
         let header_page = cast_mut::<Page, HeaderPage>(page);
         setup_header(&mut header_page.header);
-        let state = header_page.header.validate(format)?;
+        let state = header_page
+            .header
+            .validate(format, &data.key_derivation())?;
+        let (wal, replay) = match wal {
+            Some(file) => {
+                let (wal, replay) = Wal::open(file, &state, &pager, data.cipher().cloned())?;
+                (Some(wal), replay)
+            }
+            None => (None, None),
+        };
+        let (allocator_state, root, logged_held) = match replay {
+            Some(replay) => (
+                replay.state.allocator,
+                replay.state.root(),
+                Some(replay.held),
+            ),
+            None => (state.allocator, state.root(), None),
+        };
         Ok((
             Self {
-                allocator_state: Mutex::new(state.allocator),
-                version: state.version,
-                synchronizer: Synchronizer::new(data.clone()),
+                allocator_state: Mutex::new(allocator_state),
+                listeners: Mutex::new(Vec::new()),
+                history: Mutex::new(History::default()),
+                pins: Mutex::new(Pins::default()),
+                logged_held: Mutex::new(logged_held),
+                state,
+                synchronizer: Synchronizer::new(data.clone(), state.version),
+                dirty: MappedBitset::new(dirty.len())?,
                 data,
                 writable,
+                frozen: Vec::new(),
+                wal,
+                readers: Arc::default(),
                 closing: AtomicBool::new(false),
             },
-            FileHeader {
-                root: state.root_nr,
-                len: state.root_len,
-            },
+            root,
         ))
     }
 
-    pub fn create(file: File, format: &Format) -> io::Result<Self> {
-        Ok(Self::new(file, format, |header| *header = Header::new(*format))?.0)
+    pub fn create(data: Storage, wal: Option<LogFile>, format: &Format) -> io::Result<Self> {
+        if let Some(wal) = &wal {
+            wal.set_len(0)?;
+        }
+        let key_derivation = data.key_derivation();
+        let database = Self::new(data, wal, format, |header| {
+            *header = Header::new(*format, key_derivation)
+        })?
+        .0;
+        database.data.flush_header()?;
+        Ok(database)
     }
 
-    pub fn open(file: File, format: &Format) -> io::Result<(Self, FileHeader)> {
-        Self::new(file, format, |_| {})
+    /// Opens the database at its last snapshot and replays the write-ahead log, if any, on top.
+    pub fn open(
+        data: Storage,
+        wal: Option<LogFile>,
+        format: &Format,
+    ) -> io::Result<(Self, FileHeader)> {
+        Self::new(data, wal, format, |_| {})
     }
 
     pub fn pager(&self) -> Pager {
-        Pager::new(self.data.clone(), self.writable.clone())
+        Pager::new(self.data.clone(), self.writable.clone(), self.dirty.clone())
     }
 
     pub fn allocator_state(&self) -> MutexGuard<'_, AllocatorState> {
         self.allocator_state.lock().unwrap()
     }
 
-    pub fn snapshot(&mut self, root: FileHeader) -> io::Result<()> {
+    pub fn history(&self) -> MutexGuard<'_, History> {
+        self.history.lock().unwrap()
+    }
+
+    pub fn pins(&self) -> MutexGuard<'_, Pins> {
+        self.pins.lock().unwrap()
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The held pages of a replayed log, which supersede those of the last snapshot.
+    pub fn take_logged_held(&self) -> Option<Vec<PageNr>> {
+        self.logged_held.lock().unwrap().take()
+    }
+
+    /// Waits for views taken before the last snapshot, whose pages the allocator is about to hand
+    /// out again.
+    pub fn snapshot(&mut self, root: FileHeader, history: FileHeader) -> io::Result<()> {
+        self.readers.wait(self.state.version);
         let allocator_state = self.allocator_state.get_mut().unwrap();
         allocator_state.swap();
-        self.version += 1;
-        let pager = Pager::new(self.data.clone(), self.writable.clone());
-        let page = unsafe { pager.page_mut(NULL_PAGE_NR) };
-        let header_page = cast_mut::<Page, HeaderPage>(page);
-        header_page.header.snapshot(State::new(
-            self.version,
-            *allocator_state,
-            root.root,
-            root.len,
-        ));
+        self.state = State::new(self.state.version + 1, *allocator_state, root, history);
+        let writable = MappedBitset::new(self.writable.len())?;
+        let mut written = take(&mut self.frozen);
+        written.push(replace(&mut self.writable, writable));
+        self.dirty = MappedBitset::new(self.dirty.len())?;
+        self.synchronizer.snapshot(self.state, written);
+        let version = self.state.version;
+        let listeners = self.listeners.get_mut().unwrap();
+        *listeners = take(listeners)
+            .into_iter()
+            .filter_map(|mut listener| {
+                if listener(version) {
+                    Some(listener)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Makes a retained state the current one under a new version. The header is durable before
+    /// returning because pages of the replaced state become reusable right away.
+    pub fn restore(&mut self, state: State) -> io::Result<()> {
+        self.readers.wait(self.state.version + 1);
+        self.state = State {
+            oldest: state.oldest,
+            ..State::new(
+                self.state.version + 1,
+                state.allocator,
+                state.root(),
+                state.history(),
+            )
+        };
+        self.synchronizer.snapshot(self.state, Vec::new());
+        self.synchronizer.wait()?;
+        *self.allocator_state.get_mut().unwrap() = state.allocator;
         self.writable = MappedBitset::new(self.writable.len())?;
-        self.synchronizer.sync();
+        self.frozen.clear();
+        self.dirty = MappedBitset::new(self.dirty.len())?;
+        Ok(())
+    }
+
+    /// Appends the pages written since the last snapshot or log to the write-ahead log, so that
+    /// the next open continues from `root` instead of the last snapshot. The log of an older
+    /// snapshot is dropped first, but only after that snapshot is known to be durable.
+    pub fn log(&mut self, root: FileHeader) -> io::Result<()> {
+        let wal = match &mut self.wal {
+            Some(wal) => wal,
+            None => return Ok(()),
+        };
+        if wal.version() != self.state.version {
+            self.synchronizer.wait()?;
+            wal.reset(self.state.version)?;
+        }
+        let dirty = &mut self.dirty;
+        let len = dirty.len();
+        dirty.grow(len)?;
+        let pages: Vec<PageNr> = (1..len)
+            .filter(|index| unsafe { dirty.get(*index) })
+            .map(|index| index as PageNr)
+            .collect();
+        let state = State {
+            allocator: *self.allocator_state.get_mut().unwrap(),
+            root_nr: root.root,
+            root_len: root.len,
+            ..self.state
+        };
+        let held = self.pins.get_mut().unwrap().held();
+        let pager = Pager::new(self.data.clone(), self.writable.clone(), self.dirty.clone());
+        wal.append(&state, &pages, held, &pager)?;
+        self.dirty = MappedBitset::new(len)?;
         Ok(())
     }
 
+    /// Takes a view of the current state. Pages written so far become read-only, but they are
+    /// still written with the next snapshot.
+    pub fn freeze(&mut self) -> io::Result<View> {
+        let writable = MappedBitset::new(self.writable.len())?;
+        self.frozen.push(replace(&mut self.writable, writable));
+        Ok(View::new(
+            self.data.clone(),
+            self.writable.clone(),
+            self.dirty.clone(),
+            self.state.version,
+            self.readers.clone(),
+        ))
+    }
+
+    /// Fails once a page could not be read.
+    pub fn check(&self) -> io::Result<()> {
+        self.data.check()
+    }
+
+    /// Blocks until all snapshots are durable.
+    pub fn wait(&self) -> io::Result<()> {
+        self.synchronizer.wait()
+    }
+
+    /// The version of the newest snapshot that a crash can no longer take away. A successful log
+    /// implies that the snapshot it was logged on top of is durable.
+    pub fn durable_version(&self) -> u64 {
+        self.synchronizer.durable_version()
+    }
+
+    pub fn on_snapshot(&self, listener: SnapshotListener) {
+        self.listeners.lock().unwrap().push(listener)
+    }
+
     pub fn close(&self) {
         self.closing.store(true, Ordering::Relaxed);
     }
@@ -0,0 +1,283 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use bytemuck::{Pod, Zeroable};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    file::{FileGuard, FileHeader},
+    lock::{Access, Lock},
+    object::{short_type_name, Field},
+    reference::DatabaseRef,
+    vec::{Len, Vec, VecGuard, VecHeader},
+};
+
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+#[repr(C)]
+pub struct Slot {
+    len: u64,
+    root: u32,
+    occupied: u32,
+}
+
+impl Slot {
+    pub fn is_occupied(&self) -> bool {
+        self.occupied != 0
+    }
+
+    pub fn read<T: DeserializeOwned>(&self, database: &DatabaseRef) -> io::Result<T> {
+        let header = FileHeader {
+            root: self.root,
+            len: self.len,
+        };
+        let reader = FileGuard::new(&header, database.read_lock());
+        bincode::deserialize_from(reader)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))
+    }
+
+    pub fn write<T: Serialize>(&mut self, value: &T, database: &DatabaseRef) -> io::Result<()> {
+        let bytes = bincode::serialize(value)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+        let mut header = FileHeader {
+            root: self.root,
+            len: self.len,
+        };
+        let mut writer = FileGuard::new(&mut header, database.lock());
+        writer.set_len(bytes.len() as u64);
+        writer.write_all(&bytes)?;
+        drop(writer);
+        *self = Slot {
+            len: header.len,
+            root: header.root,
+            occupied: 1,
+        };
+        Ok(())
+    }
+
+    pub fn free(&mut self, database: &DatabaseRef) {
+        let mut header = FileHeader {
+            root: self.root,
+            len: self.len,
+        };
+        FileGuard::new(&mut header, database.lock()).set_len(0);
+        *self = Slot::default();
+    }
+}
+
+pub struct RecordLog<T: Serialize + DeserializeOwned> {
+    slots: Vec<Slot>,
+    database: DatabaseRef,
+    _phantom: PhantomData<T>,
+}
+
+pub struct RecordLogGuard<
+    'a,
+    T: Serialize + DeserializeOwned,
+    H: Deref<Target = VecHeader> + Access,
+> {
+    slots: VecGuard<'a, Slot, H>,
+    database: &'a DatabaseRef,
+    _phantom: PhantomData<T>,
+}
+
+pub type ReadRecordLogGuard<'a, T> = RecordLogGuard<'a, T, &'a VecHeader>;
+pub type WriteRecordLogGuard<'a, T> = RecordLogGuard<'a, T, &'a mut VecHeader>;
+
+impl<T: Serialize + DeserializeOwned> RecordLog<T> {
+    pub fn new(database: DatabaseRef) -> Self {
+        Self {
+            slots: Vec::new(database.clone()),
+            database,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        Ok(Self {
+            slots: Vec::deserialize(reader, database.clone())?,
+            database,
+            _phantom: PhantomData,
+        })
+    }
+
+    pub fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.slots.serialize(writer)
+    }
+
+    pub fn read(&self) -> ReadRecordLogGuard<'_, T> {
+        RecordLogGuard {
+            slots: self.slots.read(),
+            database: &self.database,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn write(&mut self) -> WriteRecordLogGuard<'_, T> {
+        RecordLogGuard {
+            slots: self.slots.write(),
+            database: &self.database,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: Serialize + DeserializeOwned, H: Deref<Target = VecHeader> + Access>
+    RecordLogGuard<'a, T, H>
+{
+    pub fn get(&self, id: usize) -> io::Result<Option<T>> {
+        if !self.contains(id) {
+            return Ok(None);
+        }
+        self.slots[id].read(self.database).map(Some)
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        id < self.slots.len() && self.slots[id].is_occupied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<(usize, T)>> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.is_occupied())
+            .map(move |(id, slot)| slot.read(self.database).map(|value| (id, value)))
+    }
+}
+
+impl<'a, T: Serialize + DeserializeOwned, H: Deref<Target = VecHeader> + Access> Len
+    for RecordLogGuard<'a, T, H>
+{
+    fn len(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+impl<
+        'a,
+        T: Serialize + DeserializeOwned,
+        H: DerefMut<Target = VecHeader> + Access<Lock = Lock<'a>>,
+    > RecordLogGuard<'a, T, H>
+{
+    pub fn push(&mut self, value: &T) -> io::Result<usize> {
+        let mut slot = Slot::default();
+        slot.write(value, self.database)?;
+        let id = self.slots.len();
+        self.slots.push(slot);
+        Ok(id)
+    }
+
+    pub fn set(&mut self, id: usize, value: &T) -> io::Result<()> {
+        let mut slot = self.slots[id];
+        slot.write(value, self.database)?;
+        self.slots[id] = slot;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        let mut slot = self.slots[id];
+        slot.free(self.database);
+        self.slots[id] = slot;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        for id in 0..self.slots.len() {
+            self.remove(id);
+        }
+        self.slots.resize(0, Slot::default());
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Drop for RecordLog<T> {
+    fn drop(&mut self) {
+        let lock = self.database.lock();
+        if !lock.is_closing() {
+            drop(lock);
+            self.write().clear()
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Field for RecordLog<T> {
+    fn describe() -> String {
+        format!("RecordLog<{}>", short_type_name::<T>())
+    }
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        RecordLog::serialize(self, writer)
+    }
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        RecordLog::deserialize(reader, database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::{Single, TestDir},
+        Database,
+    };
+
+    use super::*;
+
+    fn text(id: usize) -> String {
+        format!("{}:{}", id, "x".repeat(id * 97))
+    }
+
+    #[test]
+    fn records_survive_reopening() {
+        let dir = TestDir::new();
+        let mut database = dir.create(RecordLog::<String>::new);
+        {
+            let mut log = database.write();
+            for id in 0..100 {
+                assert_eq!(log.push(&text(id)).unwrap(), id);
+            }
+            assert!(log.remove(3));
+            assert!(!log.remove(3));
+            log.set(5, &"short".to_owned()).unwrap();
+        }
+        database.snapshot().unwrap();
+        drop(database);
+        let database = Database::<Single<RecordLog<String>>>::open(dir.path()).unwrap();
+        let log = database.read();
+        assert_eq!(log.len(), 100);
+        assert_eq!(log.get(3).unwrap(), None);
+        assert_eq!(log.get(5).unwrap().as_deref(), Some("short"));
+        assert_eq!(log.get(99).unwrap(), Some(text(99)));
+        assert_eq!(log.get(100).unwrap(), None);
+        let ids: std::vec::Vec<_> = log.iter().map(|record| record.unwrap().0).collect();
+        let expected: std::vec::Vec<_> = (0..100).filter(|id| *id != 3).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn truncated_record_is_an_error() {
+        let dir = TestDir::new();
+        let mut database = dir.create(RecordLog::<String>::new);
+        let mut log = database.write();
+        let id = log.push(&text(20)).unwrap();
+        log.slots[id].len = 5;
+        assert_eq!(log.get(id).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(log.iter().next().unwrap().is_err());
+    }
+
+    #[test]
+    fn corrupt_record_is_an_error() {
+        let dir = TestDir::new();
+        let mut database = dir.create(RecordLog::<String>::new);
+        let mut log = database.write();
+        let id = log.push(&String::new()).unwrap();
+        let mut slot = log.slots[id];
+        slot.write(&vec![0xffu8, 0xfe], log.database).unwrap();
+        log.slots[id] = slot;
+        assert_eq!(log.get(id).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -2,11 +2,20 @@ use std::{io, sync::Arc};
 
 use atomic_refcell::AtomicRefCell;
 
-use crate::{file::FileHeader, lock::Lock, raw::RawDatabase};
+use crate::{
+    file::FileHeader,
+    history::History,
+    lock::{Lock, ReadLock},
+    raw::RawDatabase,
+    view::View,
+};
 
 #[derive(Clone)]
 pub struct DatabaseRef(Arc<AtomicRefCell<RawDatabase>>);
 
+assert_impl_all!(DatabaseRef: Send, Sync);
+assert_impl_all!(View: Send, Sync);
+
 impl DatabaseRef {
     pub(crate) fn new(raw: RawDatabase) -> Self {
         Self(Arc::new(AtomicRefCell::new(raw)))
@@ -16,7 +25,48 @@ impl DatabaseRef {
         Lock::new(self.0.borrow())
     }
 
+    pub(crate) fn read_lock(&self) -> ReadLock<'_> {
+        ReadLock::new(self.0.borrow())
+    }
+
+    /// Waits for the previous snapshot to become durable first, so that the history it refers to
+    /// is the only one besides the new history that can still be recovered.
     pub(crate) fn snapshot(&self, root: FileHeader) -> io::Result<()> {
-        self.0.borrow_mut().snapshot(root)
+        self.0.borrow().wait()?;
+        let history = History::commit(&self.lock())?;
+        self.0.borrow_mut().snapshot(root, history)
+    }
+
+    /// Panics if the database is locked, like `snapshot`.
+    pub(crate) fn view(&self) -> io::Result<View> {
+        self.0.borrow_mut().freeze()
+    }
+
+    pub(crate) fn log(&self, root: FileHeader) -> io::Result<()> {
+        self.0.borrow_mut().log(root)
+    }
+
+    pub(crate) fn check(&self) -> io::Result<()> {
+        self.0.borrow().check()
+    }
+
+    pub(crate) fn durable_version(&self) -> u64 {
+        self.0.borrow().durable_version()
+    }
+
+    pub(crate) fn restore(&self, version: u64) -> io::Result<FileHeader> {
+        let lock = self.lock();
+        let current = lock.state();
+        if current.version == version {
+            return Ok(current.root());
+        }
+        let history = lock.history();
+        let mut state = history.find(version)?;
+        state.oldest = state.oldest.max(history.oldest().unwrap_or(version));
+        drop(history);
+        drop(lock);
+        self.0.borrow_mut().restore(state)?;
+        History::load(&self.lock())?;
+        Ok(state.root())
     }
 }
@@ -0,0 +1,404 @@
+use std::{
+    cmp::Ordering,
+    io::{self, Read, Write},
+    iter::from_fn,
+    ops::{Deref, DerefMut},
+};
+
+use bytemuck::Pod;
+
+use crate::{
+    lock::{Access, Lock},
+    object::{Field, Layout},
+    page::PageNr,
+    reference::DatabaseRef,
+    tree::{Pair, Tree, TreeGuard},
+};
+
+pub type Point = [f32; 3];
+
+const AXIS_BITS: u32 = 21;
+const AXIS_OFFSET: i64 = 1 << (AXIS_BITS - 1);
+const AXIS_MAX: i64 = (1 << AXIS_BITS) - 1;
+const AXIS_MASK: u64 = 0x1249_2492_4924_9249;
+
+pub struct SpatialIndex<V: Pod + Ord> {
+    cell_size: f32,
+    cells: Tree<Pair<u64, V>, Point>,
+    points: Tree<V, Point>,
+}
+
+pub struct SpatialIndexGuard<'a, R: Deref<Target = PageNr> + Access, V: Pod + Ord> {
+    cell_size: f32,
+    cells: TreeGuard<'a, R, Pair<u64, V>, Point>,
+    points: TreeGuard<'a, R, V, Point>,
+}
+
+pub type ReadSpatialIndexGuard<'a, V> = SpatialIndexGuard<'a, &'a PageNr, V>;
+pub type WriteSpatialIndexGuard<'a, V> = SpatialIndexGuard<'a, &'a mut PageNr, V>;
+
+impl<V: Pod + Ord> SpatialIndex<V> {
+    pub fn new(database: DatabaseRef, cell_size: f32) -> Self {
+        assert!(cell_size > 0.0);
+        Self {
+            cell_size,
+            cells: Tree::new(database.clone()),
+            points: Tree::new(database),
+        }
+    }
+
+    pub fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        let cell_size = f32::from_ne_bytes(bytes);
+        let cells = Tree::deserialize(reader, database.clone())?;
+        let points = Tree::deserialize(reader, database)?;
+        Ok(Self {
+            cell_size,
+            cells,
+            points,
+        })
+    }
+
+    pub fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&self.cell_size.to_ne_bytes())?;
+        self.cells.serialize(writer)?;
+        self.points.serialize(writer)?;
+        Ok(())
+    }
+
+    pub fn read(&self) -> ReadSpatialIndexGuard<'_, V> {
+        SpatialIndexGuard {
+            cell_size: self.cell_size,
+            cells: self.cells.read(),
+            points: self.points.read(),
+        }
+    }
+
+    pub fn write(&mut self) -> WriteSpatialIndexGuard<'_, V> {
+        SpatialIndexGuard {
+            cell_size: self.cell_size,
+            cells: self.cells.write(),
+            points: self.points.write(),
+        }
+    }
+}
+
+impl<'a, R: Deref<Target = PageNr> + Access, V: Pod + Ord> SpatialIndexGuard<'a, R, V> {
+    pub fn get(&self, id: &V) -> Option<&Point> {
+        self.points.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (V, Point)> + '_ {
+        self.points.iter().map(|(id, point)| (*id, *point))
+    }
+
+    pub fn within_box(&self, min: Point, max: Point) -> impl Iterator<Item = (V, Point)> + '_ {
+        let is_valid = (0..3).all(|i| min[i] <= max[i]);
+        let (min_cell, max_cell) = (self.cell(min), self.cell(max));
+        let (min_code, max_code) = (encode(min_cell), encode(max_cell));
+        let mut iter = self.cells.seek(move |pair| pair.key() < min_code);
+        from_fn(move || loop {
+            if !is_valid {
+                return None;
+            }
+            let (pair, point) = iter.next()?;
+            let code = pair.key();
+            if code > max_code {
+                return None;
+            }
+            if contains(min_cell, max_cell, decode(code)) {
+                if contains(min, max, *point) {
+                    return Some((pair.value(), *point));
+                }
+            } else {
+                let next = bigmin(code, min_code, max_code);
+                iter = self.cells.seek(move |pair| pair.key() < next);
+            }
+        })
+    }
+
+    pub fn within_sphere(
+        &self,
+        center: Point,
+        radius: f32,
+    ) -> impl Iterator<Item = (V, Point)> + '_ {
+        let min = [center[0] - radius, center[1] - radius, center[2] - radius];
+        let max = [center[0] + radius, center[1] + radius, center[2] + radius];
+        self.within_box(min, max)
+            .filter(move |(_, point)| distance_squared(center, *point) <= radius * radius)
+    }
+
+    pub fn nearest(&self, center: Point, k: usize) -> Vec<(V, Point)> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let limit = self.cell_size * (1 << AXIS_BITS) as f32 * 2.0;
+        let mut radius = self.cell_size;
+        let mut found = loop {
+            if radius > limit {
+                break self.iter().collect::<Vec<_>>();
+            }
+            let found: Vec<_> = self.within_sphere(center, radius).collect();
+            if found.len() >= k {
+                break found;
+            }
+            radius *= 2.0;
+        };
+        found.sort_by(|(_, a), (_, b)| {
+            distance_squared(center, *a)
+                .partial_cmp(&distance_squared(center, *b))
+                .unwrap_or(Ordering::Equal)
+        });
+        found.truncate(k);
+        found
+    }
+
+    fn cell(&self, point: Point) -> [u64; 3] {
+        let mut cell = [0; 3];
+        for (cell, value) in cell.iter_mut().zip(point.iter()) {
+            let index = (value / self.cell_size).floor() as i64 + AXIS_OFFSET;
+            *cell = index.clamp(0, AXIS_MAX) as u64;
+        }
+        cell
+    }
+}
+
+impl<'a, R: DerefMut<Target = PageNr> + Access<Lock = Lock<'a>>, V: Pod + Ord>
+    SpatialIndexGuard<'a, R, V>
+{
+    pub fn insert(&mut self, id: V, point: Point) -> Option<Point> {
+        let old = self.points.insert(id, point);
+        if let Some(old) = old {
+            self.cells.remove(&Pair::new(encode(self.cell(old)), id));
+        }
+        self.cells
+            .insert(Pair::new(encode(self.cell(point)), id), point);
+        old
+    }
+
+    pub fn remove(&mut self, id: &V) -> Option<Point> {
+        let old = self.points.remove(id)?;
+        self.cells.remove(&Pair::new(encode(self.cell(old)), *id));
+        Some(old)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.points.clear();
+    }
+}
+
+fn contains<T: PartialOrd + Copy>(min: [T; 3], max: [T; 3], value: [T; 3]) -> bool {
+    (0..3).all(|i| min[i] <= value[i] && value[i] <= max[i])
+}
+
+fn distance_squared(a: Point, b: Point) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+fn spread(value: u64) -> u64 {
+    let mut value = value & AXIS_MAX as u64;
+    value = (value | value << 32) & 0x001f_0000_0000_ffff;
+    value = (value | value << 16) & 0x001f_0000_ff00_00ff;
+    value = (value | value << 8) & 0x100f_00f0_0f00_f00f;
+    value = (value | value << 4) & 0x10c3_0c30_c30c_30c3;
+    value = (value | value << 2) & AXIS_MASK;
+    value
+}
+
+fn compact(value: u64) -> u64 {
+    let mut value = value & AXIS_MASK;
+    value = (value | value >> 2) & 0x10c3_0c30_c30c_30c3;
+    value = (value | value >> 4) & 0x100f_00f0_0f00_f00f;
+    value = (value | value >> 8) & 0x001f_0000_ff00_00ff;
+    value = (value | value >> 16) & 0x001f_0000_0000_ffff;
+    value = (value | value >> 32) & AXIS_MAX as u64;
+    value
+}
+
+fn encode(cell: [u64; 3]) -> u64 {
+    spread(cell[0]) | spread(cell[1]) << 1 | spread(cell[2]) << 2
+}
+
+fn decode(code: u64) -> [u64; 3] {
+    [compact(code), compact(code >> 1), compact(code >> 2)]
+}
+
+/// Returns the smallest code greater than `code` whose cell lies within the box spanned by
+/// `min` and `max` (Tropf and Herzog, 1981).
+fn bigmin(code: u64, mut min: u64, mut max: u64) -> u64 {
+    let mut result = max;
+    for bit in (0..3 * AXIS_BITS).rev() {
+        let mask = 1 << bit;
+        let below = (AXIS_MASK << (bit % 3)) & ((mask << 1) - 1);
+        match (code & mask != 0, min & mask != 0, max & mask != 0) {
+            (false, false, true) => {
+                result = (min & !below) | mask;
+                max = (max & !below) | (below & !mask);
+            }
+            (false, true, true) => return min,
+            (true, false, false) => return result,
+            (true, false, true) => min = (min & !below) | mask,
+            _ => {}
+        }
+    }
+    result
+}
+
+impl<V: Layout + Ord> Field for SpatialIndex<V> {
+    fn describe() -> String {
+        format!("SpatialIndex<{}>", V::describe())
+    }
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        SpatialIndex::serialize(self, writer)
+    }
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        SpatialIndex::deserialize(reader, database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::testing::TestDir;
+
+    use super::*;
+
+    fn random_point(rng: &mut StdRng, extent: f32) -> Point {
+        [
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+            rng.gen_range(-extent..extent),
+        ]
+    }
+
+    fn sorted(mut found: Vec<(u64, Point)>) -> Vec<u64> {
+        let mut ids: Vec<_> = found.drain(..).map(|(id, _)| id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let cell = [
+                rng.gen_range(0..=AXIS_MAX as u64),
+                rng.gen_range(0..=AXIS_MAX as u64),
+                rng.gen_range(0..=AXIS_MAX as u64),
+            ];
+            assert_eq!(decode(encode(cell)), cell);
+        }
+    }
+
+    /// Compares `bigmin` with the smallest greater code found by scanning all cells of the box.
+    #[test]
+    fn bigmin_matches_scan() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..200 {
+            let mut min = [0; 3];
+            let mut max = [0; 3];
+            for i in 0..3 {
+                let a = rng.gen_range(0..16);
+                let b = rng.gen_range(0..16);
+                min[i] = a.min(b);
+                max[i] = a.max(b);
+            }
+            let mut codes = Vec::new();
+            for x in min[0]..=max[0] {
+                for y in min[1]..=max[1] {
+                    for z in min[2]..=max[2] {
+                        codes.push(encode([x, y, z]));
+                    }
+                }
+            }
+            codes.sort_unstable();
+            let (min_code, max_code) = (encode(min), encode(max));
+            for code in min_code..max_code {
+                if contains(min, max, decode(code)) {
+                    continue;
+                }
+                let expected = codes.iter().find(|other| **other > code).unwrap();
+                assert_eq!(bigmin(code, min_code, max_code), *expected);
+            }
+        }
+    }
+
+    #[test]
+    fn queries_match_scan() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let dir = TestDir::new();
+        let mut index = dir.create(|database| SpatialIndex::new(database, 2.0));
+        let mut points = Vec::new();
+        for id in 0..2000u64 {
+            let point = random_point(&mut rng, 50.0);
+            index.write().insert(id, point);
+            points.push(point);
+        }
+        for id in 0..200u64 {
+            let point = random_point(&mut rng, 50.0);
+            assert_eq!(index.write().insert(id, point), Some(points[id as usize]));
+            points[id as usize] = point;
+        }
+        let index = index.read();
+        for _ in 0..100 {
+            let a = random_point(&mut rng, 60.0);
+            let b = random_point(&mut rng, 60.0);
+            let min = [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])];
+            let max = [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2])];
+            let expected: Vec<_> = (0..points.len() as u64)
+                .filter(|id| contains(min, max, points[*id as usize]))
+                .collect();
+            assert_eq!(sorted(index.within_box(min, max).collect()), expected);
+
+            let center = random_point(&mut rng, 60.0);
+            let radius = rng.gen_range(0.0..30.0);
+            let expected: Vec<_> = (0..points.len() as u64)
+                .filter(|id| distance_squared(center, points[*id as usize]) <= radius * radius)
+                .collect();
+            assert_eq!(
+                sorted(index.within_sphere(center, radius).collect()),
+                expected
+            );
+        }
+        assert_eq!(index.within_box([1.0; 3], [0.0; 3]).count(), 0);
+    }
+
+    #[test]
+    fn nearest_matches_scan() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let dir = TestDir::new();
+        let mut index = dir.create(|database| SpatialIndex::new(database, 1.0));
+        let mut points = Vec::new();
+        for id in 0..1000u64 {
+            let point = random_point(&mut rng, 100.0);
+            index.write().insert(id, point);
+            points.push(point);
+        }
+        for id in (0..1000u64).step_by(3) {
+            assert_eq!(index.write().remove(&id), Some(points[id as usize]));
+        }
+        let index = index.read();
+        for k in [0, 1, 7, 50, 1000].iter().copied() {
+            let center = random_point(&mut rng, 150.0);
+            let mut expected: Vec<_> = points
+                .iter()
+                .enumerate()
+                .filter(|(id, _)| id % 3 != 0)
+                .map(|(_, point)| distance_squared(center, *point))
+                .collect();
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            expected.truncate(k);
+            let found: Vec<_> = index
+                .nearest(center, k)
+                .into_iter()
+                .map(|(_, point)| distance_squared(center, point))
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{io, sync::Arc};
+
+use bytemuck::Zeroable;
+
+use crate::{
+    crypt::{Cipher, EncryptedFile, EncryptedPages, KeyDerivation},
+    mmap::{MappedBitset, MappedFile, MappedVec, Mapping},
+    page::Page,
+};
+
+/// Where the pages of a database live.
+#[derive(Clone)]
+pub enum Storage {
+    /// The file is mapped and the operating system writes pages back.
+    Mapped(MappedFile),
+    /// Pages are held in memory and encrypted when flushed.
+    Encrypted(EncryptedFile),
+}
+
+impl Storage {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Mapped(file) => file.len(),
+            Self::Encrypted(file) => file.len(),
+        }
+    }
+
+    pub fn key_derivation(&self) -> KeyDerivation {
+        match self {
+            Self::Mapped(_) => KeyDerivation::zeroed(),
+            Self::Encrypted(file) => file.key_derivation(),
+        }
+    }
+
+    pub fn cipher(&self) -> Option<&Cipher> {
+        match self {
+            Self::Mapped(_) => None,
+            Self::Encrypted(file) => Some(file.cipher()),
+        }
+    }
+
+    /// Hands the pages written for the snapshot with the given version to the file.
+    pub fn flush(&self, written: &mut MappedBitset, version: u64) -> io::Result<()> {
+        match self {
+            Self::Mapped(_) => Ok(()),
+            Self::Encrypted(file) => file.flush(written, version),
+        }
+    }
+
+    /// Hands the versions of all pages as of the snapshot with the given version to the file.
+    pub fn flush_versions(&self, version: u64) -> io::Result<()> {
+        match self {
+            Self::Mapped(_) => Ok(()),
+            Self::Encrypted(file) => file.flush_versions(version),
+        }
+    }
+
+    pub fn flush_header(&self) -> io::Result<()> {
+        match self {
+            Self::Mapped(_) => Ok(()),
+            Self::Encrypted(file) => file.flush_header(),
+        }
+    }
+
+    /// Fails if a page could not be read, see `EncryptedFile::check`.
+    pub fn check(&self) -> io::Result<()> {
+        match self {
+            Self::Mapped(_) => Ok(()),
+            Self::Encrypted(file) => file.check(),
+        }
+    }
+
+    /// Waits until everything handed to the file is durable.
+    pub fn sync(&self) -> io::Result<()> {
+        match self {
+            Self::Mapped(file) => file.sync(),
+            Self::Encrypted(file) => file.sync(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Pages {
+    Mapped(MappedVec<Page>),
+    Encrypted(EncryptedPages),
+}
+
+impl Pages {
+    pub fn new(storage: Storage) -> io::Result<Self> {
+        Ok(match storage {
+            Storage::Mapped(file) => Self::Mapped(MappedVec::new(file)?),
+            Storage::Encrypted(file) => Self::Encrypted(EncryptedPages::new(file)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Mapped(pages) => pages.len(),
+            Self::Encrypted(pages) => pages.len(),
+        }
+    }
+
+    /// Returns the previous mapping, which has to outlive all references into it.
+    pub fn grow(&mut self, min_len: usize) -> io::Result<Option<Arc<Mapping>>> {
+        match self {
+            Self::Mapped(pages) => pages.grow(min_len).map(Some),
+            Self::Encrypted(pages) => {
+                pages.grow(min_len);
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn get(&self, index: usize) -> *mut Page {
+        match self {
+            Self::Mapped(pages) => pages.get(index).get(),
+            Self::Encrypted(pages) => pages.get(index),
+        }
+    }
+
+    /// Like `get`, for pages that are about to be overwritten.
+    pub fn get_mut(&self, index: usize) -> *mut Page {
+        match self {
+            Self::Mapped(pages) => pages.get(index).get(),
+            Self::Encrypted(pages) => pages.get_mut(index),
+        }
+    }
+}
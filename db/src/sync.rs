@@ -1,62 +1,110 @@
 use std::{
+    io,
     sync::{
-        atomic::{AtomicIsize, Ordering},
-        Arc, Barrier,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
+        Arc,
     },
     thread::{Builder, JoinHandle},
 };
 
 use log::error;
 
-use crate::mmap::MappedFile;
+use crate::{
+    header::{HeaderPage, State},
+    mmap::MappedBitset,
+    storage::Storage,
+};
 
+/// Makes snapshots durable in the background. The header only refers to a snapshot once all of
+/// its pages are durable, so a power failure at any point leaves a header with a complete
+/// snapshot behind.
 pub struct Synchronizer {
-    state: Arc<State>,
+    sender: Option<SyncSender<Job>>,
     handle: Option<JoinHandle<()>>,
+    durable: Arc<AtomicU64>,
+}
+
+enum Job {
+    Snapshot(State, Vec<MappedBitset>),
+    Wait(Sender<io::Result<()>>),
 }
 
 impl Synchronizer {
-    pub fn new(data: MappedFile) -> Self {
-        let state = Arc::new(State {
-            barrier: Barrier::new(2),
-            pending: AtomicIsize::new(0),
-        });
-        let handle = Some(Self::spawn(state.clone(), data));
-        Self { state, handle }
+    /// Starts with the snapshot with the given version, which is already durable.
+    pub fn new(data: Storage, version: u64) -> Self {
+        let (sender, receiver) = sync_channel(0);
+        let durable = Arc::new(AtomicU64::new(version));
+        let handle = Some(Self::spawn(receiver, data, durable.clone()));
+        Self {
+            sender: Some(sender),
+            handle,
+            durable,
+        }
+    }
+
+    /// Writes `state` to the header once everything written so far is durable. Storage that is
+    /// not mapped gets the `written` pages handed over first. Blocks until the previous snapshot
+    /// is durable.
+    pub fn snapshot(&self, state: State, written: Vec<MappedBitset>) {
+        self.send(Job::Snapshot(state, written))
+    }
+
+    /// Blocks until all snapshots are durable. Fails if a snapshot could not be made durable
+    /// since the last wait.
+    pub fn wait(&self) -> io::Result<()> {
+        let (sender, receiver) = channel();
+        self.send(Job::Wait(sender));
+        receiver.recv().unwrap()
     }
 
-    pub fn sync(&self) {
-        self.state.pending.fetch_add(1, Ordering::SeqCst);
-        self.state.barrier.wait();
+    /// The version of the newest snapshot that is durable.
+    pub fn durable_version(&self) -> u64 {
+        self.durable.load(Ordering::Acquire)
     }
 
-    fn spawn(state: Arc<State>, data: MappedFile) -> JoinHandle<()> {
+    fn send(&self, job: Job) {
+        self.sender.as_ref().unwrap().send(job).unwrap()
+    }
+
+    fn spawn(receiver: Receiver<Job>, data: Storage, durable: Arc<AtomicU64>) -> JoinHandle<()> {
         Builder::new()
             .name("database synchronization thread".into())
-            .spawn(move || loop {
-                state.barrier.wait();
-                if state.pending.fetch_sub(1, Ordering::SeqCst) == 0 {
-                    return;
-                }
-                match data.sync() {
-                    Ok(_) => {}
-                    Err(error) => {
-                        error!("{}", error);
+            .spawn(move || {
+                let mut failure = None;
+                for job in receiver {
+                    match job {
+                        Job::Snapshot(state, written) => match Self::write(&data, state, written) {
+                            Ok(()) => durable.store(state.version, Ordering::Release),
+                            Err(error) => {
+                                error!("{}", error);
+                                failure = Some(error);
+                            }
+                        },
+                        Job::Wait(sender) => {
+                            let _ = sender.send(failure.take().map_or(Ok(()), Err));
+                        }
                     }
                 }
             })
             .unwrap()
     }
+
+    fn write(data: &Storage, state: State, written: Vec<MappedBitset>) -> io::Result<()> {
+        for mut written in written {
+            data.flush(&mut written, state.version)?;
+        }
+        data.flush_versions(state.version)?;
+        data.sync()?;
+        HeaderPage::snapshot(data, state)?;
+        data.flush_header()?;
+        data.sync()
+    }
 }
 
 impl Drop for Synchronizer {
     fn drop(&mut self) {
-        self.state.barrier.wait();
+        self.sender.take();
         self.handle.take().unwrap().join().unwrap();
     }
 }
-
-struct State {
-    barrier: Barrier,
-    pending: AtomicIsize,
-}
@@ -0,0 +1,62 @@
+use std::{
+    io::{self, Read, Write},
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+};
+
+use tempfile::TempDir;
+
+use crate::{
+    database::Database, header::Format, object::Field, object::Object, reference::DatabaseRef,
+};
+
+/// A database whose content is a single field, for testing containers.
+pub struct Single<T>(pub T);
+
+impl<T: Field> Object for Single<T> {
+    fn format() -> Format {
+        [0; 256]
+    }
+
+    fn serialize(&mut self, mut writer: impl Write) -> io::Result<()> {
+        self.0.serialize(&mut writer)
+    }
+
+    fn deserialize(mut reader: impl Read, database: DatabaseRef) -> io::Result<Self> {
+        T::deserialize(&mut reader, database).map(Self)
+    }
+}
+
+impl<T> Deref for Single<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Single<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// A temporary directory that is removed with all databases in it when dropped.
+pub struct TestDir(TempDir);
+
+impl TestDir {
+    pub fn new() -> Self {
+        Self(tempfile::tempdir().unwrap())
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.0.path().join("test.db")
+    }
+
+    pub fn create<T: Field>(
+        &self,
+        constructor: impl FnOnce(DatabaseRef) -> T,
+    ) -> Database<Single<T>> {
+        Database::create(self.path(), |database| Single(constructor(database))).unwrap()
+    }
+}
@@ -4,11 +4,11 @@ use bytemuck::{cast_slice, cast_slice_mut, Pod, TransparentWrapper};
 
 use crate::{
     lock::Lock,
-    page::{Page, PageNr, PAGE_SIZE},
+    page::{Page, PageNr},
     tree::node::NodeRef,
 };
 
-use super::node::{allocate, node, NodePage};
+use super::node::{allocate, node, order, second_offset, NodePage};
 
 #[derive(Clone, Copy, TransparentWrapper)]
 #[repr(transparent)]
@@ -20,21 +20,7 @@ pub struct Branch<K> {
 
 impl<K> Branch<K> {
     const fn key_order() -> usize {
-        let child_align = 4;
-        let key_size = size_of::<K>();
-        let sum_size = key_size + 4;
-        let order = (PAGE_SIZE - 6) / sum_size;
-        let mismatch = (order * key_size) % child_align;
-        if mismatch == 0 {
-            order
-        } else {
-            let offset = child_align - mismatch;
-            if order * sum_size + offset <= PAGE_SIZE - 6 {
-                order
-            } else {
-                order - 1
-            }
-        }
+        order(size_of::<K>(), size_of::<PageNr>(), 4, 6)
     }
 
     pub const fn order() -> usize {
@@ -42,15 +28,7 @@ impl<K> Branch<K> {
     }
 
     const fn child_offset() -> usize {
-        let key_size = size_of::<K>();
-        let child_align = 4;
-        let key_order = Self::key_order();
-        let mismatch = (key_order * key_size) % child_align;
-        if mismatch == 0 {
-            key_order * key_size
-        } else {
-            key_order * key_size + child_align - mismatch
-        }
+        second_offset(size_of::<K>(), 4, Self::key_order())
     }
 }
 
@@ -60,6 +38,11 @@ impl<K: Pod + Ord> Branch<K> {
         (page_nr, Self::wrap_mut(page))
     }
 
+    pub fn set_first_child(&mut self, child: PageNr) {
+        self.page.set_len(1);
+        self.children_mut()[0] = child;
+    }
+
     pub fn keys(&self) -> &[K] {
         cast_slice(&self.page[0..(self.page.len() - 1) * size_of::<K>()])
     }
@@ -151,7 +134,7 @@ impl<K: Pod + Ord> Branch<K> {
     pub unsafe fn shift_left<'a, V: Pod>(&mut self, right: &mut Self, lock: &'a Lock) -> K {
         let left_key = right.left_key::<V>(lock);
         let key = right.keys()[0];
-        self.insert_right(self.page.len(), left_key, right.children()[0]);
+        self.insert_right(self.page.len() - 1, left_key, right.children()[0]);
         right.delete_left(0);
         key
     }
@@ -159,9 +142,9 @@ impl<K: Pod + Ord> Branch<K> {
     pub unsafe fn shift_right<'a, V: Pod>(&mut self, right: &mut Self, lock: &'a Lock) -> K {
         let left_key = right.left_key::<V>(lock);
         let index = self.page.len() - 1;
-        let key = self.keys()[index];
-        right.insert_left(0, left_key, self.children()[index - 1]);
-        self.delete_right(index);
+        let key = self.keys()[index - 1];
+        right.insert_left(0, left_key, self.children()[index]);
+        self.delete_right(index - 1);
         key
     }
 
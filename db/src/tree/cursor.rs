@@ -6,7 +6,7 @@ use std::{
 use bytemuck::{Pod, TransparentWrapper};
 
 use crate::{
-    lock::Lock,
+    lock::{Lock, ReadLock},
     page::{Page, PageNr, NULL_PAGE_NR},
     tree::node::NodeRef,
 };
@@ -31,7 +31,7 @@ struct Entry {
 }
 
 impl<'a, R: Deref<Target = PageNr>, K: Pod + Ord, V: Pod> Cursor<'a, R, K, V> {
-    pub fn new(root: R, key: &'a K, lock: &Lock) -> Self {
+    pub fn new(root: R, key: &'a K, lock: &ReadLock) -> Self {
         let mut entries = Vec::new();
         let mut page_nr = *root;
         let key = loop {
@@ -66,7 +66,7 @@ impl<'a, R: Deref<Target = PageNr>, K: Pod + Ord, V: Pod> Cursor<'a, R, K, V> {
         }
     }
 
-    pub fn value<'b: 'a>(&self, lock: &'b Lock) -> Option<&'b V> {
+    pub fn value<'b: 'a>(&self, lock: &'b ReadLock) -> Option<&'b V> {
         if self.key.is_none() {
             Some(&self.leaf(lock).values()[self.entries[0].index])
         } else {
@@ -90,15 +90,15 @@ impl<'a, R: Deref<Target = PageNr>, K: Pod + Ord, V: Pod> Cursor<'a, R, K, V> {
         self.height() - 1
     }
 
-    fn is_valid(&self, lock: &Lock) -> bool {
+    fn is_valid(&self, lock: &ReadLock) -> bool {
         !self.is_empty() && self.entries[0].index < self.page(0, lock).len()
     }
 
-    fn page<'b: 'a>(&self, level: usize, lock: &'b Lock) -> &'b Page {
+    fn page<'b: 'a>(&self, level: usize, lock: &'b ReadLock) -> &'b Page {
         unsafe { lock.page(self.entries[level].page_nr) }
     }
 
-    fn leaf<'b: 'a>(&self, lock: &'b Lock) -> &'b Leaf<K, V> {
+    fn leaf<'b: 'a>(&self, lock: &'b ReadLock) -> &'b Leaf<K, V> {
         Leaf::wrap_ref(self.page(0, lock))
     }
 }
@@ -166,7 +166,7 @@ impl<'a, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod> Cursor<'a, R, K, V>
                 leaf.split(other);
                 let len = leaf.len();
                 self.parent_insert(0, other.keys()[0], page_nr, lock);
-                if index < len {
+                if index <= len {
                     leaf.insert(index, *key, value);
                 } else {
                     let index = index - len;
@@ -186,7 +186,7 @@ impl<'a, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod> Cursor<'a, R, K, V>
     unsafe fn parent_insert(&mut self, level: usize, key: K, value: PageNr, lock: &'a Lock) {
         if level == self.root_level() {
             let (page_nr, branch) = Branch::allocate(lock);
-            branch.children_mut()[0] = *self.root;
+            branch.set_first_child(*self.root);
             branch.insert_right(0, key, value);
             *self.root = page_nr;
             self.entries.push(Entry { page_nr, index: 0 });
@@ -279,33 +279,44 @@ impl<'a, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod> Cursor<'a, R, K, V>
                 let left_leaf = Leaf::<K, V>::wrap_mut(lock.page_mut(child));
                 let child = *child;
                 left_leaf.merge(self.leaf_mut(lock));
-                branch.delete_left(index);
+                branch.delete_right(index - 1);
+                lock.deallocate(self.entries[level].page_nr);
+                self.entries[level] = Entry {
+                    page_nr: child,
+                    index: 0,
+                };
                 self.entries[level + 1].index -= 1;
-                lock.deallocate(child);
             } else {
                 let left_branch = Branch::wrap_mut(lock.page_mut(child));
                 let child = *child;
                 left_branch.merge::<V>(self.branch_mut(level, lock), lock);
-                branch.delete_left(index);
+                branch.delete_right(index - 1);
+                lock.deallocate(self.entries[level].page_nr);
+                self.entries[level] = Entry {
+                    page_nr: child,
+                    index: 0,
+                };
                 self.entries[level + 1].index -= 1;
-                lock.deallocate(child);
             }
         } else {
             let child = &mut branch.children_mut()[index + 1];
-            if level == 0 {
+            let right_nr = if level == 0 {
                 let right_leaf = Leaf::wrap_mut(lock.page_mut(child));
                 self.leaf_mut(lock).merge(right_leaf);
+                *child
             } else {
                 let right_branch = Branch::wrap_mut(lock.page_mut(child));
                 self.branch_mut(level, lock).merge::<V>(right_branch, lock);
-            }
+                *child
+            };
             branch.delete_right(index);
-            lock.deallocate(self.entries[level + 1].page_nr);
+            lock.deallocate(right_nr);
         }
         if level + 1 == self.root_level() && branch.len() == 1 {
             *self.root = branch.children()[0];
+            lock.deallocate(self.entries[level + 1].page_nr);
             self.entries.pop();
-        } else if branch.len() * 2 < Branch::<K>::order() {
+        } else if level + 1 < self.root_level() && branch.len() * 2 < Branch::<K>::order() {
             self.rebalance(level + 1, lock);
         }
     }
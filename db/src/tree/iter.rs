@@ -0,0 +1,91 @@
+use std::marker::PhantomData;
+
+use bytemuck::{Pod, TransparentWrapper};
+
+use crate::{
+    lock::ReadLock,
+    page::{PageNr, NULL_PAGE_NR},
+    tree::node::NodeRef,
+};
+
+use super::{
+    leaf::Leaf,
+    node::{node, NodePage},
+};
+
+pub struct Iter<'a, K: Pod + Ord, V: Pod> {
+    entries: Vec<Entry>,
+    lock: &'a ReadLock<'a>,
+    _phantom_key: PhantomData<K>,
+    _phantom_value: PhantomData<V>,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    page_nr: PageNr,
+    index: usize,
+}
+
+impl<'a, K: Pod + Ord, V: Pod> Iter<'a, K, V> {
+    pub(super) fn new(root: PageNr, lock: &'a ReadLock<'a>, is_below: impl Fn(&K) -> bool) -> Self {
+        let mut entries = Vec::new();
+        let mut page_nr = root;
+        while page_nr != NULL_PAGE_NR {
+            match node::<K, V>(unsafe { lock.page(page_nr) }) {
+                NodeRef::Branch(branch) => {
+                    let index = branch.keys().partition_point(&is_below);
+                    entries.push(Entry { page_nr, index });
+                    page_nr = branch.children()[index];
+                }
+                NodeRef::Leaf(leaf) => {
+                    let index = leaf.keys().partition_point(&is_below);
+                    entries.push(Entry { page_nr, index });
+                    page_nr = NULL_PAGE_NR;
+                }
+            }
+        }
+        let mut iter = Self {
+            entries,
+            lock,
+            _phantom_key: PhantomData,
+            _phantom_value: PhantomData,
+        };
+        iter.skip_exhausted();
+        iter
+    }
+
+    fn skip_exhausted(&mut self) {
+        while let Some(entry) = self.entries.last() {
+            let page = unsafe { self.lock.page(entry.page_nr) };
+            if entry.index < page.len() {
+                break;
+            }
+            self.entries.pop();
+            if let Some(parent) = self.entries.last_mut() {
+                parent.index += 1;
+            }
+        }
+        while let Some(entry) = self.entries.last() {
+            match node::<K, V>(unsafe { self.lock.page(entry.page_nr) }) {
+                NodeRef::Branch(branch) => {
+                    let page_nr = branch.children()[entry.index];
+                    self.entries.push(Entry { page_nr, index: 0 });
+                }
+                NodeRef::Leaf(_) => break,
+            }
+        }
+    }
+}
+
+impl<'a, K: Pod + Ord, V: Pod> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.entries.last_mut()?;
+        let leaf = Leaf::<K, V>::wrap_ref(unsafe { self.lock.page(entry.page_nr) });
+        let index = entry.index;
+        entry.index += 1;
+        self.skip_exhausted();
+        Some((&leaf.keys()[index], &leaf.values()[index]))
+    }
+}
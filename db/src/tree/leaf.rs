@@ -1,16 +1,18 @@
 use std::{
     marker::PhantomData,
     mem::{align_of, size_of},
+    ptr::NonNull,
+    slice,
 };
 
 use bytemuck::{cast_slice, cast_slice_mut, Pod, TransparentWrapper};
 
 use crate::{
     lock::Lock,
-    page::{Page, PageNr, PAGE_SIZE},
+    page::{Page, PageNr},
 };
 
-use super::node::{allocate, NodePage};
+use super::node::{allocate, order, second_offset, NodePage};
 
 #[derive(Clone, Copy, TransparentWrapper)]
 #[repr(transparent)]
@@ -23,33 +25,11 @@ pub struct Leaf<K, V> {
 
 impl<K, V> Leaf<K, V> {
     pub const fn order() -> usize {
-        let value_align = align_of::<V>();
-        let key_size = size_of::<K>();
-        let sum_size = key_size + size_of::<V>();
-        let order = (PAGE_SIZE - 2) / sum_size;
-        let mismatch = (order * key_size) % value_align;
-        if mismatch == 0 {
-            order
-        } else {
-            let offset = value_align - mismatch;
-            if order * sum_size + offset <= PAGE_SIZE - 2 {
-                order
-            } else {
-                order - 1
-            }
-        }
+        order(size_of::<K>(), size_of::<V>(), align_of::<V>(), 2)
     }
 
     const fn value_offset() -> usize {
-        let key_size = size_of::<K>();
-        let value_align = align_of::<V>();
-        let order = Self::order();
-        let mismatch = (order * key_size) % value_align;
-        if mismatch == 0 {
-            order * key_size
-        } else {
-            order * key_size + value_align - mismatch
-        }
+        second_offset(size_of::<K>(), align_of::<V>(), Self::order())
     }
 }
 
@@ -77,11 +57,17 @@ impl<K: Pod + Ord, V: Pod> Leaf<K, V> {
     }
 
     pub fn values(&self) -> &[V] {
+        if size_of::<V>() == 0 {
+            return unsafe { slice::from_raw_parts(NonNull::dangling().as_ptr(), self.len()) };
+        }
         let offset = Self::value_offset();
         cast_slice(&self.page[offset..offset + self.page.len() * size_of::<V>()])
     }
 
     pub fn values_mut(&mut self) -> &mut [V] {
+        if size_of::<V>() == 0 {
+            return unsafe { slice::from_raw_parts_mut(NonNull::dangling().as_ptr(), self.len()) };
+        }
         let offset = Self::value_offset();
         let len = self.page.len();
         cast_slice_mut(&mut self.page[offset..offset + len * size_of::<V>()])
@@ -116,7 +102,7 @@ impl<K: Pod + Ord, V: Pod> Leaf<K, V> {
     }
 
     pub unsafe fn shift_left(&mut self, right: &mut Self) -> K {
-        self.insert(self.page.len() - 1, right.keys()[0], right.values()[0]);
+        self.insert(self.page.len(), right.keys()[0], right.values()[0]);
         right.delete(0);
         right.keys()[0]
     }
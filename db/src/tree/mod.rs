@@ -1,29 +1,49 @@
 mod branch;
 mod cursor;
+mod iter;
 mod leaf;
-mod node;
+mod multi;
+pub(crate) mod node;
+#[cfg(test)]
+mod tests;
 
 use std::{
     io::{self, Read, Write},
     marker::PhantomData,
-    ops::{Deref, DerefMut},
+    mem,
+    ops::{Bound, Deref, DerefMut, RangeBounds},
+    sync::mpsc::Receiver,
 };
 
 use bytemuck::Pod;
 
 use crate::{
-    lock::Lock,
+    feed::{Event, Feed, Pending, TreeChange},
+    lock::{Access, AsReadLock, Lock},
+    object::{Field, Layout},
     page::{PageNr, NULL_PAGE_NR},
     reference::DatabaseRef,
+    view::View,
 };
 
 use self::{cursor::Cursor, node::NodeRef};
 
+pub use iter::Iter;
+pub(crate) use multi::Pair;
+pub use multi::{MultiTree, MultiTreeGuard};
+
 pub struct Tree<K: Pod + Ord, V: Pod> {
     root: PageNr,
     database: DatabaseRef,
-    _phantom_key: PhantomData<K>,
-    _phantom_value: PhantomData<V>,
+    feed: Feed<TreeChange<K, V>>,
+}
+
+/// A read-only copy of a `Tree` that can be read on other threads while the original is being
+/// modified. See `Tree::view`.
+pub struct TreeView<K: Pod + Ord, V: Pod> {
+    root: PageNr,
+    view: View,
+    _phantom: PhantomData<(K, V)>,
 }
 
 impl<K: Pod + Ord, V: Pod> Tree<K, V> {
@@ -31,8 +51,7 @@ impl<K: Pod + Ord, V: Pod> Tree<K, V> {
         Self {
             root: NULL_PAGE_NR,
             database,
-            _phantom_key: PhantomData,
-            _phantom_value: PhantomData,
+            feed: Feed::new(),
         }
     }
 
@@ -43,8 +62,7 @@ impl<K: Pod + Ord, V: Pod> Tree<K, V> {
         Ok(Self {
             root,
             database,
-            _phantom_key: PhantomData,
-            _phantom_value: PhantomData,
+            feed: Feed::new(),
         })
     }
 
@@ -56,9 +74,9 @@ impl<K: Pod + Ord, V: Pod> Tree<K, V> {
     pub fn read(&self) -> ReadTreeGuard<'_, K, V> {
         ReadTreeGuard {
             root: &self.root,
-            lock: self.database.lock(),
-            _phantom_key: PhantomData,
-            _phantom_value: PhantomData,
+            lock: self.database.read_lock(),
+            pending: None,
+            entry_inserted: false,
         }
     }
 
@@ -66,28 +84,65 @@ impl<K: Pod + Ord, V: Pod> Tree<K, V> {
         WriteTreeGuard {
             root: &mut self.root,
             lock: self.database.lock(),
-            _phantom_key: PhantomData,
-            _phantom_value: PhantomData,
+            pending: Pending::new(&self.feed),
+            entry_inserted: false,
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<Event<TreeChange<K, V>>>
+    where
+        K: Send,
+        V: Send,
+    {
+        self.feed.subscribe(&self.database)
+    }
+
+    /// Takes a view of the current contents, with the same restrictions as `Vec::view`.
+    pub fn view(&self) -> io::Result<TreeView<K, V>> {
+        Ok(TreeView {
+            root: self.root,
+            view: self.database.view()?,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<K: Pod + Ord, V: Pod> TreeView<K, V> {
+    pub fn read(&self) -> ReadTreeGuard<'_, K, V> {
+        ReadTreeGuard {
+            root: &self.root,
+            lock: self.view.lock(),
+            pending: None,
+            entry_inserted: false,
         }
     }
 }
 
-impl<'a, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod> TreeGuard<'a, R, K, V> {
+impl<'a, R: DerefMut<Target = PageNr> + Access<Lock = Lock<'a>>, K: Pod + Ord, V: Pod>
+    TreeGuard<'a, R, K, V>
+{
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.update_entry();
         let mut cursor = Cursor::new(self.root.deref_mut(), &key, &self.lock);
         let old = cursor.value(&self.lock).cloned();
         unsafe { cursor.set_value(value, &self.lock) };
+        self.push(TreeChange::Insert { key, value });
         old
     }
 
     pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.update_entry();
         let cursor = Cursor::new(self.root.deref_mut(), key, &self.lock);
         let old = cursor.value(&self.lock).cloned();
         unsafe { cursor.delete(&self.lock) };
+        if old.is_some() {
+            self.push(TreeChange::Remove { key: *key });
+        }
         old
     }
 
     pub fn entry<'b>(&mut self, key: &'b K) -> Entry<'b, '_, 'a, &mut PageNr, K, V> {
+        self.update_entry();
         let cursor = Cursor::new(self.root.deref_mut(), key, &self.lock);
         if cursor.has_value() {
             Entry::Occupied(OccupiedEntry {
@@ -97,12 +152,16 @@ impl<'a, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod> TreeGuard<'a, R, K,
         } else {
             Entry::Vacant(VacantEntry {
                 cursor,
+                key: *key,
                 lock: &self.lock,
+                pending: &mut self.pending,
+                inserted: &mut self.entry_inserted,
             })
         }
     }
 
     pub fn clear(&mut self) {
+        self.update_entry();
         if *self.root == NULL_PAGE_NR {
             return;
         }
@@ -113,20 +172,83 @@ impl<'a, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod> TreeGuard<'a, R, K,
             self.lock.deallocate(*self.root);
         }
         *self.root = NULL_PAGE_NR;
+        self.push(TreeChange::Clear);
+    }
+
+    fn push(&mut self, change: TreeChange<K, V>) {
+        if let Some(pending) = &mut self.pending {
+            pending.push(change)
+        }
     }
 }
 
-impl<'a, R: Deref<Target = PageNr>, K: Pod + Ord, V: Pod> TreeGuard<'a, R, K, V> {
+impl<'a, R: Deref<Target = PageNr> + Access, K: Pod + Ord, V: Pod> TreeGuard<'a, R, K, V> {
     pub fn get(&self, key: &K) -> Option<&V> {
-        Cursor::new(self.root.deref(), key, &self.lock).value(&self.lock)
+        let lock = self.lock.read_lock();
+        Cursor::new(self.root.deref(), key, lock).value(lock)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.seek(|_| false)
+    }
+
+    pub fn range(&self, range: impl RangeBounds<K>) -> impl Iterator<Item = (&K, &V)> {
+        let start = copied(range.start_bound());
+        let end = copied(range.end_bound());
+        self.seek(move |key| match &start {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        })
+        .take_while(move |(key, _)| match &end {
+            Bound::Included(end) => *key <= end,
+            Bound::Excluded(end) => *key < end,
+            Bound::Unbounded => true,
+        })
+    }
+
+    pub(crate) fn seek(&self, is_below: impl Fn(&K) -> bool) -> Iter<'_, K, V> {
+        Iter::new(*self.root, self.lock.read_lock(), is_below)
+    }
+
+    /// Rereads the value of the last change if it was inserted through a `VacantEntry`, which may
+    /// have been written through the returned reference since. That reference is gone once the
+    /// guard is used again, so the value read here is the one that was stored by the change.
+    fn update_entry(&mut self) {
+        if !mem::take(&mut self.entry_inserted) {
+            return;
+        }
+        if let Some(TreeChange::Insert { key, value }) =
+            self.pending.as_mut().and_then(Pending::last_mut)
+        {
+            let lock = self.lock.read_lock();
+            if let Some(stored) = Cursor::new(self.root.deref(), key, lock).value(lock) {
+                *value = *stored;
+            }
+        }
+    }
+}
+
+fn copied<K: Copy>(bound: Bound<&K>) -> Bound<K> {
+    match bound {
+        Bound::Included(key) => Bound::Included(*key),
+        Bound::Excluded(key) => Bound::Excluded(*key),
+        Bound::Unbounded => Bound::Unbounded,
     }
 }
 
-pub struct TreeGuard<'a, R: Deref<Target = PageNr>, K: Pod + Ord, V: Pod> {
+pub struct TreeGuard<'a, R: Deref<Target = PageNr> + Access, K: Pod + Ord, V: Pod> {
     root: R,
-    lock: Lock<'a>,
-    _phantom_key: PhantomData<K>,
-    _phantom_value: PhantomData<V>,
+    lock: R::Lock,
+    pending: Option<Pending<'a, TreeChange<K, V>>>,
+    /// Whether the last change was made through a `VacantEntry`, see `update_entry`.
+    entry_inserted: bool,
+}
+
+impl<'a, R: Deref<Target = PageNr> + Access, K: Pod + Ord, V: Pod> Drop for TreeGuard<'a, R, K, V> {
+    fn drop(&mut self) {
+        self.update_entry();
+    }
 }
 
 pub type ReadTreeGuard<'a, K, V> = TreeGuard<'a, &'a PageNr, K, V>;
@@ -154,16 +276,42 @@ pub struct OccupiedEntry<'a, 'b, 'c, R: DerefMut<Target = PageNr>, K: Pod + Ord,
 
 pub struct VacantEntry<'a, 'b: 'a, 'c, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod> {
     cursor: Cursor<'a, R, K, V>,
+    key: K,
     lock: &'b Lock<'c>,
+    pending: &'b mut Option<Pending<'c, TreeChange<K, V>>>,
+    inserted: &'b mut bool,
 }
 
 impl<'a, 'b: 'a, 'c, R: DerefMut<Target = PageNr>, K: Pod + Ord, V: Pod>
     VacantEntry<'a, 'b, 'c, R, K, V>
 {
+    /// Inserts `value`. The change feed reports the value that is stored once the returned
+    /// reference is gone.
     pub fn insert(mut self, value: V) -> &'b mut V {
+        if let Some(pending) = self.pending {
+            pending.push(TreeChange::Insert {
+                key: self.key,
+                value,
+            });
+            *self.inserted = true;
+        }
         unsafe {
             self.cursor.set_value(value, self.lock);
             self.cursor.value_mut(self.lock).unwrap()
         }
     }
 }
+
+impl<K: Layout + Ord, V: Layout> Field for Tree<K, V> {
+    fn describe() -> String {
+        format!("Tree<{}, {}>", K::describe(), V::describe())
+    }
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        Tree::serialize(self, writer)
+    }
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        Tree::deserialize(reader, database)
+    }
+}
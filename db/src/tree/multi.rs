@@ -0,0 +1,268 @@
+use std::{
+    cmp::Ordering,
+    io::{self, Read, Write},
+    ops::{Bound, Deref, DerefMut, RangeBounds},
+};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    lock::{Access, Lock},
+    object::{Field, Layout},
+    page::PageNr,
+    reference::DatabaseRef,
+};
+
+use super::{copied, Tree, TreeGuard};
+
+#[repr(C, packed)]
+pub struct Pair<K, V> {
+    key: K,
+    value: V,
+}
+
+impl<K: Copy, V: Copy> Pair<K, V> {
+    pub(crate) fn new(key: K, value: V) -> Self {
+        Self { key, value }
+    }
+
+    pub(crate) fn key(&self) -> K {
+        self.key
+    }
+
+    pub(crate) fn value(&self) -> V {
+        self.value
+    }
+}
+
+impl<K: Copy, V: Copy> Clone for Pair<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K: Copy, V: Copy> Copy for Pair<K, V> {}
+
+unsafe impl<K: Pod, V: Pod> Zeroable for Pair<K, V> {}
+unsafe impl<K: Pod, V: Pod> Pod for Pair<K, V> {}
+
+impl<K: Copy + Ord, V: Copy + Ord> PartialEq for Pair<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<K: Copy + Ord, V: Copy + Ord> Eq for Pair<K, V> {}
+
+impl<K: Copy + Ord, V: Copy + Ord> PartialOrd for Pair<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K: Copy + Ord, V: Copy + Ord> Ord for Pair<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key(), self.value()).cmp(&(other.key(), other.value()))
+    }
+}
+
+pub struct MultiTree<K: Pod + Ord, V: Pod + Ord> {
+    tree: Tree<Pair<K, V>, ()>,
+}
+
+impl<K: Pod + Ord, V: Pod + Ord> MultiTree<K, V> {
+    pub fn new(database: DatabaseRef) -> Self {
+        Self {
+            tree: Tree::new(database),
+        }
+    }
+
+    pub fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        Ok(Self {
+            tree: Tree::deserialize(reader, database)?,
+        })
+    }
+
+    pub fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.tree.serialize(writer)
+    }
+
+    pub fn read(&self) -> ReadMultiTreeGuard<'_, K, V> {
+        MultiTreeGuard(self.tree.read())
+    }
+
+    pub fn write(&mut self) -> WriteMultiTreeGuard<'_, K, V> {
+        MultiTreeGuard(self.tree.write())
+    }
+}
+
+pub struct MultiTreeGuard<'a, R: Deref<Target = PageNr> + Access, K: Pod + Ord, V: Pod + Ord>(
+    TreeGuard<'a, R, Pair<K, V>, ()>,
+);
+
+pub type ReadMultiTreeGuard<'a, K, V> = MultiTreeGuard<'a, &'a PageNr, K, V>;
+pub type WriteMultiTreeGuard<'a, K, V> = MultiTreeGuard<'a, &'a mut PageNr, K, V>;
+
+impl<'a, R: Deref<Target = PageNr> + Access, K: Pod + Ord, V: Pod + Ord>
+    MultiTreeGuard<'a, R, K, V>
+{
+    pub fn get(&self, key: &K) -> impl Iterator<Item = V> + '_ {
+        let key = *key;
+        self.iter_from(move |pair| pair.key() < key)
+            .take_while(move |(k, _)| *k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn contains(&self, key: &K, value: &V) -> bool {
+        self.0.get(&Pair::new(*key, *value)).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.iter_from(|_| false)
+    }
+
+    pub fn range(&self, range: impl RangeBounds<K>) -> impl Iterator<Item = (K, V)> + '_ {
+        let start = copied(range.start_bound());
+        let end = copied(range.end_bound());
+        self.iter_from(move |pair| match &start {
+            Bound::Included(start) => pair.key() < *start,
+            Bound::Excluded(start) => pair.key() <= *start,
+            Bound::Unbounded => false,
+        })
+        .take_while(move |(key, _)| match &end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        })
+    }
+
+    fn iter_from(
+        &self,
+        is_below: impl Fn(&Pair<K, V>) -> bool,
+    ) -> impl Iterator<Item = (K, V)> + '_ {
+        self.0
+            .seek(is_below)
+            .map(|(pair, _)| (pair.key(), pair.value()))
+    }
+}
+
+impl<'a, R: DerefMut<Target = PageNr> + Access<Lock = Lock<'a>>, K: Pod + Ord, V: Pod + Ord>
+    MultiTreeGuard<'a, R, K, V>
+{
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        self.0.insert(Pair::new(key, value), ()).is_none()
+    }
+
+    pub fn remove(&mut self, key: &K, value: &V) -> bool {
+        self.0.remove(&Pair::new(*key, *value)).is_some()
+    }
+
+    pub fn remove_all(&mut self, key: &K) -> usize {
+        let values: Vec<V> = self.get(key).collect();
+        for value in values.iter() {
+            self.remove(key, value);
+        }
+        values.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+impl<K: Layout + Ord, V: Layout + Ord> Field for MultiTree<K, V> {
+    fn describe() -> String {
+        format!("MultiTree<{}, {}>", K::describe(), V::describe())
+    }
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        MultiTree::serialize(self, writer)
+    }
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        MultiTree::deserialize(reader, database)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use crate::testing::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn several_values_per_key() {
+        let dir = TestDir::new();
+        let mut tree = dir.create(MultiTree::<u32, u64>::new);
+        {
+            let mut tree = tree.write();
+            for value in [12, 10, 11] {
+                assert!(tree.insert(1, value));
+            }
+            assert!(tree.insert(2, 20));
+            assert!(!tree.insert(1, 11));
+        }
+        let tree = tree.read();
+        assert_eq!(tree.get(&1).collect::<std::vec::Vec<_>>(), [10, 11, 12]);
+        assert_eq!(tree.get(&2).collect::<std::vec::Vec<_>>(), [20]);
+        assert_eq!(tree.get(&3).count(), 0);
+        assert!(tree.contains(&1, &11));
+        assert!(!tree.contains(&2, &11));
+    }
+
+    #[test]
+    fn remove_keeps_siblings() {
+        let dir = TestDir::new();
+        let mut tree = dir.create(MultiTree::<u32, u64>::new);
+        let mut writer = tree.write();
+        for value in 0..1000 {
+            writer.insert(value as u32 % 3, value);
+        }
+        assert!(writer.remove(&1, &4));
+        assert!(!writer.remove(&1, &4));
+        assert!(!writer.remove(&1, &5));
+        let ones: std::vec::Vec<_> = writer.get(&1).collect();
+        let expected: std::vec::Vec<_> = (0..1000).filter(|v| v % 3 == 1 && *v != 4).collect();
+        assert_eq!(ones, expected);
+        assert_eq!(writer.remove_all(&1), expected.len());
+        assert_eq!(writer.get(&1).count(), 0);
+        assert_eq!(writer.get(&0).count(), 334);
+        assert_eq!(writer.get(&2).count(), 333);
+    }
+
+    #[test]
+    fn range_queries() {
+        let dir = TestDir::new();
+        let mut tree = dir.create(MultiTree::<u32, u64>::new);
+        {
+            let mut tree = tree.write();
+            for key in (0..2000).rev() {
+                tree.insert(key, key as u64 * 2 + 1);
+                tree.insert(key, key as u64 * 2);
+            }
+        }
+        let tree = tree.read();
+        let pairs = |keys: std::ops::Range<u32>| -> std::vec::Vec<(u32, u64)> {
+            keys.flat_map(|key| [(key, key as u64 * 2), (key, key as u64 * 2 + 1)])
+                .collect()
+        };
+        assert_eq!(
+            tree.range(10..20).collect::<std::vec::Vec<_>>(),
+            pairs(10..20)
+        );
+        assert_eq!(tree.range(..=3).collect::<std::vec::Vec<_>>(), pairs(0..4));
+        assert_eq!(
+            tree.range(1995..).collect::<std::vec::Vec<_>>(),
+            pairs(1995..2000)
+        );
+        assert_eq!(
+            tree.range((Bound::Excluded(5), Bound::Included(7)))
+                .collect::<std::vec::Vec<_>>(),
+            pairs(6..8)
+        );
+        assert_eq!(tree.range(3000..).count(), 0);
+        assert_eq!(tree.iter().collect::<std::vec::Vec<_>>(), pairs(0..2000));
+    }
+}
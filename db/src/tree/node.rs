@@ -34,6 +34,40 @@ pub trait NodePage: DerefMut<Target = [u8; PAGE_SIZE]> + Clone + Pod {
 
 impl NodePage for Page {}
 
+/// The number of entries that fit into a node whose page ends with `reserved` bytes. The first
+/// parts of the entries are stored in front, the second parts after them with the given
+/// alignment.
+pub const fn order(
+    first_size: usize,
+    second_size: usize,
+    second_align: usize,
+    reserved: usize,
+) -> usize {
+    let sum_size = first_size + second_size;
+    let order = (PAGE_SIZE - reserved) / sum_size;
+    let mismatch = (order * first_size) % second_align;
+    if mismatch == 0 {
+        order
+    } else {
+        let offset = second_align - mismatch;
+        if order * sum_size + offset <= PAGE_SIZE - reserved {
+            order
+        } else {
+            order - 1
+        }
+    }
+}
+
+/// The offset of the second parts of the entries in a node of the given order.
+pub const fn second_offset(first_size: usize, second_align: usize, order: usize) -> usize {
+    let mismatch = (order * first_size) % second_align;
+    if mismatch == 0 {
+        order * first_size
+    } else {
+        order * first_size + second_align - mismatch
+    }
+}
+
 pub unsafe fn allocate<'a>(lock: &'a Lock, is_leaf: bool) -> (PageNr, &'a mut Page) {
     let page_nr = lock.allocate();
     let page = lock.try_page_mut(page_nr).unwrap();
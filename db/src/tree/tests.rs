@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    lock::ReadLock,
+    page::{PageNr, NULL_PAGE_NR},
+    testing::TestDir,
+};
+
+use super::{
+    branch::Branch,
+    leaf::Leaf,
+    node::{node, NodeRef},
+    ReadTreeGuard, Tree,
+};
+
+/// A large key, so that nodes hold few entries and a few thousand keys make a tree of four
+/// levels.
+type Key = [u64; 32];
+
+fn key(i: u64) -> Key {
+    let mut key = [0; 32];
+    key[0] = i;
+    key
+}
+
+/// A permutation of `0..n` that does not follow the key order.
+fn shuffled(n: u64, seed: u64) -> Vec<u64> {
+    let mut keys: Vec<_> = (0..n).collect();
+    let mut state = seed;
+    for i in (1..keys.len()).rev() {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        keys.swap(i, (state >> 33) as usize % (i + 1));
+    }
+    keys
+}
+
+/// Checks that the tree holds exactly the expected entries and that it is balanced, ordered and
+/// that all nodes but the root are at least half full.
+fn check(tree: &ReadTreeGuard<Key, u64>, expected: &BTreeMap<Key, u64>) -> usize {
+    let entries: Vec<_> = tree.iter().map(|(key, value)| (*key, *value)).collect();
+    let expected_entries: Vec<_> = expected.iter().map(|(key, value)| (*key, *value)).collect();
+    assert!(entries == expected_entries);
+    for (key, value) in expected {
+        assert_eq!(tree.get(key), Some(value));
+    }
+    if *tree.root == NULL_PAGE_NR {
+        assert!(expected.is_empty());
+        return 0;
+    }
+    check_node(&tree.lock, *tree.root, None, None, true)
+}
+
+/// Returns the height of the subtree.
+fn check_node(
+    lock: &ReadLock,
+    page_nr: PageNr,
+    lower: Option<&Key>,
+    upper: Option<&Key>,
+    is_root: bool,
+) -> usize {
+    let in_bounds = |key: &Key| {
+        lower.map_or(true, |lower| lower <= key) && upper.map_or(true, |upper| key < upper)
+    };
+    match node::<Key, u64>(unsafe { lock.page(page_nr) }) {
+        NodeRef::Leaf(leaf) => {
+            let keys = leaf.keys();
+            assert!(!keys.is_empty());
+            assert!(is_root || keys.len() * 2 + 1 >= Leaf::<Key, u64>::order());
+            assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(keys.iter().all(in_bounds));
+            1
+        }
+        NodeRef::Branch(branch) => {
+            let keys = branch.keys();
+            let children = branch.children();
+            if is_root {
+                assert!(children.len() >= 2);
+            } else {
+                assert!(children.len() * 2 + 1 >= Branch::<Key>::order());
+            }
+            assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(keys.iter().all(in_bounds));
+            let heights: Vec<_> = children
+                .iter()
+                .enumerate()
+                .map(|(i, child)| {
+                    let lower = if i == 0 { lower } else { Some(&keys[i - 1]) };
+                    let upper = keys.get(i).or(upper);
+                    check_node(lock, *child, lower, upper, false)
+                })
+                .collect();
+            assert!(heights.windows(2).all(|pair| pair[0] == pair[1]));
+            heights[0] + 1
+        }
+    }
+}
+
+/// Inserts `n` keys in the given order, then removes them in the other order, checking the tree
+/// along the way. Returns the largest height the tree reached.
+fn insert_and_remove(insert: &[u64], remove: &[u64]) -> usize {
+    let dir = TestDir::new();
+    let mut tree = dir.create(Tree::new);
+    let mut expected = BTreeMap::new();
+    let mut height = 0;
+    for (i, k) in insert.iter().enumerate() {
+        assert_eq!(tree.write().insert(key(*k), *k), None);
+        expected.insert(key(*k), *k);
+        if i % 97 == 0 {
+            height = height.max(check(&tree.read(), &expected));
+        }
+    }
+    height = height.max(check(&tree.read(), &expected));
+    for (i, k) in remove.iter().enumerate() {
+        assert_eq!(tree.write().remove(&key(*k)), Some(*k));
+        assert_eq!(tree.write().remove(&key(*k)), None);
+        expected.remove(&key(*k));
+        if i % 97 == 0 || expected.len() < 64 {
+            check(&tree.read(), &expected);
+        }
+    }
+    assert_eq!(check(&tree.read(), &expected), 0);
+    height
+}
+
+const N: u64 = 20_000;
+
+/// Removing the smallest key first always rebalances with the right sibling, borrowing from it
+/// or merging it into the underfull node, at every level until the root collapses.
+#[test]
+fn remove_ascending() {
+    let keys: Vec<_> = (0..N).collect();
+    assert!(insert_and_remove(&keys, &keys) >= 4);
+}
+
+/// Removing the largest key first always rebalances with the left sibling.
+#[test]
+fn remove_descending() {
+    let keys: Vec<_> = (0..N).collect();
+    let reversed: Vec<_> = keys.iter().rev().copied().collect();
+    assert!(insert_and_remove(&keys, &reversed) >= 4);
+}
+
+/// Random inserts split nodes at every position and random removals mix all rebalance cases.
+#[test]
+fn remove_shuffled() {
+    assert!(insert_and_remove(&shuffled(N, 1), &shuffled(N, 2)) >= 3);
+}
+
+#[test]
+fn reuse_after_removing_all() {
+    let keys = shuffled(1000, 3);
+    let dir = TestDir::new();
+    let mut tree = dir.create(Tree::new);
+    let mut expected = BTreeMap::new();
+    for _ in 0..2 {
+        for k in &keys {
+            tree.write().insert(key(*k), *k);
+            expected.insert(key(*k), *k);
+        }
+        check(&tree.read(), &expected);
+        for k in &keys {
+            tree.write().remove(&key(*k));
+            expected.remove(&key(*k));
+        }
+        check(&tree.read(), &expected);
+    }
+}
@@ -5,15 +5,19 @@ use std::{
     marker::PhantomData,
     mem::size_of,
     ops::{Deref, DerefMut, Index, IndexMut},
+    sync::mpsc::Receiver,
 };
 
 use bytemuck::{cast_slice, cast_slice_mut, Pod};
 
 use crate::{
     cursor::{reallocate, PageLookup},
-    lock::Lock,
+    feed::{Event, Feed, Pending, VecChange},
+    lock::{Access, AsReadLock, Lock},
+    object::{Field, Layout},
     page::{PageNr, PAGE_SIZE},
     reference::DatabaseRef,
+    view::View,
 };
 
 #[derive(Clone, Default, Copy)]
@@ -36,13 +40,27 @@ impl VecHeader {
 pub struct Vec<T: Pod> {
     header: VecHeader,
     database: DatabaseRef,
-    _phantom: PhantomData<T>,
+    feed: Feed<VecChange<T>>,
 }
 
-pub struct VecGuard<'a, T: Pod, H> {
+pub struct VecGuard<'a, T: Pod, H: Deref<Target = VecHeader> + Access> {
     header: H,
-    lock: Lock<'a>,
+    lock: H::Lock,
     lookup: Cell<PageLookup>,
+    pending: Option<Pending<'a, VecChange<T>>>,
+    len: usize,
+    dirty: Dirty,
+}
+
+/// The indices written through a guard, one bit each.
+#[derive(Default)]
+struct Dirty(std::vec::Vec<u64>);
+
+/// A read-only copy of a `Vec` that can be read on other threads while the original is being
+/// modified. See `Vec::view`.
+pub struct VecView<T: Pod> {
+    header: VecHeader,
+    view: View,
     _phantom: PhantomData<T>,
 }
 
@@ -54,28 +72,50 @@ impl<T: Pod> Vec<T> {
         Self {
             header: VecHeader::default(),
             database,
-            _phantom: PhantomData,
+            feed: Feed::new(),
         }
     }
 
     pub fn write(&mut self) -> WriteVecGuard<'_, T> {
         WriteVecGuard {
+            len: self.header.len,
             header: &mut self.header,
             lock: self.database.lock(),
             lookup: Cell::new(PageLookup::Invalid),
-            _phantom: PhantomData,
+            pending: Pending::new(&self.feed),
+            dirty: Dirty::default(),
         }
     }
 
     pub fn read(&self) -> ReadVecGuard<'_, T> {
         ReadVecGuard {
             header: &self.header,
-            lock: self.database.lock(),
+            lock: self.database.read_lock(),
             lookup: Cell::new(PageLookup::Invalid),
-            _phantom: PhantomData,
+            pending: None,
+            len: self.header.len,
+            dirty: Dirty::default(),
         }
     }
 
+    pub fn subscribe(&self) -> Receiver<Event<VecChange<T>>>
+    where
+        T: Send,
+    {
+        self.feed.subscribe(&self.database)
+    }
+
+    /// Takes a view of the current contents. Panics if the database is locked. The second
+    /// snapshot after taking the view waits until it is dropped, so it must not be kept on the
+    /// thread that takes snapshots.
+    pub fn view(&self) -> io::Result<VecView<T>> {
+        Ok(VecView {
+            header: self.header,
+            view: self.database.view()?,
+            _phantom: PhantomData,
+        })
+    }
+
     pub fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
         let mut bytes = [0; 4];
         reader.read_exact(&mut bytes)?;
@@ -86,7 +126,7 @@ impl<T: Pod> Vec<T> {
         Ok(Self {
             header: VecHeader { root, len },
             database,
-            _phantom: PhantomData,
+            feed: Feed::new(),
         })
     }
 
@@ -97,6 +137,19 @@ impl<T: Pod> Vec<T> {
     }
 }
 
+impl<T: Pod> VecView<T> {
+    pub fn read(&self) -> ReadVecGuard<'_, T> {
+        ReadVecGuard {
+            header: &self.header,
+            lock: self.view.lock(),
+            lookup: Cell::new(PageLookup::Invalid),
+            pending: None,
+            len: self.header.len,
+            dirty: Dirty::default(),
+        }
+    }
+}
+
 impl<T: Pod> Drop for Vec<T> {
     fn drop(&mut self) {
         let lock = self.database.lock();
@@ -107,7 +160,7 @@ impl<T: Pod> Drop for Vec<T> {
     }
 }
 
-impl<'a, T: Pod, H: Deref<Target = VecHeader>> VecGuard<'a, T, H> {
+impl<'a, T: Pod, H: Deref<Target = VecHeader> + Access> VecGuard<'a, T, H> {
     pub fn iter(&self) -> Iter<'_, Self> {
         Iter {
             container: self,
@@ -116,13 +169,13 @@ impl<'a, T: Pod, H: Deref<Target = VecHeader>> VecGuard<'a, T, H> {
     }
 }
 
-impl<'a, T: Pod, H: Deref<Target = VecHeader>> Len for VecGuard<'a, T, H> {
+impl<'a, T: Pod, H: Deref<Target = VecHeader> + Access> Len for VecGuard<'a, T, H> {
     fn len(&self) -> usize {
         self.header.len
     }
 }
 
-impl<'a, T: Pod, H: DerefMut<Target = VecHeader>> VecGuard<'a, T, H> {
+impl<'a, T: Pod, H: DerefMut<Target = VecHeader> + Access<Lock = Lock<'a>>> VecGuard<'a, T, H> {
     fn internal_resize(&mut self, new_len: usize) {
         let current_pages = self.header.pages::<T>();
         self.header.len = new_len;
@@ -143,9 +196,19 @@ impl<'a, T: Pod, H: DerefMut<Target = VecHeader>> VecGuard<'a, T, H> {
         self.internal_resize(index + 1);
         self[index] = value;
     }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.header.len;
+        if len == 0 {
+            return None;
+        }
+        let value = self[len - 1];
+        self.internal_resize(len - 1);
+        Some(value)
+    }
 }
 
-impl<'a, T: Pod, H: Deref<Target = VecHeader>> Index<usize> for VecGuard<'a, T, H> {
+impl<'a, T: Pod, H: Deref<Target = VecHeader> + Access> Index<usize> for VecGuard<'a, T, H> {
     type Output = T;
 
     fn index(&self, index: usize) -> &Self::Output {
@@ -154,13 +217,15 @@ impl<'a, T: Pod, H: Deref<Target = VecHeader>> Index<usize> for VecGuard<'a, T,
         let page_offset = index % VecHeader::elements_per_page::<T>() as usize * size_of::<T>();
         let pages = self.header.pages::<T>();
         let mut lookup = self.lookup.get();
-        let page = lookup.get(self.header.root, pages, page_index, &self.lock);
+        let page = lookup.get(self.header.root, pages, page_index, self.lock.read_lock());
         self.lookup.set(lookup);
         &cast_slice::<u8, T>(&page[page_offset..page_offset + size_of::<T>()])[0]
     }
 }
 
-impl<'a, T: Pod, H: DerefMut<Target = VecHeader>> IndexMut<usize> for VecGuard<'a, T, H> {
+impl<'a, T: Pod, H: DerefMut<Target = VecHeader> + Access<Lock = Lock<'a>>> IndexMut<usize>
+    for VecGuard<'a, T, H>
+{
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         assert!(index < self.header.len as usize);
         let page_index = index / VecHeader::elements_per_page::<T>() as usize;
@@ -173,10 +238,48 @@ impl<'a, T: Pod, H: DerefMut<Target = VecHeader>> IndexMut<usize> for VecGuard<'
                 .as_mut()
                 .unwrap()
         };
+        if self.pending.is_some() {
+            self.dirty.insert(index);
+        }
         &mut cast_slice_mut::<u8, T>(&mut page[page_offset..page_offset + size_of::<T>()])[0]
     }
 }
 
+impl<'a, T: Pod, H: Deref<Target = VecHeader> + Access> Drop for VecGuard<'a, T, H> {
+    fn drop(&mut self) {
+        if let Some(mut pending) = self.pending.take() {
+            let len = self.header.len;
+            if len != self.len {
+                pending.push(VecChange::Resize { len });
+            }
+            for index in self.dirty.iter().take_while(|index| *index < len) {
+                pending.push(VecChange::Set {
+                    index,
+                    value: self[index],
+                });
+            }
+        }
+    }
+}
+
+impl Dirty {
+    fn insert(&mut self, index: usize) {
+        let (word, bit) = (index / 64, index % 64);
+        if word >= self.0.len() {
+            self.0.resize(word + 1, 0);
+        }
+        self.0[word] |= 1 << bit;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| word * 64 + bit)
+        })
+    }
+}
+
 pub trait Len {
     fn len(&self) -> usize;
 
@@ -222,3 +325,17 @@ impl<'a, T: IndexMut<usize> + Len> Iterator for IterMut<'a, T> {
         }
     }
 }
+
+impl<T: Layout> Field for Vec<T> {
+    fn describe() -> String {
+        format!("Vec<{}>", T::describe())
+    }
+
+    fn serialize(&self, writer: &mut impl Write) -> io::Result<()> {
+        Vec::serialize(self, writer)
+    }
+
+    fn deserialize(reader: &mut impl Read, database: DatabaseRef) -> io::Result<Self> {
+        Vec::deserialize(reader, database)
+    }
+}
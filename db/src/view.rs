@@ -0,0 +1,92 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::{lock::ReadLock, mmap::MappedBitset, page::Pager, storage::Storage};
+
+/// A read-only state of the database that other threads can read while the database is being
+/// modified. Taking a view makes all pages read-only, so the writer copies every page it
+/// modifies afterwards and the pages of the view stay untouched. Pages released after the view
+/// was taken become reusable two snapshots later, so the second snapshot after a view waits
+/// until the view is dropped.
+#[derive(Clone)]
+pub struct View(Arc<Inner>);
+
+struct Inner {
+    data: Storage,
+    writable: MappedBitset,
+    dirty: MappedBitset,
+    generation: u64,
+    readers: Arc<Readers>,
+}
+
+/// The number of live views per generation, i.e. the version of the last snapshot when they
+/// were taken.
+#[derive(Default)]
+pub struct Readers {
+    generations: Mutex<BTreeMap<u64, usize>>,
+    released: Condvar,
+}
+
+impl View {
+    pub(crate) fn new(
+        data: Storage,
+        writable: MappedBitset,
+        dirty: MappedBitset,
+        generation: u64,
+        readers: Arc<Readers>,
+    ) -> Self {
+        *readers
+            .generations
+            .lock()
+            .unwrap()
+            .entry(generation)
+            .or_default() += 1;
+        Self(Arc::new(Inner {
+            data,
+            writable,
+            dirty,
+            generation,
+            readers,
+        }))
+    }
+
+    pub(crate) fn lock(&self) -> ReadLock<'_> {
+        ReadLock::view(self)
+    }
+
+    pub(crate) fn pager(&self) -> Pager {
+        Pager::new(
+            self.0.data.clone(),
+            self.0.writable.clone(),
+            self.0.dirty.clone(),
+        )
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let mut generations = self.readers.generations.lock().unwrap();
+        let count = generations.get_mut(&self.generation).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            generations.remove(&self.generation);
+            self.readers.released.notify_all();
+        }
+    }
+}
+
+impl Readers {
+    /// Blocks until there are no views of generations before `generation` anymore.
+    pub fn wait(&self, generation: u64) {
+        let mut generations = self.generations.lock().unwrap();
+        while generations
+            .keys()
+            .next()
+            .map_or(false, |oldest| *oldest < generation)
+        {
+            generations = self.released.wait(generations).unwrap();
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    ops::{Deref, DerefMut},
+};
+
+use bytemuck::{bytes_of, bytes_of_mut, cast_slice, Pod, Zeroable};
+use sha3::{Digest, Sha3_512};
+
+#[cfg(feature = "fault-injection")]
+use crate::fault::{DiskFile, FaultyDisk};
+use crate::{
+    crypt::Cipher,
+    header::{Checksum, State},
+    page::{PageNr, Pager, PAGE_SIZE},
+};
+
+/// An append-only log of the pages written since the last snapshot. Each record holds the state
+/// at the time it was logged, the held pages and the contents of the pages written since the
+/// previous record, followed by a checksum over all of it. The records of encrypted databases
+/// are sealed as a whole and prefixed with their length.
+pub struct Wal {
+    file: LogFile,
+    version: u64,
+    len: u64,
+    cipher: Option<Cipher>,
+}
+
+/// The file of a log, which may live on a simulated disk.
+pub struct LogFile {
+    file: File,
+    #[cfg(feature = "fault-injection")]
+    disk: Option<(FaultyDisk, u64)>,
+}
+
+/// The outcome of replaying a log on top of a snapshot.
+pub struct Replay {
+    pub state: State,
+    pub held: Vec<PageNr>,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct RecordHeader {
+    state: State,
+    pages: u64,
+    held: u64,
+}
+
+impl LogFile {
+    pub fn new(file: File) -> Self {
+        Self {
+            file,
+            #[cfg(feature = "fault-injection")]
+            disk: None,
+        }
+    }
+
+    /// Uses the log file of a simulated disk, which only receives its contents when
+    /// synchronized.
+    #[cfg(feature = "fault-injection")]
+    pub fn faulty(file: File, disk: &FaultyDisk, attachment: u64) -> Self {
+        Self {
+            file,
+            disk: Some((disk.clone(), attachment)),
+        }
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        #[cfg(feature = "fault-injection")]
+        if let Some((disk, attachment)) = &self.disk {
+            return disk.sync(*attachment, DiskFile::Log);
+        }
+        self.file.sync_data()
+    }
+}
+
+impl Deref for LogFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.file
+    }
+}
+
+impl DerefMut for LogFile {
+    fn deref_mut(&mut self) -> &mut File {
+        &mut self.file
+    }
+}
+
+impl Wal {
+    /// Replays the intact records that were logged on top of `state` and discards the rest of
+    /// the log, including records of older snapshots and a torn record at the end.
+    pub fn open(
+        mut file: LogFile,
+        state: &State,
+        pager: &Pager,
+        cipher: Option<Cipher>,
+    ) -> io::Result<(Self, Option<Replay>)> {
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(0))?;
+        let mut offset = 0;
+        let mut replay = None;
+        loop {
+            let remaining = len - offset;
+            let read = match &cipher {
+                Some(cipher) => Self::read_sealed(&mut file, remaining, state.version, cipher)?,
+                None => Self::read(&mut file, remaining, state.version)?,
+            };
+            let (record, size) = match read {
+                Some(read) => read,
+                None => break,
+            };
+            let (pages, held, contents) = record.parts();
+            for (nr, content) in pages.iter().zip(contents.chunks_exact(PAGE_SIZE)) {
+                unsafe {
+                    pager.page_mut(*nr).copy_from_slice(content);
+                    pager.enable_write(*nr);
+                }
+            }
+            replay = Some(Replay {
+                state: record.header.state,
+                held,
+            });
+            offset += size;
+        }
+        file.set_len(offset)?;
+        Ok((
+            Self {
+                file,
+                version: state.version,
+                len: offset,
+                cipher,
+            },
+            replay,
+        ))
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Drops all records and starts a log on top of the snapshot with the given version.
+    pub fn reset(&mut self, version: u64) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.version = version;
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Appends a record and waits until it is durable. A failed append leaves the previous
+    /// records intact and is overwritten by the next one.
+    pub fn append(
+        &mut self,
+        state: &State,
+        pages: &[PageNr],
+        held: &[PageNr],
+        pager: &Pager,
+    ) -> io::Result<()> {
+        let header = RecordHeader {
+            state: *state,
+            pages: pages.len() as u64,
+            held: held.len() as u64,
+        };
+        let mut bytes = bytes_of(&header).to_vec();
+        bytes.extend_from_slice(cast_slice(pages));
+        bytes.extend_from_slice(cast_slice(held));
+        for nr in pages {
+            bytes.extend_from_slice(unsafe { &pager.page(*nr)[..] });
+        }
+        let checksum = Sha3_512::digest(&bytes);
+        bytes.extend_from_slice(&checksum);
+        if let Some(cipher) = &self.cipher {
+            let sealed = cipher.seal(self.version, &bytes);
+            bytes = (sealed.len() as u64).to_le_bytes().to_vec();
+            bytes.extend(sealed);
+        }
+        self.file.seek(SeekFrom::Start(self.len))?;
+        self.file.write_all(&bytes)?;
+        self.file.sync()?;
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn read(file: &mut File, remaining: u64, version: u64) -> io::Result<Option<(Record, u64)>> {
+        let mut header = RecordHeader::zeroed();
+        if remaining < size_of::<RecordHeader>() as u64 {
+            return Ok(None);
+        }
+        file.read_exact(bytes_of_mut(&mut header))?;
+        if header.state.version != version {
+            return Ok(None);
+        }
+        let size = match Record::size(&header) {
+            Some(size) if size <= remaining => size,
+            _ => return Ok(None),
+        };
+        let mut bytes = vec![0; size as usize - size_of::<RecordHeader>()];
+        file.read_exact(&mut bytes)?;
+        Ok(Record::verify(header, bytes).map(|record| (record, size)))
+    }
+
+    fn read_sealed(
+        file: &mut File,
+        remaining: u64,
+        version: u64,
+        cipher: &Cipher,
+    ) -> io::Result<Option<(Record, u64)>> {
+        let mut len = [0; 8];
+        if remaining < len.len() as u64 {
+            return Ok(None);
+        }
+        file.read_exact(&mut len)?;
+        let size = match u64::from_le_bytes(len).checked_add(len.len() as u64) {
+            Some(size) if size <= remaining => size,
+            _ => return Ok(None),
+        };
+        let mut sealed = vec![0; size as usize - len.len()];
+        file.read_exact(&mut sealed)?;
+        let mut bytes = match cipher.unseal(version, &sealed) {
+            Some(bytes) if bytes.len() >= size_of::<RecordHeader>() => bytes,
+            _ => return Ok(None),
+        };
+        let body = bytes.split_off(size_of::<RecordHeader>());
+        let mut header = RecordHeader::zeroed();
+        bytes_of_mut(&mut header).copy_from_slice(&bytes);
+        if header.state.version != version
+            || Record::size(&header) != Some((bytes.len() + body.len()) as u64)
+        {
+            return Ok(None);
+        }
+        Ok(Record::verify(header, body).map(|record| (record, size)))
+    }
+}
+
+struct Record {
+    header: RecordHeader,
+    body: Vec<u8>,
+}
+
+impl Record {
+    fn size(header: &RecordHeader) -> Option<u64> {
+        let nrs = header.pages.checked_add(header.held)?.checked_mul(4)?;
+        let contents = header.pages.checked_mul(PAGE_SIZE as u64)?;
+        nrs.checked_add(contents)?
+            .checked_add((size_of::<RecordHeader>() + size_of::<Checksum>()) as u64)
+    }
+
+    /// Checks the checksum at the end of `bytes`, which follow the header.
+    fn verify(header: RecordHeader, mut bytes: Vec<u8>) -> Option<Self> {
+        let (body, checksum) = bytes.split_at(bytes.len() - size_of::<Checksum>());
+        let mut hasher = Sha3_512::new();
+        hasher.update(bytes_of(&header));
+        hasher.update(body);
+        if hasher.finalize()[..] != *checksum {
+            return None;
+        }
+        bytes.truncate(body.len());
+        Some(Self {
+            header,
+            body: bytes,
+        })
+    }
+
+    fn parts(&self) -> (Vec<PageNr>, Vec<PageNr>, &[u8]) {
+        let (pages, rest) = self.body.split_at(self.header.pages as usize * 4);
+        let (held, contents) = rest.split_at(self.header.held as usize * 4);
+        let nrs = |bytes: &[u8]| -> Vec<PageNr> {
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| PageNr::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect()
+        };
+        (nrs(pages), nrs(held), contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, ops::Range};
+
+    use crate::{
+        testing::{Single, TestDir},
+        Database, Tree,
+    };
+
+    type Content = Single<Tree<u32, u32>>;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn insert(database: &mut Database<Content>, keys: Range<u32>) {
+        let mut tree = database.write();
+        for key in keys {
+            tree.insert(key, key * 2);
+        }
+    }
+
+    fn assert_contains(database: &Database<Content>, keys: Range<u32>) {
+        let tree = database.read();
+        let entries: Vec<_> = tree.iter().map(|(key, value)| (*key, *value)).collect();
+        let expected: Vec<_> = keys.map(|key| (key, key * 2)).collect();
+        assert!(entries == expected);
+    }
+
+    /// Logs two batches of changes on top of a snapshot, then drops the database without
+    /// another snapshot, as if the process crashed.
+    fn log_twice(mut database: Database<Content>) {
+        insert(&mut database, 0..1000);
+        database.snapshot().unwrap();
+        insert(&mut database, 1000..2000);
+        database.log().unwrap();
+        insert(&mut database, 2000..3000);
+        database.log().unwrap();
+        insert(&mut database, 3000..3100);
+    }
+
+    #[test]
+    fn replay_logged_changes() {
+        let dir = TestDir::new();
+        log_twice(dir.create(Tree::new));
+        let mut database = Database::<Content>::open(dir.path()).unwrap();
+        assert_contains(&database, 0..3000);
+        insert(&mut database, 3000..4000);
+        database.log().unwrap();
+        drop(database);
+        let database = Database::<Content>::open(dir.path()).unwrap();
+        assert_contains(&database, 0..4000);
+    }
+
+    #[test]
+    fn replay_encrypted_log() {
+        let dir = TestDir::new();
+        log_twice(
+            Database::create_encrypted(dir.path(), SECRET, |database| Single(Tree::new(database)))
+                .unwrap(),
+        );
+        let database = Database::<Content>::open_encrypted(dir.path(), SECRET).unwrap();
+        assert_contains(&database, 0..3000);
+    }
+
+    #[test]
+    fn discard_torn_record() {
+        let dir = TestDir::new();
+        log_twice(dir.create(Tree::new));
+        let wal = OpenOptions::new()
+            .write(true)
+            .open(dir.path().with_extension("wal"))
+            .unwrap();
+        let len = wal.metadata().unwrap().len();
+        wal.set_len(len - 100).unwrap();
+        drop(wal);
+        let database = Database::<Content>::open(dir.path()).unwrap();
+        assert_contains(&database, 0..2000);
+    }
+}
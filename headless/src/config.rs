@@ -0,0 +1,207 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use server::ServerConfig;
+use structopt::StructOpt;
+
+use crate::error::Error;
+
+const DEFAULT_CONFIG: &str = "server.toml";
+
+/// Settings of the configuration file given on the command line or in the environment. The
+/// command line takes precedence over the environment, which takes precedence over the file.
+/// Trusted keys and insecure authentication can only be given on the command line.
+#[derive(StructOpt)]
+pub struct ConfigOverrides {
+    #[structopt(long, env("WOSIM_SERVER_NAME"))]
+    name: Option<String>,
+    #[structopt(long, env("WOSIM_SERVER_DESCRIPTION"))]
+    description: Option<String>,
+    /// Simulation ticks per second
+    #[structopt(long, env("WOSIM_TICK_RATE"))]
+    tick_rate: Option<u32>,
+    /// Updates pushed to players per second, which has to divide the tick rate
+    #[structopt(long, env("WOSIM_PUSH_RATE"))]
+    push_rate: Option<u32>,
+    #[structopt(long, env("WOSIM_MAX_PLAYERS"))]
+    max_players: Option<usize>,
+    /// Slots in addition to the maximum number of players that only admins may take
+    #[structopt(long, env("WOSIM_RESERVED_SLOTS"))]
+    reserved_slots: Option<usize>,
+    /// Message of the day, sent to players when they join
+    #[structopt(long, env("WOSIM_MOTD"))]
+    motd: Option<String>,
+    /// Seconds between saves of the world, 0 to save only when the world changed enough
+    #[structopt(long, env("WOSIM_AUTOSAVE_INTERVAL"))]
+    autosave_interval: Option<u64>,
+    /// Saves the world after this many updates
+    #[structopt(long, env("WOSIM_AUTOSAVE_AFTER_UPDATES"))]
+    autosave_after_updates: Option<u64>,
+    /// Maximum number of restorable snapshots of the world
+    #[structopt(long, env("WOSIM_RETENTION_COUNT"))]
+    retention_count: Option<usize>,
+    /// Minimum seconds between two restorable snapshots of the world
+    #[structopt(long, env("WOSIM_RETENTION_INTERVAL"))]
+    retention_interval: Option<u64>,
+    /// Milliseconds between appends to the write-ahead log
+    #[structopt(long, env("WOSIM_LOG_INTERVAL_MS"))]
+    log_interval_ms: Option<u64>,
+    /// Capacity of the message channels between the connections and the main loop
+    #[structopt(long, env("WOSIM_CHANNEL_BOUND"))]
+    channel_bound: Option<usize>,
+    /// Distance within which players see each other
+    #[structopt(long, env("WOSIM_VIEW_RADIUS"))]
+    view_radius: Option<f32>,
+    /// Distance beyond which a seen player is hidden again
+    #[structopt(long, env("WOSIM_HIDE_RADIUS"))]
+    hide_radius: Option<f32>,
+    /// Trusts tokens signed by an issuer, given as name=base64(public key)
+    #[structopt(long = "trusted-key", parse(try_from_str = parse_parameter))]
+    trusted_keys: Vec<(String, String)>,
+    /// Accepts unsigned tokens, so that anyone can join as anyone
+    #[structopt(long, conflicts_with("trusted-keys"))]
+    insecure: bool,
+}
+
+impl ConfigOverrides {
+    pub fn apply(self, config: &mut ServerConfig) {
+        config.name = self.name.or_else(|| config.name.take());
+        if let Some(description) = self.description {
+            config.description = description;
+        }
+        config.tick_rate = self.tick_rate.unwrap_or(config.tick_rate);
+        config.push_rate = self.push_rate.unwrap_or(config.push_rate);
+        config.max_players = self.max_players.or(config.max_players);
+        config.reserved_slots = self.reserved_slots.unwrap_or(config.reserved_slots);
+        config.motd = self.motd.or_else(|| config.motd.take());
+        config.autosave.interval = self.autosave_interval.or(config.autosave.interval);
+        config.autosave.after_updates = self
+            .autosave_after_updates
+            .or(config.autosave.after_updates);
+        config.retention.count = self.retention_count.unwrap_or(config.retention.count);
+        config.retention.interval = self.retention_interval.unwrap_or(config.retention.interval);
+        config.log_interval_ms = self.log_interval_ms.unwrap_or(config.log_interval_ms);
+        config.channel_bound = self.channel_bound.unwrap_or(config.channel_bound);
+        config.view_radius = self.view_radius.unwrap_or(config.view_radius);
+        config.hide_radius = self.hide_radius.unwrap_or(config.hide_radius);
+        config.auth.insecure |= self.insecure;
+        config.auth.trusted_keys.extend(self.trusted_keys);
+    }
+}
+
+pub fn parse_parameter(s: &str) -> Result<(String, String), String> {
+    match s.find('=') {
+        Some(index) => Ok((s[..index].to_owned(), s[index + 1..].to_owned())),
+        None => Err(format!("expected name=value, found '{}'", s)),
+    }
+}
+
+/// Reads the server configuration, from `DEFAULT_CONFIG` if no path is given and it exists.
+pub fn load_config(path: Option<PathBuf>) -> Result<ServerConfig, Error> {
+    let path = match path {
+        Some(path) => path,
+        None if Path::new(DEFAULT_CONFIG).exists() => PathBuf::from(DEFAULT_CONFIG),
+        None => return Ok(ServerConfig::default()),
+    };
+    let config = fs::read_to_string(path).map_err(Error::ReadConfig)?;
+    parse_config(&config)
+}
+
+fn parse_config(config: &str) -> Result<ServerConfig, Error> {
+    toml::from_str(config).map_err(Error::ParseConfig)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+name = "test"
+tick_rate = 20
+push_rate = 10
+motd = "from the file"
+view_radius = 80
+
+[autosave]
+interval = 600
+
+[retention]
+count = 5
+
+[auth]
+trusted_keys = { hub = "a2V5" }
+"#;
+
+    #[test]
+    fn parse_file() {
+        let config = parse_config(CONFIG).unwrap();
+        assert_eq!(config.name.as_deref(), Some("test"));
+        assert_eq!(config.tick_rate, 20);
+        assert_eq!(config.push_rate, 10);
+        assert_eq!(config.motd.as_deref(), Some("from the file"));
+        assert_eq!(config.view_radius, 80.0);
+        assert_eq!(config.autosave.interval, Some(600));
+        assert_eq!(config.retention.count, 5);
+        assert_eq!(config.auth.trusted_keys["hub"], "a2V5");
+        assert!(!config.auth.insecure);
+    }
+
+    #[test]
+    fn missing_settings_take_defaults() {
+        let config = parse_config(CONFIG).unwrap();
+        let defaults = ServerConfig::default();
+        assert_eq!(config.hide_radius, defaults.hide_radius);
+        assert_eq!(
+            config.autosave.after_updates,
+            defaults.autosave.after_updates
+        );
+        assert_eq!(config.retention.interval, defaults.retention.interval);
+        assert_eq!(parse_config("").unwrap().tick_rate, defaults.tick_rate);
+        assert!(parse_config("tick_rate = \"fast\"").is_err());
+    }
+
+    /// The only test that sets variables of the environment, since tests run in parallel.
+    #[test]
+    fn command_line_overrides_environment_overrides_file() {
+        let mut config = parse_config(CONFIG).unwrap();
+        env::set_var("WOSIM_PUSH_RATE", "5");
+        env::set_var("WOSIM_MOTD", "from the environment");
+        env::set_var("WOSIM_RETENTION_COUNT", "7");
+        let overrides = ConfigOverrides::from_iter_safe(&[
+            "serve",
+            "--motd",
+            "from the command line",
+            "--autosave-interval",
+            "0",
+            "--hide-radius",
+            "90",
+        ]);
+        env::remove_var("WOSIM_PUSH_RATE");
+        env::remove_var("WOSIM_MOTD");
+        env::remove_var("WOSIM_RETENTION_COUNT");
+        overrides.unwrap().apply(&mut config);
+        assert_eq!(config.tick_rate, 20);
+        assert_eq!(config.push_rate, 5);
+        assert_eq!(config.motd.as_deref(), Some("from the command line"));
+        assert_eq!(config.retention.count, 7);
+        assert_eq!(config.autosave.interval, Some(0));
+        assert_eq!(config.view_radius, 80.0);
+        assert_eq!(config.hide_radius, 90.0);
+        assert_eq!(config.name.as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn trusted_keys_are_added() {
+        let mut config = parse_config(CONFIG).unwrap();
+        let overrides =
+            ConfigOverrides::from_iter_safe(&["serve", "--trusted-key", "other=b3RoZXI="]);
+        overrides.unwrap().apply(&mut config);
+        assert_eq!(config.auth.trusted_keys.len(), 2);
+        assert_eq!(config.auth.trusted_keys["other"], "b3RoZXI=");
+        assert!(ConfigOverrides::from_iter_safe(&["serve", "--trusted-key", "other"]).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{stdin, BufRead},
+    sync::mpsc::{channel, Receiver},
+    thread,
+    time::Duration,
+};
+
+use server::{AdminError, Service};
+
+/// Seconds the server counts down before it shuts down unless told otherwise.
+const DEFAULT_COUNTDOWN: u64 = 10;
+
+const HELP: &str = "\
+players                           lists the players who are online
+kick <player> [reason]            disconnects a player
+ban <player> [reason]             bans a player forever
+tempban <player> <secs> [reason]  bans a player for some seconds
+say <message>                     sends a chat message to all players
+snapshot                          takes a snapshot of the world database
+stats                             shows statistics of the world database
+stop [secs]                       shuts the server down after a countdown
+help                              shows this help";
+
+/// Reads admin commands from the standard input on a separate thread.
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+/// Whether the server should go on after a command.
+pub enum Outcome {
+    Continue,
+    Stop,
+}
+
+impl Console {
+    pub fn spawn() -> Self {
+        let (tx, lines) = channel();
+        thread::spawn(move || {
+            for line in stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if tx.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+        Self { lines }
+    }
+
+    /// Returns the next command the admin entered, if any.
+    pub fn next_line(&self) -> Option<String> {
+        self.lines.try_recv().ok()
+    }
+}
+
+/// An admin command, parsed from a line of the console.
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    Nothing,
+    Players,
+    Kick {
+        player: &'a str,
+        reason: &'a str,
+    },
+    /// Bans a player for the given number of seconds, or forever.
+    Ban {
+        player: &'a str,
+        reason: &'a str,
+        duration: Option<u64>,
+    },
+    Say(&'a str),
+    Snapshot,
+    Stats,
+    Stop(Duration),
+    Help,
+}
+
+/// Why a line of the console is not a valid command.
+#[derive(Debug, PartialEq)]
+enum ParseError<'a> {
+    UnknownCommand(&'a str),
+    MissingPlayer,
+    MissingMessage,
+    InvalidDuration(&'a str),
+    InvalidCountdown(&'a str),
+}
+
+impl Display for ParseError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownCommand(command) => {
+                write!(f, "unknown command '{}', try help", command)
+            }
+            ParseError::MissingPlayer => write!(f, "expected the name or uuid of a player"),
+            ParseError::MissingMessage => write!(f, "expected a message"),
+            ParseError::InvalidDuration(duration) => write!(
+                f,
+                "expected the ban duration in seconds, found '{}'",
+                duration
+            ),
+            ParseError::InvalidCountdown(countdown) => write!(
+                f,
+                "expected the countdown in seconds, found '{}'",
+                countdown
+            ),
+        }
+    }
+}
+
+/// Runs an admin command against the service and prints its result.
+pub async fn execute(service: &Service, line: &str) -> Outcome {
+    match parse(line) {
+        Ok(command) => match run(service, command).await {
+            Ok(outcome) => return outcome,
+            Err(error) => println!("error: {}", error),
+        },
+        Err(error) => println!("{}", error),
+    }
+    Outcome::Continue
+}
+
+fn parse(line: &str) -> Result<Command<'_>, ParseError<'_>> {
+    let (command, arguments) = split(line.trim());
+    let player = |arguments| match split(arguments) {
+        ("", _) => Err(ParseError::MissingPlayer),
+        split => Ok(split),
+    };
+    match command {
+        "" => Ok(Command::Nothing),
+        "players" => Ok(Command::Players),
+        "kick" => {
+            let (player, reason) = player(arguments)?;
+            Ok(Command::Kick { player, reason })
+        }
+        "ban" => {
+            let (player, reason) = player(arguments)?;
+            Ok(Command::Ban {
+                player,
+                reason,
+                duration: None,
+            })
+        }
+        "tempban" => {
+            let (player, arguments) = player(arguments)?;
+            let (duration, reason) = split(arguments);
+            let duration = duration
+                .parse()
+                .map_err(|_| ParseError::InvalidDuration(duration))?;
+            Ok(Command::Ban {
+                player,
+                reason,
+                duration: Some(duration),
+            })
+        }
+        "say" if arguments.is_empty() => Err(ParseError::MissingMessage),
+        "say" => Ok(Command::Say(arguments)),
+        "snapshot" => Ok(Command::Snapshot),
+        "stats" => Ok(Command::Stats),
+        "stop" if arguments.is_empty() => Ok(Command::Stop(Duration::from_secs(DEFAULT_COUNTDOWN))),
+        "stop" => arguments
+            .parse()
+            .map(|countdown| Command::Stop(Duration::from_secs(countdown)))
+            .map_err(|_| ParseError::InvalidCountdown(arguments)),
+        "help" => Ok(Command::Help),
+        _ => Err(ParseError::UnknownCommand(command)),
+    }
+}
+
+async fn run(service: &Service, command: Command<'_>) -> Result<Outcome, AdminError> {
+    match command {
+        Command::Nothing => {}
+        Command::Players => players(service).await?,
+        Command::Kick { player, reason } => {
+            service.kick(player.to_owned(), reason.to_owned()).await?
+        }
+        Command::Ban {
+            player,
+            reason,
+            duration,
+        } => {
+            service
+                .ban(player.to_owned(), reason.to_owned(), duration)
+                .await?
+        }
+        Command::Say(text) => service.broadcast(text.to_owned()).await?,
+        Command::Snapshot => {
+            let duration = service.snapshot().await?;
+            println!("took a snapshot in {:?}", duration);
+        }
+        Command::Stats => {
+            let stats = service.database_stats().await?;
+            println!("versions: {}", stats.versions);
+            println!("chunks: {}", stats.chunks);
+            println!("players: {}", stats.players);
+            println!("profiles: {}", stats.profiles);
+            println!("chat messages: {}", stats.chat_messages);
+        }
+        Command::Stop(countdown) => {
+            if let Err(error) = service.count_down(countdown).await {
+                println!("error: {}", error);
+            }
+            return Ok(Outcome::Stop);
+        }
+        Command::Help => println!("{}", HELP),
+    }
+    Ok(Outcome::Continue)
+}
+
+async fn players(service: &Service) -> Result<(), AdminError> {
+    let players = service.online_players().await?;
+    println!("{} players online", players.len());
+    for player in players {
+        let connection = match player.stats {
+            Some(stats) => format!(
+                "rtt {}ms, sent {} bytes, received {} bytes",
+                stats.path.rtt.as_millis(),
+                stats.udp_tx.bytes,
+                stats.udp_rx.bytes
+            ),
+            None => "local".to_owned(),
+        };
+        println!(
+            "{}\t{}\t{}\tonline for {}s\t{}",
+            player.name,
+            player.uuid,
+            player.role,
+            player.online_for.as_secs(),
+            connection
+        );
+    }
+    Ok(())
+}
+
+/// Splits off the first word of the arguments.
+fn split(arguments: &str) -> (&str, &str) {
+    match arguments.find(' ') {
+        Some(index) => (&arguments[..index], arguments[index + 1..].trim()),
+        None => (arguments, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use server::{create_world, GeneratorSettings, ServerConfig};
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[test]
+    fn parse_kick() {
+        assert_eq!(
+            parse("kick alice"),
+            Ok(Command::Kick {
+                player: "alice",
+                reason: ""
+            })
+        );
+        assert_eq!(
+            parse("  kick alice  spamming the chat "),
+            Ok(Command::Kick {
+                player: "alice",
+                reason: "spamming the chat"
+            })
+        );
+        assert_eq!(parse("kick"), Err(ParseError::MissingPlayer));
+        assert_eq!(parse("kick "), Err(ParseError::MissingPlayer));
+    }
+
+    #[test]
+    fn parse_ban() {
+        assert_eq!(
+            parse("ban bob griefing"),
+            Ok(Command::Ban {
+                player: "bob",
+                reason: "griefing",
+                duration: None
+            })
+        );
+        assert_eq!(
+            parse("tempban bob 3600 griefing"),
+            Ok(Command::Ban {
+                player: "bob",
+                reason: "griefing",
+                duration: Some(3600)
+            })
+        );
+        assert_eq!(parse("ban"), Err(ParseError::MissingPlayer));
+        assert_eq!(parse("tempban bob"), Err(ParseError::InvalidDuration("")));
+        assert_eq!(
+            parse("tempban bob soon griefing"),
+            Err(ParseError::InvalidDuration("soon"))
+        );
+    }
+
+    #[test]
+    fn parse_broadcast() {
+        assert_eq!(
+            parse("say  restart in 5 minutes "),
+            Ok(Command::Say("restart in 5 minutes"))
+        );
+        assert_eq!(parse("say"), Err(ParseError::MissingMessage));
+    }
+
+    #[test]
+    fn parse_other_commands() {
+        assert_eq!(parse(""), Ok(Command::Nothing));
+        assert_eq!(
+            parse("stop"),
+            Ok(Command::Stop(Duration::from_secs(DEFAULT_COUNTDOWN)))
+        );
+        assert_eq!(parse("stop 0"), Ok(Command::Stop(Duration::from_secs(0))));
+        assert_eq!(parse("stop now"), Err(ParseError::InvalidCountdown("now")));
+        assert_eq!(parse("kik alice"), Err(ParseError::UnknownCommand("kik")));
+    }
+
+    #[test]
+    fn players_who_are_not_online() {
+        let dir = tempfile::tempdir().unwrap();
+        let world = dir.path().join("world.db");
+        let settings = GeneratorSettings {
+            generator: "flat".to_owned(),
+            size: 16,
+            ..GeneratorSettings::default()
+        };
+        create_world(&world, &settings, None).unwrap();
+        let mut config = ServerConfig {
+            name: Some("test".to_owned()),
+            world,
+            ..ServerConfig::default()
+        };
+        config.auth.insecure = true;
+        Runtime::new().unwrap().block_on(async {
+            let service = Service::new(None, config).unwrap();
+            let kick = parse("kick ghost").unwrap();
+            assert!(matches!(
+                run(&service, kick).await,
+                Err(AdminError::NotOnline(name)) if name == "ghost"
+            ));
+            let ban = parse("ban ghost").unwrap();
+            assert!(matches!(
+                run(&service, ban).await,
+                Err(AdminError::UnknownPlayer(name)) if name == "ghost"
+            ));
+            let uuid = uuid::Uuid::from_u128(1).to_string();
+            let ban = Command::Ban {
+                player: &uuid,
+                reason: "",
+                duration: None,
+            };
+            assert!(matches!(run(&service, ban).await, Ok(Outcome::Continue)));
+            let say = parse("say hello").unwrap();
+            assert!(matches!(run(&service, say).await, Ok(Outcome::Continue)));
+            service.stop().await.unwrap();
+        });
+    }
+}
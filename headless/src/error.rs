@@ -3,7 +3,7 @@ use vulkan::ApiResult;
 
 use std::io;
 
-use server::CreateServiceError;
+use server::{CreateServiceError, CreateWorldError, TokenError};
 
 #[derive(Debug)]
 pub enum Error {
@@ -12,8 +12,12 @@ pub enum Error {
     OpenServer(OpenError),
     NoSuitableDeviceFound,
     CreateService(CreateServiceError),
+    CreateWorld(CreateWorldError),
     SelfSign(SelfSignError),
     FromPem(FromPemError),
+    Token(TokenError),
+    ReadConfig(io::Error),
+    ParseConfig(toml::de::Error),
 }
 
 impl From<vulkan::Error> for Error {
@@ -1,26 +1,53 @@
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 use std::{
     ffi::CString,
-    path::PathBuf,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::vulkan::DeviceCandidate;
 use ::vulkan::Instance;
+use config::{load_config, parse_parameter, ConfigOverrides};
+use console::{Console, Outcome};
 use error::Error;
 use net::{from_pem, local_server_address, self_signed, Server, ServerConfiguration};
 use semver::Version;
-use server::{create_world, Service};
-use structopt::StructOpt;
+use server::{
+    create_world, generate_signing_key, public_key, restore_world, set_world_access, sign_token,
+    world_access, world_versions, GeneratorSettings, Role, ServerConfig, Service, TokenClaims,
+};
+use structopt::{clap::AppSettings, StructOpt};
 use tokio::{runtime::Runtime, time::sleep};
 use util::iterator::MaxOkFilterMap;
+use uuid::Uuid;
 
+mod config;
+mod console;
 mod error;
 mod vulkan;
 
+#[derive(StructOpt)]
+struct Options {
+    /// Encrypts the world database with a key derived from this secret
+    #[structopt(long, env("WOSIM_WORLD_SECRET"), hide_env_values = true)]
+    world_secret: Option<String>,
+    /// Server configuration in TOML, server.toml if it exists and no other file is given
+    #[structopt(long, env("WOSIM_CONFIG"))]
+    config: Option<PathBuf>,
+    /// Path of the world database, overriding the configuration
+    #[structopt(long, env("WOSIM_WORLD"))]
+    world: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
 #[derive(StructOpt)]
 enum Command {
     Serve {
@@ -32,19 +59,151 @@ enum Command {
         private_key: Option<PathBuf>,
         #[structopt(long)]
         use_mdns: bool,
+        #[structopt(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Generates a key for signing tokens and prints its public key
+    GenerateKey {
+        /// File to write the signing key to, which must not exist yet
+        path: PathBuf,
+    },
+    /// Prints a token signed with a key
+    IssueToken {
+        /// File with the signing key
+        #[structopt(long)]
+        key: PathBuf,
+        /// Name servers know the key by
+        #[structopt(long)]
+        issuer: String,
+        #[structopt(long)]
+        uuid: Uuid,
+        #[structopt(long)]
+        name: String,
+        /// Seconds until the token expires
+        #[structopt(long, default_value = "86400")]
+        valid_for: u64,
+    },
+    /// Creates a new world, which is the same for the same generator settings
+    Create {
+        /// One of empty, flat, perlin and heightmap
+        #[structopt(long, default_value = "perlin")]
+        generator: String,
+        #[structopt(long, default_value = "0")]
+        seed: u32,
+        /// Edge length of the generated area in blocks
+        #[structopt(long, default_value = "41")]
+        size: u32,
+        /// Generator specific parameter as name=value
+        #[structopt(long = "param", parse(try_from_str = parse_parameter))]
+        parameters: Vec<(String, String)>,
+    },
+    /// Shows or changes roles, bans and the whitelist of the world
+    Access(AccessCommand),
+    #[structopt(setting = AppSettings::DisableVersion)]
+    Restore {
+        #[structopt(long)]
+        version: Option<u64>,
+    },
+}
+
+#[derive(StructOpt)]
+enum AccessCommand {
+    Show,
+    /// Sets the role of a user to player, moderator or admin
+    Role {
+        uuid: Uuid,
+        role: Role,
+    },
+    Ban {
+        uuid: Uuid,
+        #[structopt(long, default_value = "")]
+        reason: String,
+        /// Seconds until the ban ends, forever if not given
+        #[structopt(long)]
+        duration: Option<u64>,
+    },
+    Unban {
+        uuid: Uuid,
+    },
+    /// Lets only whitelisted users, moderators and admins join
+    EnableWhitelist,
+    /// Lets everyone join who is not banned
+    DisableWhitelist,
+    /// Adds a user to the whitelist, enabling it if necessary
+    Whitelist {
+        uuid: Uuid,
+    },
+    Unwhitelist {
+        uuid: Uuid,
     },
-    Create,
+}
+
+impl AccessCommand {
+    fn run(self, world: &Path, secret: Option<&[u8]>) -> Result<(), Error> {
+        let mut access = world_access(world, secret)?;
+        match self {
+            AccessCommand::Show => {
+                for (uuid, role) in &access.roles {
+                    println!("{}\t{}", uuid, role);
+                }
+                for (uuid, ban) in &access.bans {
+                    match ban.until {
+                        Some(until) => println!("{}\tbanned until {}: {}", uuid, until, ban.reason),
+                        None => println!("{}\tbanned: {}", uuid, ban.reason),
+                    }
+                }
+                match &access.whitelist {
+                    Some(whitelist) => {
+                        for uuid in whitelist {
+                            println!("{}\twhitelisted", uuid);
+                        }
+                    }
+                    None => println!("whitelist disabled"),
+                }
+                return Ok(());
+            }
+            AccessCommand::Role { uuid, role } => access.set_role(uuid, role),
+            AccessCommand::Ban {
+                uuid,
+                reason,
+                duration,
+            } => access.ban(uuid, reason, duration),
+            AccessCommand::Unban { uuid } => {
+                access.bans.remove(&uuid);
+            }
+            AccessCommand::EnableWhitelist => {
+                access.whitelist.get_or_insert_with(Default::default);
+            }
+            AccessCommand::DisableWhitelist => access.whitelist = None,
+            AccessCommand::Whitelist { uuid } => {
+                access
+                    .whitelist
+                    .get_or_insert_with(Default::default)
+                    .insert(uuid);
+            }
+            AccessCommand::Unwhitelist { uuid } => {
+                if let Some(whitelist) = &mut access.whitelist {
+                    whitelist.remove(&uuid);
+                }
+            }
+        }
+        access.remove_expired_bans();
+        set_world_access(world, &access, secret)?;
+        Ok(())
+    }
 }
 
 impl Command {
-    fn run(self) -> Result<(), Error> {
+    fn run(self, secret: Option<&[u8]>, mut config: ServerConfig) -> Result<(), Error> {
         match self {
             Command::Serve {
                 port,
                 certificate_chain,
                 private_key,
                 use_mdns,
+                overrides,
             } => {
+                overrides.apply(&mut config);
                 let running = Arc::new(AtomicBool::new(true));
                 let r = running.clone();
                 ctrlc::set_handler(move || {
@@ -65,7 +224,8 @@ impl Command {
                         .max_ok_filter_map(DeviceCandidate::new)?
                         .ok_or(Error::NoSuitableDeviceFound)?
                         .create()?;
-                    let service = Arc::new(Service::new().map_err(Error::CreateService)?);
+                    let service =
+                        Arc::new(Service::new(secret, config).map_err(Error::CreateService)?);
                     let (certificate_chain, private_key) = if let Some(certificate_chain) =
                         certificate_chain
                     {
@@ -83,20 +243,103 @@ impl Command {
                         },
                     );
                     server.open().map_err(Error::OpenServer)?;
-                    while running.load(Ordering::SeqCst) {
-                        sleep(Duration::from_millis(10)).await;
+                    let admin_console = Console::spawn();
+                    loop {
+                        while running.load(Ordering::SeqCst) {
+                            if let Some(line) = admin_console.next_line() {
+                                if let Outcome::Stop = console::execute(&service, &line).await {
+                                    break;
+                                }
+                            }
+                            sleep(Duration::from_millis(10)).await;
+                        }
+                        match service.stop().await {
+                            Ok(()) => break,
+                            Err(error) => {
+                                let cause = std::error::Error::source(&error)
+                                    .map(|source| format!(": {}", source))
+                                    .unwrap_or_default();
+                                println!("error: {}{}, the server keeps running", error, cause);
+                                running.store(true, Ordering::SeqCst);
+                            }
+                        }
                     }
                     server.close();
-                    let _ = service.stop().await;
                     Ok(())
                 })
             }
-            Command::Create => create_world().map_err(Error::Io),
+            Command::Create {
+                generator,
+                seed,
+                size,
+                parameters,
+            } => {
+                let settings = GeneratorSettings {
+                    generator,
+                    seed,
+                    size,
+                    parameters: parameters.into_iter().collect(),
+                };
+                create_world(&config.world, &settings, secret).map_err(Error::CreateWorld)
+            }
+            Command::GenerateKey { path } => {
+                let key = generate_signing_key().map_err(Error::Token)?;
+                let mut options = OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                options.mode(0o600);
+                options.open(path)?.write_all(&key)?;
+                println!(
+                    "{}",
+                    base64::encode(public_key(&key).map_err(Error::Token)?)
+                );
+                Ok(())
+            }
+            Command::IssueToken {
+                key,
+                issuer,
+                uuid,
+                name,
+                valid_for,
+            } => {
+                let expires = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+                    + valid_for;
+                let claims = TokenClaims {
+                    uuid,
+                    name,
+                    expires,
+                    issuer,
+                };
+                let token = sign_token(&fs::read(key)?, &claims).map_err(Error::Token)?;
+                println!("{}", token);
+                Ok(())
+            }
+            Command::Access(command) => command.run(&config.world, secret),
+            Command::Restore { version } => {
+                if let Some(version) = version {
+                    restore_world(&config.world, version, secret)?;
+                } else {
+                    for version in world_versions(&config.world, secret)? {
+                        let age = version.time.elapsed().unwrap_or_default();
+                        println!("{}\t{}s ago", version.version, age.as_secs());
+                    }
+                }
+                Ok(())
+            }
         }
     }
 }
 
 fn main() -> Result<(), Error> {
     env_logger::init();
-    Command::from_args().run()
+    let options = Options::from_args();
+    let secret = options.world_secret.as_ref().map(String::as_bytes);
+    let mut config = load_config(options.config)?;
+    if let Some(world) = options.world {
+        config.world = world;
+    }
+    options.command.run(secret, config)
 }
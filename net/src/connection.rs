@@ -38,6 +38,17 @@ impl<M: Message> Connection<M> {
     pub fn stats(&self) -> Option<remote::ConnectionStats> {
         self.remote.as_ref().map(send::Connection::stats)
     }
+
+    /// Closes a remote connection with an application error code and reason. Returns false for
+    /// local connections, which cannot be closed.
+    pub fn close(&self, code: u32, reason: &str) -> bool {
+        if let Some(remote) = self.remote.as_ref() {
+            remote.close(code, reason);
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl<M: Message + 'static> Clone for Connection<M> {
@@ -15,3 +15,7 @@ pub use remote::*;
 pub use resolver::*;
 pub use service::*;
 pub use verification::*;
+
+/// Application error code of connections whose token is not accepted, unless the service chooses
+/// another one, see `Service::rejection`. Services should keep their own codes apart from it.
+pub const CLOSE_REJECTED: u32 = 6;
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use eyre::{eyre, Context};
 use futures::StreamExt;
 use log::error;
-use quinn::{Connecting, Incoming, NewConnection};
+use quinn::{Connecting, Incoming, NewConnection, VarInt};
 use tokio::spawn;
 
 use crate::{send, AuthToken, Connection, Service};
@@ -19,17 +19,19 @@ pub async fn listen<S: Service>(mut incoming: Incoming, service: Arc<S>) {
     }
 }
 
+/// Reads the token from the first stream the client opens and answers by finishing the stream
+/// if the token is accepted, or by closing the connection with the rejection of the service.
 async fn accept<S: Service>(connecting: Connecting, service: Arc<S>) -> eyre::Result<()> {
     let NewConnection {
         connection,
-        bi_streams,
-        mut uni_streams,
+        mut bi_streams,
+        uni_streams,
         datagrams,
         ..
     } = connecting
         .await
         .wrap_err("could not establish new connection")?;
-    let recv = uni_streams
+    let (mut send, recv) = bi_streams
         .next()
         .await
         .ok_or_else(|| eyre!("no token was received"))?
@@ -39,10 +41,19 @@ async fn accept<S: Service>(connecting: Connecting, service: Arc<S>) -> eyre::Re
         .await
         .wrap_err("could not read token")?;
     let token = std::str::from_utf8(&buffer).wrap_err("token must be utf-8 encoded")?;
-    let connection = Connection::remote(send::Connection::new(connection));
-    let receiver = service
-        .authenticate(connection, AuthToken::Remote(token))
-        .wrap_err("token authentication failed")?;
+    let remote = Connection::remote(send::Connection::new(connection.clone()));
+    let receiver = match service.authenticate(remote, AuthToken::Remote(token)) {
+        Ok(receiver) => receiver,
+        Err(error) => {
+            let rejection = service.rejection(&error);
+            connection.close(
+                VarInt::from_u32(rejection.code),
+                rejection.reason.as_bytes(),
+            );
+            return Err(error).wrap_err("token authentication failed");
+        }
+    };
+    send.finish().await.wrap_err("could not accept token")?;
     super::connection(bi_streams, uni_streams, datagrams, receiver).await;
     Ok(())
 }
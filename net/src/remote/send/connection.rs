@@ -59,6 +59,11 @@ impl Connection {
         self.0.stats()
     }
 
+    /// Closes the connection, telling the peer why with an application error code and reason.
+    pub fn close(&self, code: u32, reason: &str) {
+        self.0.close(VarInt::from_u32(code), reason.as_bytes())
+    }
+
     async fn open_uni(&self) -> Result<SendStream, Error> {
         let tx = self.0.open_uni().await?;
         Ok(Stream(tx, self.clone()))
@@ -8,14 +8,14 @@ use std::{
 
 use quinn::{
     ClientConfigBuilder, ConnectError, ConnectionError, Endpoint, EndpointError, NewConnection,
-    WriteError,
+    ReadError, ReadToEndError, WriteError,
 };
 use thiserror::Error;
 use tokio::{spawn, sync::mpsc};
 
 use crate::{
-    local_server_address, recv, self_signed, send, AuthToken, Connection, SelfSignError, Server,
-    ServerConfiguration, Service, Verification,
+    local_server_address, recv, self_signed, send, AuthToken, Connection, Rejection, SelfSignError,
+    Server, ServerConfiguration, Service, Verification,
 };
 
 const CHANNEL_BUFFER: usize = 16;
@@ -63,6 +63,10 @@ pub enum ResolveError<A: Error + 'static> {
     WriteTokenStream(#[source] WriteError),
     #[error("could not finish token stream")]
     FinishTokenStream(#[source] WriteError),
+    #[error("server rejected the connection: {0}")]
+    Rejected(Rejection),
+    #[error("could not read the answer to the token")]
+    ReadTokenAnswer(#[source] ReadToEndError),
     #[error("could not generate self-signed certificate")]
     SelfSign(#[from] SelfSignError),
 }
@@ -137,8 +141,8 @@ impl<S: Service> Resolver<S> {
                     .map_err(ResolveError::Connect)?
                     .await
                     .map_err(ResolveError::Connecting)?;
-                let mut send = connection
-                    .open_uni()
+                let (mut send, recv) = connection
+                    .open_bi()
                     .await
                     .map_err(ResolveError::OpenTokenStream)?;
                 send.write_all(token.as_bytes())
@@ -147,6 +151,18 @@ impl<S: Service> Resolver<S> {
                 send.finish()
                     .await
                     .map_err(ResolveError::FinishTokenStream)?;
+                match recv.read_to_end(0).await {
+                    Ok(_) => {}
+                    Err(ReadToEndError::Read(ReadError::ConnectionClosed(
+                        ConnectionError::ApplicationClosed(close),
+                    ))) => {
+                        return Err(ResolveError::Rejected(Rejection {
+                            code: close.error_code.into_inner() as u32,
+                            reason: String::from_utf8_lossy(&close.reason).into_owned(),
+                        }))
+                    }
+                    Err(error) => return Err(ResolveError::ReadTokenAnswer(error)),
+                }
                 spawn(recv::connection(bi_streams, uni_streams, datagrams, tx));
                 Ok((
                     Connection::remote(send::Connection::new(connection)),
@@ -1,10 +1,10 @@
 use std::error::Error;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display};
 
 use quinn::TransportConfig;
 use tokio::sync::mpsc;
 
-use crate::{AuthToken, Connection, Message};
+use crate::{AuthToken, Connection, Message, CLOSE_REJECTED};
 
 pub trait Service: Send + Sync + 'static {
     type AuthError: Error + Send + Sync + 'static;
@@ -31,9 +31,31 @@ pub trait Service: Send + Sync + 'static {
         TransportConfig::default()
     }
 
+    /// The application error code and reason a connection is closed with when its token is not
+    /// accepted.
+    fn rejection(&self, error: &Self::AuthError) -> Rejection {
+        Rejection {
+            code: CLOSE_REJECTED,
+            reason: error.to_string(),
+        }
+    }
+
     fn authentication_type(&self) -> &str;
 
     fn name(&self) -> &str;
 
     fn description(&self) -> &str;
 }
+
+/// Why a server closed a connection, as told to the client.
+#[derive(Clone, Debug)]
+pub struct Rejection {
+    pub code: u32,
+    pub reason: String,
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (code {})", self.reason, self.code)
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{state::unix_time, AuthenticationError};
+
+/// What a user may do on a server, in order of increasing privilege.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Role {
+    Player,
+    Moderator,
+    Admin,
+}
+
+/// What only some roles may do. Administration through the service is not checked against it,
+/// since only the operator of the server has access to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// Joining when the slots for players are taken.
+    ReservedSlot,
+}
+
+/// A ban of a user, which lasts until the given time or forever.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ban {
+    pub reason: String,
+    /// Seconds since the Unix epoch.
+    pub since: u64,
+    /// Seconds since the Unix epoch.
+    pub until: Option<u64>,
+}
+
+/// Who may join a server and with which role. Users without a role are players.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AccessControl {
+    pub roles: BTreeMap<Uuid, Role>,
+    pub bans: BTreeMap<Uuid, Ban>,
+    /// Only these users may join if set. Admins and moderators may always join.
+    pub whitelist: Option<BTreeSet<Uuid>>,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown role '{0}'")]
+pub struct ParseRoleError(String);
+
+impl Role {
+    pub fn has(self, permission: Permission) -> bool {
+        match permission {
+            Permission::ReservedSlot => self == Role::Admin,
+        }
+    }
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Player
+    }
+}
+
+impl FromStr for Role {
+    type Err = ParseRoleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "player" => Ok(Role::Player),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(ParseRoleError(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Player => "player",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+impl Ban {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.until.map_or(true, |until| now < until)
+    }
+}
+
+impl AccessControl {
+    pub fn role(&self, uuid: Uuid) -> Role {
+        self.roles.get(&uuid).copied().unwrap_or_default()
+    }
+
+    /// Returns the role of a user if it may join.
+    pub fn check(&self, uuid: Uuid) -> Result<Role, AuthenticationError> {
+        if let Some(ban) = self.bans.get(&uuid) {
+            if ban.is_active(unix_time()) {
+                return Err(AuthenticationError::Banned {
+                    reason: ban.reason.clone(),
+                    until: ban.until,
+                });
+            }
+        }
+        let role = self.role(uuid);
+        if let Some(whitelist) = &self.whitelist {
+            if role == Role::Player && !whitelist.contains(&uuid) {
+                return Err(AuthenticationError::NotWhitelisted);
+            }
+        }
+        Ok(role)
+    }
+
+    /// Sets the role of a user, forgetting it for players.
+    pub fn set_role(&mut self, uuid: Uuid, role: Role) {
+        if role == Role::Player {
+            self.roles.remove(&uuid);
+        } else {
+            self.roles.insert(uuid, role);
+        }
+    }
+
+    /// Bans a user for the given number of seconds or forever. Expiry times that would overflow
+    /// are clamped to the largest time.
+    pub fn ban(&mut self, uuid: Uuid, reason: String, duration: Option<u64>) {
+        let since = unix_time();
+        let until = duration.map(|duration| since.saturating_add(duration));
+        self.bans.insert(
+            uuid,
+            Ban {
+                reason,
+                since,
+                until,
+            },
+        );
+    }
+
+    /// Forgets bans that have run out.
+    pub fn remove_expired_bans(&mut self) {
+        let now = unix_time();
+        let expired: Vec<_> = self
+            .bans
+            .iter()
+            .filter(|(_, ban)| !ban.is_active(now))
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        for uuid in expired {
+            self.bans.remove(&uuid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_ban_does_not_overflow() {
+        let mut access = AccessControl::default();
+        let uuid = Uuid::from_u128(1);
+        access.ban(uuid, "reason".to_owned(), Some(u64::MAX));
+        assert_eq!(access.bans[&uuid].until, Some(u64::MAX));
+        assert!(matches!(
+            access.check(uuid),
+            Err(AuthenticationError::Banned { .. })
+        ));
+    }
+}
@@ -0,0 +1,156 @@
+use std::{io, time::Duration};
+
+use log::{error, info};
+use net::ConnectionStats;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    send_chat, state::unix_time, ChatMessage, Role, State, World, CLOSE_BANNED, CLOSE_KICKED,
+    CLOSE_SHUTDOWN,
+};
+
+/// Name shown for chat messages the server sends itself.
+pub const SERVER_NAME: &str = "Server";
+
+/// A connected player as shown on the admin console.
+#[derive(Debug)]
+pub struct OnlinePlayer {
+    pub uuid: Uuid,
+    pub name: String,
+    pub role: Role,
+    pub online_for: Duration,
+    /// Statistics of the connection, unless the player plays locally.
+    pub stats: Option<ConnectionStats>,
+}
+
+#[derive(Debug)]
+pub struct DatabaseStats {
+    pub versions: usize,
+    pub chunks: usize,
+    pub players: usize,
+    pub profiles: usize,
+    pub chat_messages: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum AdminError {
+    #[error("no player '{0}' is online")]
+    NotOnline(String),
+    #[error("'{0}' is neither a uuid nor the name of a player who is online")]
+    UnknownPlayer(String),
+    #[error("the local player cannot be kicked")]
+    LocalPlayer,
+    #[error("could not access the database")]
+    Database(#[source] io::Error),
+    #[error("the server has stopped")]
+    Stopped,
+}
+
+pub(crate) fn list_players(state: &State) -> Vec<OnlinePlayer> {
+    let mut players: Vec<_> = state
+        .observers
+        .iter()
+        .map(|(uuid, observer)| OnlinePlayer {
+            uuid: *uuid,
+            name: state.names.get(uuid).cloned().unwrap_or_default(),
+            role: observer.role,
+            online_for: observer.connected.elapsed(),
+            stats: observer.connection.stats(),
+        })
+        .collect();
+    players.sort_by(|a, b| a.name.cmp(&b.name));
+    players
+}
+
+/// Finds the uuid of a player who is online by name or uuid.
+fn online_player(state: &State, target: &str) -> Result<Uuid, AdminError> {
+    if let Ok(uuid) = Uuid::parse_str(target) {
+        if state.observers.contains_key(&uuid) {
+            return Ok(uuid);
+        }
+    }
+    state
+        .names
+        .iter()
+        .find(|(_, name)| name.as_str() == target)
+        .map(|(uuid, _)| *uuid)
+        .ok_or_else(|| AdminError::NotOnline(target.to_owned()))
+}
+
+/// Closes the connection of a player, who is then removed like any other disconnecting player.
+fn close(state: &State, uuid: Uuid, code: u32, reason: &str) -> Result<(), AdminError> {
+    let observer = state
+        .observers
+        .get(&uuid)
+        .ok_or_else(|| AdminError::NotOnline(uuid.to_string()))?;
+    if observer.connection.close(code, reason) {
+        Ok(())
+    } else {
+        Err(AdminError::LocalPlayer)
+    }
+}
+
+pub(crate) fn kick(state: &State, target: &str, reason: &str) -> Result<(), AdminError> {
+    let uuid = online_player(state, target)?;
+    close(state, uuid, CLOSE_KICKED, reason)?;
+    info!("Kicked user {}: {}", target, reason);
+    Ok(())
+}
+
+/// Bans a player by uuid, or by name if the player is online, and kicks the player if online.
+pub(crate) fn ban(
+    state: &mut State,
+    target: &str,
+    reason: &str,
+    duration: Option<u64>,
+) -> Result<(), AdminError> {
+    let uuid = match Uuid::parse_str(target) {
+        Ok(uuid) => uuid,
+        Err(_) => online_player(state, target)
+            .map_err(|_| AdminError::UnknownPlayer(target.to_owned()))?,
+    };
+    let world: &mut World = &mut state.database;
+    let mut access = world.access().map_err(AdminError::Database)?;
+    access.ban(uuid, reason.to_owned(), duration);
+    world.set_access(&access).map_err(AdminError::Database)?;
+    *state.access.write().unwrap() = access;
+    info!("Banned user {}: {}", target, reason);
+    if state.observers.contains_key(&uuid) {
+        if let Err(error) = close(state, uuid, CLOSE_BANNED, reason) {
+            error!("could not disconnect banned user {}: {}", target, error);
+        }
+    }
+    Ok(())
+}
+
+/// Sends a chat message from the server to all players.
+pub(crate) async fn broadcast(state: &mut State, text: String) {
+    let message = ChatMessage {
+        sender: Uuid::nil(),
+        name: SERVER_NAME.to_owned(),
+        time: unix_time(),
+        text,
+    };
+    send_chat(state, message).await;
+}
+
+/// Closes the connections of all remote players because the server shuts down.
+pub(crate) fn close_all(state: &State) {
+    for observer in state.observers.values() {
+        observer
+            .connection
+            .close(CLOSE_SHUTDOWN, "the server shut down");
+    }
+}
+
+pub(crate) fn database_stats(state: &State) -> DatabaseStats {
+    let world: &World = &state.database;
+    DatabaseStats {
+        versions: state.database.versions().len(),
+        chunks: world.chunks.read().iter().count(),
+        players: world.players.read().iter().count(),
+        profiles: world.profiles.read().keys().count(),
+        chat_messages: world.chat.read().keys().count(),
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    io,
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
+
+use db::Database;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+
+use crate::{Push, State, World};
+
+/// Delay before a failed save is retried, doubled for each further failure.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Upper bound for the delay before a failed save is retried.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// When the world is saved with a snapshot. Either condition triggers a save. The write-ahead log
+/// bounds the loss on a crash regardless, but snapshots keep the log short and make restorable
+/// versions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutosaveSettings {
+    /// Seconds between saves, where 0 turns periodic saves off.
+    pub interval: Option<u64>,
+    /// Number of updates after which the world is saved.
+    pub after_updates: Option<u64>,
+}
+
+/// Tells clients that the world was saved or that saving failed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveReport {
+    pub duration: Duration,
+    pub failed: bool,
+}
+
+/// The database of the world, which `save` hands to a blocking task while it takes a snapshot.
+pub(crate) struct WorldDatabase(Option<Database<World>>);
+
+pub(crate) struct Autosave {
+    settings: AutosaveSettings,
+    last_save: Instant,
+    updates: u64,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self {
+            interval: Some(5 * 60),
+            after_updates: None,
+        }
+    }
+}
+
+impl WorldDatabase {
+    pub fn new(database: Database<World>) -> Self {
+        Self(Some(database))
+    }
+}
+
+impl Deref for WorldDatabase {
+    type Target = Database<World>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().expect("the world is being saved")
+    }
+}
+
+impl DerefMut for WorldDatabase {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut().expect("the world is being saved")
+    }
+}
+
+impl Autosave {
+    pub fn new(settings: AutosaveSettings) -> Self {
+        Self {
+            settings,
+            last_save: Instant::now(),
+            updates: 0,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    pub fn count(&mut self, updates: usize) {
+        self.updates += updates as u64;
+    }
+
+    pub fn is_due(&self) -> bool {
+        if let Some(retry_at) = self.retry_at {
+            return Instant::now() >= retry_at;
+        }
+        let interval = self
+            .settings
+            .interval
+            .filter(|interval| *interval > 0)
+            .map_or(false, |interval| {
+                self.last_save.elapsed() >= Duration::from_secs(interval)
+            });
+        let updates = self
+            .settings
+            .after_updates
+            .map_or(false, |after_updates| self.updates >= after_updates);
+        interval || updates
+    }
+
+    fn saved(&mut self) {
+        self.last_save = Instant::now();
+        self.updates = 0;
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    fn failed(&mut self) {
+        let delay = (RETRY_DELAY * 2u32.saturating_pow(self.failures)).min(MAX_RETRY_DELAY);
+        self.failures += 1;
+        self.retry_at = Some(Instant::now() + delay);
+    }
+}
+
+/// Takes a snapshot of the world and tells the players about it. Failures are logged and the
+/// save is retried later. The snapshot waits for the views of the world to be dropped, so the
+/// database moves to a blocking task for it, which keeps the tasks that still hold views making
+/// progress on any runtime.
+pub(crate) async fn save(state: &mut State) -> io::Result<Duration> {
+    let start = Instant::now();
+    let mut database = state.database.0.take().expect("the world is being saved");
+    let (database, result) = spawn_blocking(move || {
+        let result = database.snapshot();
+        (database, result)
+    })
+    .await
+    .unwrap();
+    state.database.0 = Some(database);
+    let duration = start.elapsed();
+    match &result {
+        Ok(()) => {
+            info!("Saved the world in {:?}", duration);
+            state.autosave.saved();
+        }
+        Err(error) => {
+            error!("could not save the world: {}", error);
+            state.autosave.failed();
+        }
+    }
+    let report = SaveReport {
+        duration,
+        failed: result.is_err(),
+    };
+    for observer in state.observers.values() {
+        let _ = observer.sync_push.send(Push::Saved(report.clone())).await;
+    }
+    result.map(|()| duration)
+}
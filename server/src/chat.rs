@@ -0,0 +1,67 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::RateLimit;
+
+/// Longest chat message in characters.
+pub const MAX_CHAT_LENGTH: usize = 256;
+
+/// Number of recent chat messages kept in the world and sent to joining players.
+pub const CHAT_HISTORY: usize = 50;
+
+/// Messages a player may send at once after being quiet for a while.
+const CHAT_BURST: f32 = 5.0;
+
+/// Messages per second a player may send in the long run.
+const CHAT_RATE: f32 = 0.5;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub sender: Uuid,
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub time: u64,
+    pub text: String,
+}
+
+/// Returns the text to send for a message a player typed, or `None` if it is empty or too long.
+pub fn chat_text(text: &str) -> Option<&str> {
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > MAX_CHAT_LENGTH {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Limits the rate at which a player sends chat messages.
+pub struct ChatLimit(RateLimit);
+
+impl Default for ChatLimit {
+    fn default() -> Self {
+        Self(RateLimit::new(CHAT_RATE, CHAT_BURST))
+    }
+}
+
+impl ChatLimit {
+    /// Returns whether the player may send a message now, counting it if so.
+    pub fn allow(&mut self) -> bool {
+        self.0.update(Instant::now());
+        self.0.spend(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_empty_and_long_text() {
+        assert_eq!(chat_text("  hello "), Some("hello"));
+        assert_eq!(chat_text("   "), None);
+        assert!(chat_text(&"x".repeat(MAX_CHAT_LENGTH)).is_some());
+        assert!(chat_text(&"x".repeat(MAX_CHAT_LENGTH + 1)).is_none());
+    }
+}
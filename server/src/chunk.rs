@@ -0,0 +1,96 @@
+use bytemuck::{Pod, Zeroable};
+use db::Layout;
+use serde::{Deserialize, Serialize};
+
+use crate::Position;
+
+/// Edge length of a chunk in blocks.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Distance between neighboring blocks.
+pub const BLOCK_SPACING: f32 = 3.0;
+
+/// The position of a chunk in units of chunks.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Pod,
+    Zeroable,
+    Layout,
+    Serialize,
+    Deserialize,
+)]
+#[repr(C)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// The blocks of a chunk as one bit per block.
+#[derive(Clone, Copy, Debug, Pod, Zeroable, Layout, Serialize, Deserialize)]
+#[repr(C)]
+pub struct Chunk {
+    blocks: [u128; 32],
+}
+
+impl ChunkCoord {
+    /// The chunk containing the block at the given block coordinates.
+    pub fn of_block(x: i32, y: i32, z: i32) -> Self {
+        Self {
+            x: x.div_euclid(CHUNK_SIZE),
+            y: y.div_euclid(CHUNK_SIZE),
+            z: z.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// The chunk containing the given world position.
+    pub fn of_position(position: Position) -> Self {
+        let block = |v: f32| (v / BLOCK_SPACING).round() as i32;
+        Self::of_block(block(position.x), block(position.y), block(position.z))
+    }
+
+    pub fn distance_squared(self, other: Self) -> i64 {
+        let d = |a: i32, b: i32| (a as i64 - b as i64).pow(2);
+        d(self.x, other.x) + d(self.y, other.y) + d(self.z, other.z)
+    }
+}
+
+impl Chunk {
+    /// Adds the block at the given block coordinates, which must lie in the chunk.
+    pub fn insert(&mut self, x: i32, y: i32, z: i32) {
+        let index = Self::index(x, y, z);
+        self.blocks[index / 128] |= 1u128 << (index % 128);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|bits| *bits == 0)
+    }
+
+    /// The world positions of the blocks in this chunk at the given coordinate.
+    pub fn positions(&self, coord: ChunkCoord) -> impl Iterator<Item = Position> + '_ {
+        (0..CHUNK_SIZE.pow(3) as usize)
+            .filter(move |index| self.blocks[index / 128] & (1u128 << (index % 128)) != 0)
+            .map(move |index| {
+                let index = index as i32;
+                let block =
+                    |chunk: i32, local: i32| (chunk * CHUNK_SIZE + local) as f32 * BLOCK_SPACING;
+                Position {
+                    x: block(coord.x, index / (CHUNK_SIZE * CHUNK_SIZE)),
+                    y: block(coord.y, index / CHUNK_SIZE % CHUNK_SIZE),
+                    z: block(coord.z, index % CHUNK_SIZE),
+                }
+            })
+    }
+
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        let local = |v: i32| v.rem_euclid(CHUNK_SIZE);
+        ((local(x) * CHUNK_SIZE + local(y)) * CHUNK_SIZE + local(z)) as usize
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
+
+use db::Retention;
+use ring::signature::ED25519_PUBLIC_KEY_LEN;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Authentication, AutosaveSettings, CreateServiceError, ViewDistance, DEFAULT_TICK_RATE,
+};
+
+/// Settings of a server, as read from its configuration file. Missing settings take their
+/// defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Name shown to players looking for servers, the name of the current directory if not set.
+    pub name: Option<String>,
+    pub description: String,
+    /// Path of the world database.
+    pub world: PathBuf,
+    /// Simulation ticks per second.
+    pub tick_rate: u32,
+    /// Updates pushed to players per second, at most one per tick. It has to divide the tick
+    /// rate.
+    pub push_rate: u32,
+    /// Number of players who may be online at once, unlimited if not set.
+    pub max_players: Option<usize>,
+    /// Slots in addition to the maximum number of players that only admins may take.
+    pub reserved_slots: usize,
+    /// Message of the day, sent to players when they join.
+    pub motd: Option<String>,
+    pub autosave: AutosaveSettings,
+    pub retention: RetentionSettings,
+    /// Milliseconds between appends to the write-ahead log, which bound the changes lost on a
+    /// crash.
+    pub log_interval_ms: u64,
+    /// Capacity of the message channels between the connections and the main loop.
+    pub channel_bound: usize,
+    /// Distance within which players see each other.
+    pub view_radius: f32,
+    /// Distance beyond which a seen player is hidden again, at least the view radius.
+    pub hide_radius: f32,
+    pub auth: AuthConfig,
+}
+
+/// Which snapshots of the world stay restorable, see `Retention`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    /// Maximum number of restorable snapshots.
+    pub count: usize,
+    /// Minimum seconds between two restorable snapshots.
+    pub interval: u64,
+}
+
+/// How users are authenticated, see `Authentication`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Accepts unsigned tokens, so that anyone can join as anyone. The trusted keys are ignored
+    /// then.
+    pub insecure: bool,
+    /// Public keys of the trusted token issuers by name, encoded in base64.
+    pub trusted_keys: BTreeMap<String, String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            description: String::new(),
+            world: PathBuf::from("world.db"),
+            tick_rate: DEFAULT_TICK_RATE,
+            push_rate: DEFAULT_TICK_RATE,
+            max_players: None,
+            reserved_slots: 0,
+            motd: None,
+            autosave: AutosaveSettings::default(),
+            retention: RetentionSettings::default(),
+            log_interval_ms: 1000,
+            channel_bound: 16,
+            view_radius: 120.0,
+            hide_radius: 140.0,
+            auth: AuthConfig::default(),
+        }
+    }
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            count: 24,
+            interval: 60 * 60,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Ticks from one push of updates to the next. The push rate has to divide the tick rate, so
+    /// that updates are pushed as often as configured.
+    pub(crate) fn push_interval(&self) -> Result<u64, CreateServiceError> {
+        if self.push_rate == 0 || self.tick_rate % self.push_rate != 0 {
+            return Err(CreateServiceError::InvalidPushRate(
+                self.push_rate,
+                self.tick_rate,
+            ));
+        }
+        Ok((self.tick_rate / self.push_rate).max(1) as u64)
+    }
+
+    pub(crate) fn log_interval(&self) -> Duration {
+        Duration::from_millis(self.log_interval_ms.max(1))
+    }
+
+    pub(crate) fn channel_bound(&self) -> usize {
+        self.channel_bound.max(1)
+    }
+
+    pub(crate) fn view_distance(&self) -> ViewDistance {
+        let view = self.view_radius.max(0.0);
+        ViewDistance {
+            view,
+            hide: self.hide_radius.max(view),
+        }
+    }
+}
+
+impl RetentionSettings {
+    pub fn retention(&self) -> Retention {
+        Retention {
+            count: self.count,
+            interval: Duration::from_secs(self.interval),
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn authentication(&self) -> Result<Authentication, CreateServiceError> {
+        if self.insecure {
+            return Ok(Authentication::Insecure);
+        }
+        if self.trusted_keys.is_empty() {
+            return Err(CreateServiceError::NoTrustedKeys);
+        }
+        let mut issuers = HashMap::new();
+        for (issuer, key) in &self.trusted_keys {
+            let key = base64::decode(key)
+                .map_err(|error| CreateServiceError::DecodeTrustedKey(issuer.clone(), error))?;
+            if key.len() != ED25519_PUBLIC_KEY_LEN {
+                return Err(CreateServiceError::InvalidTrustedKey(
+                    issuer.clone(),
+                    key.len(),
+                ));
+            }
+            issuers.insert(issuer.clone(), key);
+        }
+        Ok(Authentication::Signed(issuers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate_signing_key, public_key};
+
+    use super::*;
+
+    #[test]
+    fn push_rate_divides_tick_rate() {
+        let config = |tick_rate, push_rate| ServerConfig {
+            tick_rate,
+            push_rate,
+            ..ServerConfig::default()
+        };
+        assert_eq!(config(30, 30).push_interval().unwrap(), 1);
+        assert_eq!(config(30, 10).push_interval().unwrap(), 3);
+        assert_eq!(config(20, 1).push_interval().unwrap(), 20);
+        for (tick_rate, push_rate) in [(30, 20), (30, 60), (30, 0)] {
+            assert!(matches!(
+                config(tick_rate, push_rate).push_interval(),
+                Err(CreateServiceError::InvalidPushRate(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn hide_radius_is_at_least_view_radius() {
+        let config = ServerConfig {
+            view_radius: 100.0,
+            hide_radius: 50.0,
+            ..ServerConfig::default()
+        };
+        let distance = config.view_distance();
+        assert_eq!(distance.view, 100.0);
+        assert_eq!(distance.hide, 100.0);
+    }
+
+    #[test]
+    fn signed_authentication_needs_trusted_keys() {
+        let config = AuthConfig::default();
+        assert!(matches!(
+            config.authentication(),
+            Err(CreateServiceError::NoTrustedKeys)
+        ));
+        let config = AuthConfig {
+            insecure: true,
+            ..AuthConfig::default()
+        };
+        assert!(matches!(
+            config.authentication(),
+            Ok(Authentication::Insecure)
+        ));
+    }
+
+    #[test]
+    fn trusted_keys_are_checked() {
+        let key = public_key(&generate_signing_key().unwrap()).unwrap();
+        let mut config = AuthConfig::default();
+        config
+            .trusted_keys
+            .insert("hub".to_owned(), base64::encode(&key));
+        assert!(matches!(
+            config.authentication(),
+            Ok(Authentication::Signed(issuers)) if issuers["hub"] == key
+        ));
+        config
+            .trusted_keys
+            .insert("short".to_owned(), base64::encode(&key[1..]));
+        assert!(matches!(
+            config.authentication(),
+            Err(CreateServiceError::InvalidTrustedKey(issuer, 31)) if issuer == "short"
+        ));
+        config
+            .trusted_keys
+            .insert("short".to_owned(), "not base64!".to_owned());
+        assert!(matches!(
+            config.authentication(),
+            Err(CreateServiceError::DecodeTrustedKey(issuer, _)) if issuer == "short"
+        ));
+    }
+}
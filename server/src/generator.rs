@@ -0,0 +1,466 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    ops::Range,
+    str::FromStr,
+};
+
+use bytemuck::Zeroable;
+use noise::{NoiseFn, Perlin, Seedable};
+use thiserror::Error;
+
+use crate::{Chunk, ChunkCoord};
+
+/// Largest edge length of a generated area, which keeps block coordinates far within the range
+/// that positions represent exactly.
+pub const MAX_SIZE: u32 = 1 << 16;
+
+/// Largest number of layers of blocks a generator may stack.
+pub const MAX_HEIGHT: i32 = 1 << 10;
+
+/// Largest number of block positions a generator may visit, which bounds the time and memory it
+/// takes to generate a world. Generators check the box they fill against it before generating.
+pub const MAX_VOLUME: u64 = 1 << 28;
+
+/// Everything that determines a generated world. Generating twice with the same settings gives
+/// the same world.
+#[derive(Clone, Debug)]
+pub struct GeneratorSettings {
+    pub generator: String,
+    pub seed: u32,
+    /// Edge length of the generated area in blocks.
+    pub size: u32,
+    /// Options specific to the generator.
+    pub parameters: HashMap<String, String>,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            generator: "perlin".to_owned(),
+            seed: 0,
+            size: 41,
+            parameters: HashMap::new(),
+        }
+    }
+}
+
+impl GeneratorSettings {
+    /// Parses the parameter with the given name, or returns the default if it is not set.
+    pub fn parameter<T: FromStr>(&self, name: &str, default: T) -> Result<T, GenerateError> {
+        match self.parameters.get(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| GenerateError::InvalidParameter(name.to_owned(), value.clone())),
+            None => Ok(default),
+        }
+    }
+
+    /// Like `parameter`, but also fails if the value does not satisfy `valid`.
+    pub fn checked_parameter<T: FromStr>(
+        &self,
+        name: &str,
+        default: T,
+        valid: impl FnOnce(&T) -> bool,
+    ) -> Result<T, GenerateError> {
+        let value = self.parameter(name, default)?;
+        if valid(&value) {
+            Ok(value)
+        } else {
+            let text = self.parameters.get(name).cloned().unwrap_or_default();
+            Err(GenerateError::InvalidParameter(name.to_owned(), text))
+        }
+    }
+
+    /// Fails if a parameter is set that is not in `known`.
+    pub fn check_parameters(&self, known: &[&str]) -> Result<(), GenerateError> {
+        match self
+            .parameters
+            .keys()
+            .find(|name| !known.contains(&name.as_str()))
+        {
+            Some(name) => Err(GenerateError::UnknownParameter(name.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// The block coordinates along one axis, centered around zero. Fails if the size is zero or
+    /// larger than `MAX_SIZE`.
+    pub fn range(&self) -> Result<Range<i32>, GenerateError> {
+        if self.size == 0 || self.size > MAX_SIZE {
+            return Err(GenerateError::InvalidSize(self.size));
+        }
+        let size = self.size as i32;
+        Ok(-(size / 2)..size - size / 2)
+    }
+
+    /// Fails if filling a box that spans the generated area horizontally and has the given
+    /// height would visit more than `MAX_VOLUME` block positions.
+    pub fn check_volume(&self, height: u32) -> Result<(), GenerateError> {
+        let volume = self.size as u64 * self.size as u64 * height as u64;
+        if volume > MAX_VOLUME {
+            return Err(GenerateError::TooLarge(volume));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `scale` is usable as the number of blocks per noise period.
+fn valid_scale(scale: &f64) -> bool {
+    scale.is_finite() && *scale > 0.0
+}
+
+pub trait WorldGenerator {
+    /// Calls `place` with the block coordinates of every block of the world, in an order that
+    /// only depends on the settings.
+    fn generate(
+        &self,
+        settings: &GeneratorSettings,
+        place: &mut dyn FnMut(i32, i32, i32),
+    ) -> Result<(), GenerateError>;
+}
+
+#[derive(Debug, Error)]
+pub enum GenerateError {
+    #[error("unknown generator '{0}'")]
+    UnknownGenerator(String),
+    #[error("invalid size {0}, must be between 1 and {}", MAX_SIZE)]
+    InvalidSize(u32),
+    #[error("world of {0} blocks is too large, at most {} are allowed", MAX_VOLUME)]
+    TooLarge(u64),
+    #[error("unknown parameter '{0}'")]
+    UnknownParameter(String),
+    #[error("invalid value '{1}' for parameter '{0}'")]
+    InvalidParameter(String, String),
+    #[error("could not read heightmap")]
+    ReadHeightmap(#[source] io::Error),
+    #[error("invalid height '{0}' in heightmap")]
+    InvalidHeight(String),
+}
+
+/// The generators that settings can name. The default registry has the built-in generators,
+/// further ones are added with `register`.
+pub struct GeneratorRegistry {
+    generators: HashMap<String, Box<dyn WorldGenerator>>,
+}
+
+impl GeneratorRegistry {
+    /// Creates a registry without any generators.
+    pub fn new() -> Self {
+        Self {
+            generators: HashMap::new(),
+        }
+    }
+
+    /// Makes the generator available under `name`, replacing a generator of the same name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        generator: impl WorldGenerator + 'static,
+    ) -> &mut Self {
+        self.generators.insert(name.into(), Box::new(generator));
+        self
+    }
+
+    /// Returns the generator with the given name.
+    pub fn generator(&self, name: &str) -> Result<&dyn WorldGenerator, GenerateError> {
+        self.generators
+            .get(name)
+            .map(|generator| generator.as_ref())
+            .ok_or_else(|| GenerateError::UnknownGenerator(name.to_owned()))
+    }
+
+    /// The names of the registered generators in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.generators.keys().map(String::as_str)
+    }
+
+    /// Generates the chunks of a world with the generator named in the settings.
+    pub fn generate(
+        &self,
+        settings: &GeneratorSettings,
+    ) -> Result<BTreeMap<ChunkCoord, Chunk>, GenerateError> {
+        let mut chunks = BTreeMap::new();
+        self.generator(&settings.generator)?
+            .generate(settings, &mut |x, y, z| {
+                chunks
+                    .entry(ChunkCoord::of_block(x, y, z))
+                    .or_insert_with(Chunk::zeroed)
+                    .insert(x, y, z)
+            })?;
+        Ok(chunks)
+    }
+}
+
+impl Default for GeneratorRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register("empty", EmptyGenerator)
+            .register("flat", FlatGenerator)
+            .register("perlin", PerlinGenerator)
+            .register("heightmap", HeightmapGenerator);
+        registry
+    }
+}
+
+/// A world without any blocks.
+pub struct EmptyGenerator;
+
+impl WorldGenerator for EmptyGenerator {
+    fn generate(
+        &self,
+        settings: &GeneratorSettings,
+        _place: &mut dyn FnMut(i32, i32, i32),
+    ) -> Result<(), GenerateError> {
+        settings.check_parameters(&[])
+    }
+}
+
+/// A square plate of blocks. Parameters: `height` (layers of blocks between 1 and `MAX_HEIGHT`,
+/// default 1).
+pub struct FlatGenerator;
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(
+        &self,
+        settings: &GeneratorSettings,
+        place: &mut dyn FnMut(i32, i32, i32),
+    ) -> Result<(), GenerateError> {
+        settings.check_parameters(&["height"])?;
+        let height: i32 =
+            settings.checked_parameter("height", 1, |height| (1..=MAX_HEIGHT).contains(height))?;
+        let range = settings.range()?;
+        settings.check_volume(height as u32)?;
+        for x in range.clone() {
+            for y in 1 - height..1 {
+                for z in range.clone() {
+                    place(x, y, z)
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A cube with blocks where three-dimensional Perlin noise is above a threshold. Parameters:
+/// `scale` (blocks per noise period, positive, default 20) and `threshold` (between -1 and 1,
+/// default 0).
+pub struct PerlinGenerator;
+
+impl WorldGenerator for PerlinGenerator {
+    fn generate(
+        &self,
+        settings: &GeneratorSettings,
+        place: &mut dyn FnMut(i32, i32, i32),
+    ) -> Result<(), GenerateError> {
+        settings.check_parameters(&["scale", "threshold"])?;
+        let scale: f64 = settings.checked_parameter("scale", 20.0, valid_scale)?;
+        let threshold: f64 = settings.parameter("threshold", 0.0)?;
+        let perlin = Perlin::new().set_seed(settings.seed);
+        let range = settings.range()?;
+        settings.check_volume(settings.size)?;
+        for x in range.clone() {
+            for y in range.clone() {
+                for z in range.clone() {
+                    let v = perlin.get([x as f64 / scale, y as f64 / scale, z as f64 / scale]);
+                    if v >= threshold {
+                        place(x, y, z)
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Columns of blocks on a square area. The heights are read from the text file given by the
+/// `path` parameter, one row of whitespace separated heights between 0 and `MAX_HEIGHT` per line,
+/// or taken from two-dimensional Perlin noise with the parameters `scale` (blocks per noise
+/// period, positive, default 40) and `amplitude` (maximum height up to `MAX_HEIGHT`, default 16).
+/// The file is centered on the area like generated heights, and columns outside of it are left
+/// out.
+pub struct HeightmapGenerator;
+
+impl HeightmapGenerator {
+    fn read(path: &str) -> Result<Vec<Vec<i32>>, GenerateError> {
+        let text = fs::read_to_string(path).map_err(GenerateError::ReadHeightmap)?;
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|text| match text.parse::<i32>() {
+                        Ok(height) if (0..=MAX_HEIGHT).contains(&height) => Ok(height),
+                        _ => Err(GenerateError::InvalidHeight(text.to_owned())),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn column(x: i32, z: i32, height: i32, place: &mut dyn FnMut(i32, i32, i32)) {
+        for y in 0..height {
+            place(x, y, z)
+        }
+    }
+}
+
+impl WorldGenerator for HeightmapGenerator {
+    fn generate(
+        &self,
+        settings: &GeneratorSettings,
+        place: &mut dyn FnMut(i32, i32, i32),
+    ) -> Result<(), GenerateError> {
+        settings.check_parameters(&["path", "scale", "amplitude"])?;
+        let range = settings.range()?;
+        if let Some(path) = settings.parameters.get("path") {
+            let rows = Self::read(path)?;
+            let highest = rows.iter().flatten().copied().max().unwrap_or(0);
+            settings.check_volume(highest as u32)?;
+            let depth = rows.len() as i32;
+            for (z, row) in rows.iter().enumerate() {
+                let z = z as i32 - depth / 2;
+                let width = row.len() as i32;
+                for (x, height) in row.iter().enumerate() {
+                    let x = x as i32 - width / 2;
+                    if range.contains(&x) && range.contains(&z) {
+                        Self::column(x, z, *height, place)
+                    }
+                }
+            }
+        } else {
+            let scale: f64 = settings.checked_parameter("scale", 40.0, valid_scale)?;
+            let amplitude: f64 = settings.checked_parameter("amplitude", 16.0, |amplitude| {
+                (0.0..=MAX_HEIGHT as f64).contains(amplitude)
+            })?;
+            settings.check_volume((amplitude.round() as u32).max(1))?;
+            let perlin = Perlin::new().set_seed(settings.seed);
+            for z in range.clone() {
+                for x in range.clone() {
+                    let v = perlin.get([x as f64 / scale, z as f64 / scale]);
+                    let height = ((v + 1.0) / 2.0 * amplitude).round() as i32;
+                    Self::column(x, z, height.max(1), place)
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::bytes_of;
+
+    use super::*;
+
+    fn settings(generator: &str, size: u32, parameters: &[(&str, &str)]) -> GeneratorSettings {
+        GeneratorSettings {
+            generator: generator.to_owned(),
+            seed: 7,
+            size,
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn generate(settings: &GeneratorSettings) -> Result<Vec<(ChunkCoord, Vec<u8>)>, GenerateError> {
+        let chunks = GeneratorRegistry::default().generate(settings)?;
+        Ok(chunks
+            .into_iter()
+            .map(|(coord, chunk)| (coord, bytes_of(&chunk).to_vec()))
+            .collect())
+    }
+
+    fn invalid_parameter(settings: &GeneratorSettings, name: &str) -> bool {
+        matches!(
+            generate(settings),
+            Err(GenerateError::InvalidParameter(parameter, _)) if parameter == name
+        )
+    }
+
+    #[test]
+    fn same_settings_give_same_world() {
+        for settings in [
+            settings("flat", 40, &[("height", "3")]),
+            settings("perlin", 40, &[("scale", "7.5")]),
+            settings("heightmap", 40, &[("amplitude", "20")]),
+        ] {
+            let world = generate(&settings).unwrap();
+            assert!(!world.is_empty());
+            assert!(world == generate(&settings).unwrap());
+        }
+        let world = generate(&settings("perlin", 40, &[])).unwrap();
+        let mut other = settings("perlin", 40, &[]);
+        other.seed += 1;
+        assert!(world != generate(&other).unwrap());
+    }
+
+    #[test]
+    fn reject_invalid_scale() {
+        for scale in ["0", "-1", "NaN", "inf", "large"] {
+            for generator in ["perlin", "heightmap"] {
+                let settings = settings(generator, 8, &[("scale", scale)]);
+                assert!(invalid_parameter(&settings, "scale"));
+            }
+        }
+    }
+
+    #[test]
+    fn reject_invalid_heights() {
+        for height in ["0", "-2", "1025"] {
+            let settings = settings("flat", 8, &[("height", height)]);
+            assert!(invalid_parameter(&settings, "height"));
+        }
+        for amplitude in ["-1", "1025", "NaN"] {
+            let settings = settings("heightmap", 8, &[("amplitude", amplitude)]);
+            assert!(invalid_parameter(&settings, "amplitude"));
+        }
+        assert!(generate(&settings("flat", 8, &[("height", "1024")])).is_ok());
+        assert!(matches!(
+            generate(&settings("flat", 8, &[("depth", "1")])),
+            Err(GenerateError::UnknownParameter(_))
+        ));
+    }
+
+    #[test]
+    fn reject_too_large_worlds() {
+        assert!(matches!(
+            generate(&settings("perlin", 1024, &[])),
+            Err(GenerateError::TooLarge(_))
+        ));
+        assert!(matches!(
+            generate(&settings("flat", MAX_SIZE, &[("height", "1024")])),
+            Err(GenerateError::TooLarge(_))
+        ));
+        assert!(matches!(
+            generate(&settings("flat", MAX_SIZE + 1, &[])),
+            Err(GenerateError::InvalidSize(_))
+        ));
+    }
+
+    #[test]
+    fn heightmap_file_is_checked_and_cropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("heights.txt");
+        let path_text = path.to_str().unwrap();
+        for height in ["-1", "1025", "high"] {
+            fs::write(&path, format!("1 2\n3 {}\n", height)).unwrap();
+            assert!(matches!(
+                generate(&settings("heightmap", 8, &[("path", path_text)])),
+                Err(GenerateError::InvalidHeight(text)) if text == height
+            ));
+        }
+        fs::write(&path, "1 1 1 1\n1 1 1 1\n1 1 1 1\n1 1 1 1\n").unwrap();
+        let mut blocks = Vec::new();
+        HeightmapGenerator
+            .generate(
+                &settings("heightmap", 2, &[("path", path_text)]),
+                &mut |x, y, z| blocks.push((x, y, z)),
+            )
+            .unwrap();
+        blocks.sort_unstable();
+        assert_eq!(blocks, [(-1, 0, -1), (-1, 0, 0), (0, 0, -1), (0, 0, 0)]);
+    }
+}
@@ -1,8 +1,25 @@
-use std::{mem::swap, sync::Arc};
+use std::{collections::HashMap, iter::once, mem::take, time::Instant};
 
-use log::info;
+use log::{debug, error, info, warn};
+use tokio::{
+    spawn,
+    sync::mpsc::{self, Receiver, Sender},
+    task::spawn_blocking,
+};
+use uuid::Uuid;
 
-use crate::{state::Observer, Push, SelfUpdate, ServerMessage, Setup, State, UpdateBatch, World};
+use crate::{
+    admin::{ban, broadcast, close_all, database_stats, kick, list_players},
+    chat_text, save,
+    state::{unix_time, Observer},
+    ChatLimit, ChatMessage, ChunkCoord, ChunkStream, Interest, Movement, Player, PlayerGrid, Push,
+    SelfUpdate, ServerMessage, Setup, State, TickContext, Update, UpdateBatch, User, World,
+    CLOSE_KICKED, SERVER_NAME,
+};
+
+/// Upper bound for the chunks sent to a player per push. Small pushes let chunks that come into
+/// range later overtake those that were not sent yet.
+const CHUNKS_PER_PUSH: usize = 16;
 
 pub enum ControlFlow {
     Continue,
@@ -11,52 +28,532 @@ pub enum ControlFlow {
 
 pub(super) async fn handle(state: &mut State, message: ServerMessage) -> ControlFlow {
     match message {
-        ServerMessage::Stop => {
-            state.database.snapshot().unwrap();
-            return ControlFlow::Stop;
+        ServerMessage::Stop(tx) => {
+            record_sessions(state);
+            let result = save(state).await;
+            let stopped = result.is_ok();
+            if stopped {
+                close_all(state);
+            }
+            let _ = tx.send(result.map(|_| ()));
+            if stopped {
+                return ControlFlow::Stop;
+            }
+        }
+        ServerMessage::Log => {
+            if let Err(error) = state.database.log() {
+                error!("could not write the write-ahead log: {}", error);
+            }
         }
         ServerMessage::Connected(user) => {
+            if let Some(previous) = state.observers.remove(&user.uuid) {
+                info!(
+                    "User {} connected again, closing the previous session",
+                    user.name
+                );
+                previous
+                    .connection
+                    .close(CLOSE_KICKED, "logged in from another session");
+            }
             let world: &mut World = &mut state.database;
-            world.register_player(user.uuid, &mut state.updates);
-            let positions = world.positions.read().iter().cloned().collect();
-            let players = world.players.read().iter().cloned().collect();
-            info!("User {} connected", user.name);
+            if let Err(error) = world.register_player(user.uuid, &user.name) {
+                error!(
+                    "could not update the profile of user {}: {}",
+                    user.name, error
+                );
+            }
+            state.names.insert(user.uuid, user.name.clone());
+            let players = match world.players.view() {
+                Ok(players) => players,
+                Err(error) => {
+                    error!("could not set up user {}: {}", user.name, error);
+                    return ControlFlow::Continue;
+                }
+            };
+            info!("User {} connected as {}", user.name, user.role);
+            let mut chunks = ChunkStream::default();
+            let mut interest = Interest::default();
+            if let Some(player) = world.player(user.uuid) {
+                chunks.move_to(ChunkCoord::of_position(player.position));
+                let others = world.players.read();
+                let grid = PlayerGrid::new(state.view_distance, others.iter().copied());
+                let names = &state.names;
+                interest.update(user.uuid, player.position, &grid, names, &mut Vec::new());
+            }
+            let names = interest
+                .visible()
+                .iter()
+                .chain(once(&user.uuid))
+                .filter_map(|uuid| Some((*uuid, state.names.get(uuid)?.clone())))
+                .collect();
+            let (sync_push, pushes) = mpsc::channel(state.channel_bound);
+            let chat = world.chat_history().unwrap_or_else(|error| {
+                error!("could not read the chat history: {}", error);
+                Vec::new()
+            });
+            spawn(setup(
+                user.uuid,
+                players,
+                names,
+                chat,
+                user.connection.synchronous(),
+                pushes,
+            ));
             let observer = Observer {
-                sync_push: user.connection.synchronous(),
+                sync_push,
                 after_update: state.updates.len(),
+                chunks,
+                interest,
+                movement: Movement::default(),
+                chat_limit: ChatLimit::default(),
+                role: user.role,
+                connection: user.connection,
+                connected: Instant::now(),
+                session: user.session,
             };
-            let _ = observer
-                .sync_push
-                .send(Push::Setup(Setup(user.uuid, players, positions)))
-                .await;
+            if let Some(motd) = &state.motd {
+                let message = ChatMessage {
+                    sender: Uuid::nil(),
+                    name: SERVER_NAME.to_owned(),
+                    time: unix_time(),
+                    text: motd.clone(),
+                };
+                let _ = observer.sync_push.send(Push::Chat(message)).await;
+            }
             state.observers.insert(user.uuid, observer);
         }
         ServerMessage::Disconnected(user) => {
+            if observer(state, &user).is_none() {
+                debug!("Previous session of user {} ended", user.name);
+                return ControlFlow::Continue;
+            }
             info!("User {} disconnected", user.name);
             state.observers.remove(&user.uuid);
+            unregister(state, user.uuid);
         }
-        ServerMessage::PushUpdates => {
-            let mut updates = Vec::new();
-            swap(&mut state.updates, &mut updates);
-            let updates = Arc::new(updates);
-            for (_, observer) in state.observers.iter_mut() {
-                let _ = observer
-                    .sync_push
-                    .send(Push::Updates(UpdateBatch(
-                        updates.clone(),
-                        observer.after_update,
-                    )))
-                    .await;
-                observer.after_update = 0;
+        ServerMessage::Tick => {
+            let start = Instant::now();
+            let dt = start.duration_since(state.last_tick);
+            state.last_tick = start;
+            state.tick += 1;
+            let mut context = TickContext {
+                tick: state.tick,
+                dt,
+                world: &mut state.database,
+                updates: &mut state.updates,
+                moves: &mut state.moves,
+            };
+            for system in state.systems.iter_mut() {
+                system.run(&mut context);
+            }
+            if state.tick % state.push_interval == 0 {
+                state.autosave.count(state.updates.len());
+                push_updates(state).await;
             }
+            if state.autosave.is_due() {
+                let _ = save(state).await;
+            }
+            state.tick_metrics.record(start.elapsed());
         }
         ServerMessage::Request(user, request) => match request {
             crate::Request::UpdateSelf(SelfUpdate(pos, orientation)) => {
-                let world: &mut World = &mut state.database;
-                world.update_player(user.uuid, pos, orientation, &mut state.updates)
+                let world: &World = &state.database;
+                let observer = state
+                    .observers
+                    .get_mut(&user.uuid)
+                    .filter(|observer| observer.session == user.session);
+                let (observer, player) = match (observer, world.player(user.uuid)) {
+                    (Some(observer), Some(player)) => (observer, player),
+                    _ => return ControlFlow::Continue,
+                };
+                let from = state
+                    .moves
+                    .get(&user.uuid)
+                    .map_or(player.position, |(position, _)| *position);
+                match observer.movement.check(from, pos, &state.bounds) {
+                    Ok(()) => {
+                        state.moves.insert(user.uuid, (pos, orientation));
+                        observer.chunks.move_to(ChunkCoord::of_position(pos));
+                    }
+                    Err(error) => {
+                        if observer.movement.is_repeated_violation() {
+                            warn!("User {} keeps moving invalidly: {}", user.name, error);
+                        } else {
+                            debug!("Rejected move of user {}: {}", user.name, error);
+                        }
+                        let correction = Push::Correction(from);
+                        let _ = observer.sync_push.send(correction).await;
+                    }
+                }
             }
             crate::Request::Shutdown => panic!(),
+            crate::Request::Chat(text) => {
+                let observer = match observer(state, &user) {
+                    Some(observer) => observer,
+                    None => return ControlFlow::Continue,
+                };
+                let text = match chat_text(&text) {
+                    Some(text) => text,
+                    None => {
+                        debug!(
+                            "Rejected empty or too long chat message of user {}",
+                            user.name
+                        );
+                        return ControlFlow::Continue;
+                    }
+                };
+                if !observer.chat_limit.allow() {
+                    debug!(
+                        "Rejected chat message of user {} over the rate limit",
+                        user.name
+                    );
+                    return ControlFlow::Continue;
+                }
+                let message = ChatMessage {
+                    sender: user.uuid,
+                    name: user.name.clone(),
+                    time: unix_time(),
+                    text: text.to_owned(),
+                };
+                send_chat(state, message).await;
+            }
         },
+        ServerMessage::ListPlayers(tx) => {
+            let _ = tx.send(list_players(state));
+        }
+        ServerMessage::Kick(target, reason, tx) => {
+            let _ = tx.send(kick(state, &target, &reason));
+        }
+        ServerMessage::Ban(target, reason, duration, tx) => {
+            let _ = tx.send(ban(state, &target, &reason, duration));
+        }
+        ServerMessage::Broadcast(text) => broadcast(state, text).await,
+        ServerMessage::Snapshot(tx) => {
+            let _ = tx.send(save(state).await);
+        }
+        ServerMessage::DatabaseStats(tx) => {
+            let _ = tx.send(database_stats(state));
+        }
     }
     ControlFlow::Continue
 }
+
+/// The observer of the session of `user`, unless the user connected again since.
+fn observer<'a>(state: &'a mut State, user: &User) -> Option<&'a mut Observer> {
+    state
+        .observers
+        .get_mut(&user.uuid)
+        .filter(|observer| observer.session == user.session)
+}
+
+/// Keeps a chat message in the chat history and sends it to all players.
+pub(crate) async fn send_chat(state: &mut State, message: ChatMessage) {
+    let world: &mut World = &mut state.database;
+    if let Err(error) = world.add_chat_message(&message) {
+        error!("could not store chat message: {}", error);
+    }
+    for observer in state.observers.values() {
+        let push = Push::Chat(message.clone());
+        let _ = observer.sync_push.send(push).await;
+    }
+}
+
+/// Applies the pending moves and adds the sessions so far to the play time of the connected
+/// players before the final save. They stay connected, in case the save fails and the server
+/// keeps running.
+fn record_sessions(state: &mut State) {
+    let world: &mut World = &mut state.database;
+    for (uuid, (position, orientation)) in state.moves.drain() {
+        world.update_player(uuid, position, orientation, &mut state.updates);
+    }
+    for uuid in state.observers.keys() {
+        if let Err(error) = world.record_play_time(*uuid) {
+            let name = state.names.get(uuid).map_or("", String::as_str);
+            error!("could not update the profile of user {}: {}", name, error);
+        }
+    }
+}
+
+/// Applies the pending move of a player that left and marks it offline.
+fn unregister(state: &mut State, uuid: Uuid) {
+    let name = state.names.remove(&uuid).unwrap_or_default();
+    let world: &mut World = &mut state.database;
+    if let Some((position, orientation)) = state.moves.remove(&uuid) {
+        world.update_player(uuid, position, orientation, &mut state.updates);
+    }
+    if let Err(error) = world.unregister_player(uuid, &mut state.updates) {
+        error!("could not update the profile of user {}: {}", name, error);
+    }
+}
+
+/// Sends each observer the updates it is interested in and the next chunks it needs.
+async fn push_updates(state: &mut State) {
+    let updates = take(&mut state.updates);
+    let world: &World = &state.database;
+    let grid = PlayerGrid::new(state.view_distance, world.players.read().iter().copied());
+    for (uuid, observer) in state.observers.iter_mut() {
+        let interest = &mut observer.interest;
+        let mut batch: Vec<_> = updates[observer.after_update..]
+            .iter()
+            .filter(|update| match update {
+                Update::Player(other, ..) => interest.is_visible(other),
+                Update::PlayerLeft(other) => interest.remove(other),
+                _ => true,
+            })
+            .cloned()
+            .collect();
+        if let Some(player) = world.player(*uuid) {
+            interest.update(*uuid, player.position, &grid, &state.names, &mut batch);
+        }
+        let _ = observer
+            .sync_push
+            .send(Push::Updates(UpdateBatch(state.tick, batch)))
+            .await;
+        observer.after_update = 0;
+        if let Some(batch) = observer.chunks.batch(&world.chunks, CHUNKS_PER_PUSH) {
+            let _ = observer.sync_push.send(Push::Chunks(batch)).await;
+        }
+    }
+}
+
+/// Builds the setup from views of the world, so that the main loop can go on meanwhile, and
+/// forwards the pushes that were queued up in the meantime once it is sent. The setup contains
+/// the player itself and the players it sees, which are the ones `names` has a name for.
+async fn setup(
+    uuid: Uuid,
+    players: db::VecView<Player>,
+    names: HashMap<Uuid, String>,
+    chat: Vec<ChatMessage>,
+    sync_push: Sender<Push>,
+    mut pushes: Receiver<Push>,
+) {
+    let setup = spawn_blocking(move || {
+        let players = players
+            .read()
+            .iter()
+            .filter_map(|player| {
+                let name = names.get(&Uuid::from_u128(player.uuid))?;
+                Some((*player, name.clone()))
+            })
+            .collect();
+        Setup(uuid, players, chat)
+    })
+    .await
+    .unwrap();
+    if sync_push.send(Push::Setup(setup)).await.is_err() {
+        return;
+    }
+    while let Some(push) = pushes.recv().await {
+        if sync_push.send(push).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        path::Path,
+        sync::{Arc, Mutex, RwLock},
+        thread::sleep,
+        time::Duration,
+    };
+
+    use db::{Database, FaultyDisk, Unsynced};
+    use net::Connection;
+    use tokio::{
+        runtime::{Builder, Runtime},
+        sync::oneshot,
+    };
+
+    use crate::{
+        Autosave, AutosaveSettings, Bounds, Request, Role, System, TickMetrics, ViewDistance,
+        WorldDatabase,
+    };
+
+    use super::*;
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    fn state(path: &Path) -> State {
+        state_of(Database::create(path, |database| World::new(database, BTreeMap::new())).unwrap())
+    }
+
+    fn state_of(database: Database<World>) -> State {
+        let bounds = Bounds::of_world(&database.chunks);
+        State {
+            database: WorldDatabase::new(database),
+            updates: Vec::new(),
+            observers: HashMap::new(),
+            names: HashMap::new(),
+            bounds,
+            tick: 0,
+            last_tick: Instant::now(),
+            moves: HashMap::new(),
+            push_interval: 1,
+            systems: Vec::new(),
+            tick_metrics: TickMetrics::new(Duration::from_millis(50)),
+            access: Arc::new(RwLock::new(Default::default())),
+            autosave: Autosave::new(AutosaveSettings::default()),
+            motd: None,
+            channel_bound: 16,
+            view_distance: ViewDistance {
+                view: 100.0,
+                hide: 120.0,
+            },
+        }
+    }
+
+    /// A session of a local user, with the receiver of its pushes.
+    fn user(uuid: Uuid, session: u64) -> (User, Receiver<Push>) {
+        let (sender, pushes) = mpsc::channel(64);
+        let user = User {
+            uuid,
+            name: "player".to_owned(),
+            role: Role::Player,
+            connection: Connection::local(sender),
+            session,
+        };
+        (user, pushes)
+    }
+
+    #[test]
+    fn previous_session_does_not_end_the_next() {
+        let dir = tempfile::tempdir().unwrap();
+        runtime().block_on(async {
+            let mut state = state(&dir.path().join("world.db"));
+            let uuid = Uuid::from_u128(1);
+            let (first, _first_pushes) = user(uuid, 1);
+            let (second, _second_pushes) = user(uuid, 2);
+            handle(&mut state, ServerMessage::Connected(first.clone())).await;
+            handle(&mut state, ServerMessage::Connected(second.clone())).await;
+            assert_eq!(state.observers[&uuid].session, 2);
+            handle(&mut state, ServerMessage::Disconnected(first)).await;
+            assert_eq!(state.observers[&uuid].session, 2);
+            assert!(state.names.contains_key(&uuid));
+            assert!(state.database.player(uuid).unwrap().is_online());
+            handle(&mut state, ServerMessage::Disconnected(second)).await;
+            assert!(state.observers.is_empty());
+            assert!(state.names.is_empty());
+            assert!(!state.database.player(uuid).unwrap().is_online());
+        });
+    }
+
+    #[test]
+    fn requests_of_previous_session_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        runtime().block_on(async {
+            let mut state = state(&dir.path().join("world.db"));
+            let uuid = Uuid::from_u128(1);
+            let (first, _first_pushes) = user(uuid, 1);
+            let (second, _second_pushes) = user(uuid, 2);
+            handle(&mut state, ServerMessage::Connected(first.clone())).await;
+            handle(&mut state, ServerMessage::Connected(second)).await;
+            let text = "hello".to_owned();
+            handle(
+                &mut state,
+                ServerMessage::Request(first, Request::Chat(text)),
+            )
+            .await;
+            assert!(state.database.chat_history().unwrap().is_empty());
+        });
+    }
+
+    /// Records the ticks it runs in, by name.
+    struct Recorder(&'static str, Arc<Mutex<Vec<(&'static str, u64, Duration)>>>);
+
+    impl System for Recorder {
+        fn run(&mut self, context: &mut TickContext) {
+            self.1
+                .lock()
+                .unwrap()
+                .push((self.0, context.tick, context.dt));
+        }
+    }
+
+    /// Pretends that the player logged in `seconds` ago.
+    fn set_last_seen(state: &mut State, uuid: Uuid, seconds: u64) {
+        let mut profiles = state.database.profiles.write();
+        let mut profile = profiles.get(&uuid.as_u128()).unwrap().unwrap();
+        profile.last_seen = unix_time() - seconds;
+        profiles.insert(uuid.as_u128(), &profile).unwrap();
+    }
+
+    #[test]
+    fn stop_saves_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("world.db");
+        let uuid = Uuid::from_u128(1);
+        runtime().block_on(async {
+            let mut state = state(&path);
+            let (user, _pushes) = user(uuid, 1);
+            handle(&mut state, ServerMessage::Connected(user)).await;
+            set_last_seen(&mut state, uuid, 100);
+            let (tx, rx) = oneshot::channel();
+            let flow = handle(&mut state, ServerMessage::Stop(tx)).await;
+            assert!(matches!(flow, ControlFlow::Stop));
+            rx.await.unwrap().unwrap();
+        });
+        let database = Database::<World>::open(&path).unwrap();
+        let profile = database
+            .profiles
+            .read()
+            .get(&uuid.as_u128())
+            .unwrap()
+            .unwrap();
+        assert!(profile.play_time >= 100);
+    }
+
+    #[test]
+    fn failed_stop_keeps_players_connected() {
+        let disk = FaultyDisk::new(0, Unsynced::Drop);
+        runtime().block_on(async {
+            let database =
+                Database::create_faulty(&disk, |database| World::new(database, BTreeMap::new()))
+                    .unwrap();
+            let mut state = state_of(database);
+            let uuid = Uuid::from_u128(1);
+            let (user, _pushes) = user(uuid, 1);
+            handle(&mut state, ServerMessage::Connected(user)).await;
+            disk.power_loss().unwrap();
+            // The snapshot after the power failure only fails to become durable, which the next
+            // snapshot reports.
+            let _ = save(&mut state).await;
+            let (tx, rx) = oneshot::channel();
+            let flow = handle(&mut state, ServerMessage::Stop(tx)).await;
+            assert!(matches!(flow, ControlFlow::Continue));
+            assert!(rx.await.unwrap().is_err());
+            assert!(state.observers.contains_key(&uuid));
+            assert!(state.names.contains_key(&uuid));
+            assert!(state.database.player(uuid).unwrap().is_online());
+        });
+    }
+
+    #[test]
+    fn systems_run_once_per_tick_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        runtime().block_on(async {
+            let mut state = state(&dir.path().join("world.db"));
+            let runs = Arc::new(Mutex::new(Vec::new()));
+            state.systems = vec![
+                Box::new(Recorder("first", runs.clone())),
+                Box::new(Recorder("second", runs.clone())),
+            ];
+            handle(&mut state, ServerMessage::Tick).await;
+            sleep(Duration::from_millis(20));
+            handle(&mut state, ServerMessage::Tick).await;
+            let runs = runs.lock().unwrap();
+            let order: Vec<_> = runs.iter().map(|(name, tick, _)| (*name, *tick)).collect();
+            assert_eq!(
+                order,
+                vec![("first", 1), ("second", 1), ("first", 2), ("second", 2)]
+            );
+            assert_eq!(runs[0].2, runs[1].2);
+            assert!(runs[2].2 >= Duration::from_millis(20));
+            assert_eq!(state.tick_metrics.ticks, 2);
+        });
+    }
+}
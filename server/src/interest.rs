@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::{Player, Position, Update};
+
+/// The distances that decide which players see each other.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewDistance {
+    /// Distance within which a player sees other players.
+    pub view: f32,
+    /// Distance beyond which a seen player is hidden again. It is at least the view distance so
+    /// that players moving along the border do not appear and disappear all the time.
+    pub hide: f32,
+}
+
+/// The online players by the cell of a uniform grid they are in, so that the players near a
+/// position are found without going through all players. Cells are as large as the hide
+/// distance, so a query only looks at the cells next to the one of its center.
+pub struct PlayerGrid {
+    distance: ViewDistance,
+    cells: HashMap<(i32, i32, i32), Vec<Player>>,
+}
+
+impl PlayerGrid {
+    pub fn new(distance: ViewDistance, players: impl Iterator<Item = Player>) -> Self {
+        let mut grid = Self {
+            distance,
+            cells: HashMap::new(),
+        };
+        for player in players.filter(Player::is_online) {
+            let cell = grid.cell(player.position);
+            grid.cells.entry(cell).or_default().push(player);
+        }
+        grid
+    }
+
+    pub fn distance(&self) -> ViewDistance {
+        self.distance
+    }
+
+    fn cell(&self, position: Position) -> (i32, i32, i32) {
+        let cell_size = self.distance.hide.max(1.0);
+        let coord = |v: f32| (v / cell_size).floor() as i32;
+        (coord(position.x), coord(position.y), coord(position.z))
+    }
+
+    /// The players in the cells next to the one of `center`, which include all players within
+    /// the hide distance of it.
+    pub fn near(&self, center: Position) -> impl Iterator<Item = &Player> {
+        let (x, y, z) = self.cell(center);
+        let neighbors = (-1..=1)
+            .flat_map(move |dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| (dx, dy, dz))));
+        neighbors
+            .filter_map(move |(dx, dy, dz)| {
+                let cell = (
+                    x.saturating_add(dx),
+                    y.saturating_add(dy),
+                    z.saturating_add(dz),
+                );
+                self.cells.get(&cell)
+            })
+            .flatten()
+    }
+}
+
+/// The other players a player sees, which are the only ones it gets updates about.
+#[derive(Default)]
+pub struct Interest {
+    visible: HashSet<Uuid>,
+}
+
+impl Interest {
+    pub fn is_visible(&self, uuid: &Uuid) -> bool {
+        self.visible.contains(uuid)
+    }
+
+    pub fn visible(&self) -> &HashSet<Uuid> {
+        &self.visible
+    }
+
+    /// Forgets a player without an update, returning whether it was visible.
+    pub fn remove(&mut self, uuid: &Uuid) -> bool {
+        self.visible.remove(uuid)
+    }
+
+    /// Recomputes which online players of the grid are seen from `center`. Adds a `NewPlayer`
+    /// update with the current state and name of every player that came into view and an
+    /// `OutOfView` update for every player that went out of view.
+    pub fn update(
+        &mut self,
+        own: Uuid,
+        center: Position,
+        grid: &PlayerGrid,
+        names: &HashMap<Uuid, String>,
+        updates: &mut Vec<Update>,
+    ) {
+        let mut visible = HashSet::with_capacity(self.visible.len());
+        let ViewDistance { view, hide } = grid.distance();
+        for player in grid.near(center) {
+            let uuid = Uuid::from_u128(player.uuid);
+            if uuid == own {
+                continue;
+            }
+            let distance = center.distance(player.position);
+            if self.visible.contains(&uuid) {
+                if distance <= hide {
+                    visible.insert(uuid);
+                }
+            } else if distance <= view {
+                visible.insert(uuid);
+                let name = names.get(&uuid).cloned().unwrap_or_default();
+                updates.push(Update::NewPlayer(*player, name));
+            }
+        }
+        for uuid in self.visible.difference(&visible) {
+            updates.push(Update::OutOfView(*uuid));
+        }
+        self.visible = visible;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+
+    const DISTANCE: ViewDistance = ViewDistance {
+        view: 10.0,
+        hide: 15.0,
+    };
+
+    const OWN: Uuid = Uuid::from_u128(1);
+
+    const OTHER: Uuid = Uuid::from_u128(2);
+
+    fn player(uuid: Uuid, x: f32, online: bool) -> Player {
+        Player {
+            uuid: uuid.as_u128(),
+            position: Position { x, y: 0.0, z: 0.0 },
+            online: online as u8,
+            ..Player::zeroed()
+        }
+    }
+
+    /// Updates the interest of the own player at the origin with the other player at `x`.
+    fn update(interest: &mut Interest, x: f32, online: bool) -> Vec<Update> {
+        let players = vec![player(OWN, 0.0, true), player(OTHER, x, online)];
+        let grid = PlayerGrid::new(DISTANCE, players.into_iter());
+        let mut names = HashMap::new();
+        names.insert(OTHER, "other".to_owned());
+        let mut updates = Vec::new();
+        let center = Position {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        interest.update(OWN, center, &grid, &names, &mut updates);
+        updates
+    }
+
+    #[test]
+    fn grid_finds_players_near_center() {
+        let players = (-5..=5).map(|x| player(Uuid::from_u128(x as u128), x as f32 * 10.0, true));
+        let grid = PlayerGrid::new(DISTANCE, players);
+        let center = Position {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let near: Vec<_> = grid.near(center).collect();
+        for x in -1..=1 {
+            let uuid = Uuid::from_u128(x as u128).as_u128();
+            assert!(near.iter().any(|player| player.uuid == uuid));
+        }
+        assert!(near
+            .iter()
+            .all(|player| player.position.x.abs() <= 2.0 * DISTANCE.hide));
+    }
+
+    #[test]
+    fn players_come_into_view_once() {
+        let mut interest = Interest::default();
+        assert!(update(&mut interest, 12.0, true).is_empty());
+        assert!(!interest.is_visible(&OTHER));
+        let updates = update(&mut interest, 8.0, true);
+        assert!(matches!(
+            updates.as_slice(),
+            [Update::NewPlayer(player, name)]
+                if player.uuid == OTHER.as_u128() && name == "other"
+        ));
+        assert!(interest.is_visible(&OTHER));
+        assert!(update(&mut interest, 9.0, true).is_empty());
+        assert!(!interest.is_visible(&OWN));
+    }
+
+    #[test]
+    fn players_go_out_of_view_beyond_hide_distance() {
+        let mut interest = Interest::default();
+        update(&mut interest, 5.0, true);
+        assert!(update(&mut interest, 12.0, true).is_empty());
+        assert!(interest.is_visible(&OTHER));
+        let updates = update(&mut interest, 16.0, true);
+        assert!(matches!(updates.as_slice(), [Update::OutOfView(uuid)] if *uuid == OTHER));
+        assert!(!interest.is_visible(&OTHER));
+        assert!(update(&mut interest, 12.0, true).is_empty());
+    }
+
+    #[test]
+    fn offline_players_are_not_seen() {
+        let mut interest = Interest::default();
+        assert!(update(&mut interest, 5.0, false).is_empty());
+        update(&mut interest, 5.0, true);
+        let updates = update(&mut interest, 5.0, false);
+        assert!(matches!(updates.as_slice(), [Update::OutOfView(uuid)] if *uuid == OTHER));
+    }
+
+    #[test]
+    fn left_players_are_forgotten() {
+        let mut interest = Interest::default();
+        assert!(!interest.remove(&OTHER));
+        update(&mut interest, 5.0, true);
+        assert!(interest.remove(&OTHER));
+        assert!(!interest.is_visible(&OTHER));
+        assert!(!interest.remove(&OTHER));
+        let updates = update(&mut interest, 5.0, true);
+        assert!(matches!(updates.as_slice(), [Update::NewPlayer(..)]));
+    }
+}
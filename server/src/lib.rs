@@ -1,30 +1,179 @@
+mod access;
+mod admin;
+mod autosave;
+mod chat;
+mod chunk;
+mod config;
+mod generator;
 mod handle;
+mod interest;
+mod limit;
 mod message;
+mod movement;
 mod service;
 mod state;
+mod stream;
+mod tick;
+mod token;
 mod user;
 
-use std::io;
+use std::{io, mem::take, path::Path};
 
-use db::Database;
+pub use access::*;
+pub use admin::{AdminError, DatabaseStats, OnlinePlayer, SERVER_NAME};
+pub(self) use autosave::{save, Autosave, WorldDatabase};
+pub use autosave::{AutosaveSettings, SaveReport};
+pub(self) use chat::ChatLimit;
+pub use chat::{chat_text, ChatMessage, CHAT_HISTORY, MAX_CHAT_LENGTH};
+pub use chunk::*;
+pub use config::*;
+use db::{Database, Format, Object, Version};
+pub use generator::*;
 pub(self) use handle::*;
+pub(self) use interest::{Interest, PlayerGrid, ViewDistance};
+pub(self) use limit::RateLimit;
 pub use message::*;
+pub use movement::MAX_SPEED;
+pub(self) use movement::{Bounds, Movement, MovementSystem};
 pub use service::*;
 pub use state::Orientation;
 pub use state::Player;
 pub use state::Position;
+pub use state::Profile;
 pub(self) use state::State;
 pub use state::Update;
-pub(self) use state::World;
+pub use state::World;
+pub use state::WorldData;
+pub(self) use stream::ChunkStream;
+use thiserror::Error;
+pub(self) use tick::{drive_ticks, TickMetrics};
+pub use tick::{System, TickContext, DEFAULT_TICK_RATE};
+pub use token::*;
 pub(self) use user::User;
 
 pub use net::Connection;
 pub use quinn::Certificate;
 
-pub const PROTOCOL: &str = "wosim/0.1";
+pub const PROTOCOL: &str = "wosim/0.2";
 
-pub fn create_world() -> io::Result<()> {
-    let mut db = Database::create("world.db", World::new)?;
-    db.snapshot()?;
-    Ok(())
+/// Application error code of connections closed because the player was kicked.
+pub const CLOSE_KICKED: u32 = 1;
+
+/// Application error code of connections closed because the player was banned.
+pub const CLOSE_BANNED: u32 = 2;
+
+/// Application error code of connections closed because the server shut down.
+pub const CLOSE_SHUTDOWN: u32 = 3;
+
+/// Application error code of connections closed because their token was not accepted.
+pub const CLOSE_UNAUTHENTICATED: u32 = 4;
+
+/// Application error code of connections closed because the server has no free slot.
+pub const CLOSE_FULL: u32 = 5;
+
+/// Application error code of connections whose user was authenticated but may not join.
+pub use net::CLOSE_REJECTED;
+
+/// Creates the world database at `path` with the built-in generators, see `create_world_with`.
+pub fn create_world(
+    path: impl AsRef<Path>,
+    settings: &GeneratorSettings,
+    secret: Option<&[u8]>,
+) -> Result<(), CreateWorldError> {
+    create_world_with(path, &GeneratorRegistry::default(), settings, secret)
+}
+
+/// Creates the world database at `path` with a generator of the registry, encrypted if a secret
+/// is given. The world is generated before the database is created, so invalid settings leave no
+/// database behind.
+pub fn create_world_with(
+    path: impl AsRef<Path>,
+    registry: &GeneratorRegistry,
+    settings: &GeneratorSettings,
+    secret: Option<&[u8]>,
+) -> Result<(), CreateWorldError> {
+    let blocks = registry.generate(settings)?;
+    let constructor = |database| World::new(database, blocks);
+    let mut db = match secret {
+        Some(secret) => Database::create_encrypted(path, secret, constructor),
+        None => Database::create(path, constructor),
+    }
+    .map_err(CreateWorldError::Database)?;
+    db.snapshot().map_err(CreateWorldError::Database)
+}
+
+#[derive(Debug, Error)]
+pub enum CreateWorldError {
+    #[error("could not generate world")]
+    Generate(#[from] GenerateError),
+    #[error("could not create database")]
+    Database(#[source] io::Error),
+}
+
+pub fn world_versions(path: impl AsRef<Path>, secret: Option<&[u8]>) -> io::Result<Vec<Version>> {
+    Ok(open_world(path, secret)?.versions())
+}
+
+pub fn restore_world(
+    path: impl AsRef<Path>,
+    version: u64,
+    secret: Option<&[u8]>,
+) -> io::Result<()> {
+    match secret {
+        Some(secret) => Database::<World>::restore_encrypted(path, version, secret),
+        None => Database::<World>::restore(path, version),
+    }
+}
+
+/// Reads the access control of the world database at `path`, which must not be in use.
+pub fn world_access(path: impl AsRef<Path>, secret: Option<&[u8]>) -> io::Result<AccessControl> {
+    open_world(path, secret)?.access()
+}
+
+/// Replaces the access control of the world database at `path`, which must not be in use.
+pub fn set_world_access(
+    path: impl AsRef<Path>,
+    access: &AccessControl,
+    secret: Option<&[u8]>,
+) -> io::Result<()> {
+    let mut db = open_world(path, secret)?;
+    db.set_access(access)?;
+    db.snapshot()
+}
+
+/// The format of world databases, to tell them apart from other databases.
+pub fn world_format() -> Format {
+    World::format()
+}
+
+/// Reads the collections of the world database at `path`, which must not be in use.
+pub fn export_world(path: impl AsRef<Path>, secret: Option<&[u8]>) -> io::Result<WorldData> {
+    open_world(path, secret)?.export()
+}
+
+/// Creates a world database at `path` from exported collections.
+pub fn import_world(
+    path: impl AsRef<Path>,
+    mut data: WorldData,
+    secret: Option<&[u8]>,
+) -> io::Result<()> {
+    data.validate()?;
+    let profiles = take(&mut data.profiles);
+    let chat = take(&mut data.chat);
+    let access = take(&mut data.access);
+    let mut db = match secret {
+        Some(secret) => {
+            Database::create_encrypted(path, secret, |database| World::import(database, data))?
+        }
+        None => Database::create(path, |database| World::import(database, data))?,
+    };
+    db.import_records(profiles, chat, &access)?;
+    db.snapshot()
+}
+
+fn open_world(path: impl AsRef<Path>, secret: Option<&[u8]>) -> io::Result<Database<World>> {
+    match secret {
+        Some(secret) => Database::open_encrypted(path, secret),
+        None => Database::open(path),
+    }
 }
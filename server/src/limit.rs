@@ -0,0 +1,87 @@
+use std::time::Instant;
+
+/// An allowance that builds up over time at a fixed rate, up to a burst, and that actions of a
+/// player spend, such as chat messages or the distance it moves.
+pub struct RateLimit {
+    rate: f32,
+    burst: f32,
+    allowed: f32,
+    last_update: Instant,
+}
+
+impl RateLimit {
+    /// Starts with the whole burst allowed.
+    pub fn new(rate: f32, burst: f32) -> Self {
+        Self {
+            rate,
+            burst,
+            allowed: burst,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Adds the allowance built up since the last update.
+    pub fn update(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_update)
+            .as_secs_f32();
+        self.last_update = now;
+        self.allowed = (self.allowed + elapsed * self.rate).min(self.burst);
+    }
+
+    /// Spends `amount` of the allowance if that much is left, otherwise nothing.
+    pub fn spend(&mut self, amount: f32) -> bool {
+        if amount <= self.allowed {
+            self.allowed -= amount;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The allowance that is left.
+    pub fn allowed(&self) -> f32 {
+        self.allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const RATE: f32 = 2.0;
+    const BURST: f32 = 5.0;
+
+    #[test]
+    fn burst_is_limited() {
+        let mut limit = RateLimit::new(RATE, BURST);
+        limit.update(limit.last_update);
+        assert!(limit.spend(BURST - 1.0));
+        assert!(limit.spend(1.0));
+        assert!(!limit.spend(0.5));
+        assert_eq!(limit.allowed(), 0.0);
+    }
+
+    #[test]
+    fn allowance_builds_up_to_burst() {
+        let mut limit = RateLimit::new(RATE, BURST);
+        let start = limit.last_update;
+        assert!(limit.spend(BURST));
+        limit.update(start + Duration::from_millis(250));
+        assert!(!limit.spend(1.0));
+        limit.update(start + Duration::from_millis(500));
+        assert!(limit.spend(1.0));
+        assert!(!limit.spend(0.5));
+        limit.update(start + Duration::from_secs(3600));
+        assert_eq!(limit.allowed(), BURST);
+    }
+
+    #[test]
+    fn failed_spend_keeps_allowance() {
+        let mut limit = RateLimit::new(RATE, BURST);
+        assert!(!limit.spend(BURST + 1.0));
+        assert!(limit.spend(BURST));
+    }
+}
@@ -1,18 +1,21 @@
-use std::sync::Arc;
+use std::{io, time::Duration};
 
 use net::{Message, OutgoingMessage};
 use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
 use crate::{
     state::{Orientation, Position, Update},
-    Player, User,
+    AdminError, ChatMessage, Chunk, ChunkCoord, DatabaseStats, OnlinePlayer, Player, SaveReport,
+    User, MAX_CHAT_LENGTH,
 };
 
 #[derive(Debug)]
 pub enum Request {
     UpdateSelf(SelfUpdate),
     Shutdown,
+    Chat(String),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,8 +26,23 @@ pub(crate) enum ServerMessage {
     Connected(User),
     Disconnected(User),
     Request(User, Request),
-    Stop,
-    PushUpdates,
+    /// Saves the world and stops the main loop, which keeps running if saving fails.
+    Stop(oneshot::Sender<io::Result<()>>),
+    Tick,
+    Log,
+    ListPlayers(oneshot::Sender<Vec<OnlinePlayer>>),
+    /// Kicks a player, given by name or uuid, for a reason.
+    Kick(String, String, oneshot::Sender<Result<(), AdminError>>),
+    /// Bans a player, given by name or uuid, for a reason and a number of seconds or forever.
+    Ban(
+        String,
+        String,
+        Option<u64>,
+        oneshot::Sender<Result<(), AdminError>>,
+    ),
+    Broadcast(String),
+    Snapshot(oneshot::Sender<io::Result<Duration>>),
+    DatabaseStats(oneshot::Sender<DatabaseStats>),
 }
 
 impl Message for Request {
@@ -34,6 +52,7 @@ impl Message for Request {
         match self {
             Request::UpdateSelf(value) => Ok(OutgoingMessage::datagram(1, value)?),
             Request::Shutdown => Ok(OutgoingMessage::uni(2, ())?),
+            Request::Chat(text) => Ok(OutgoingMessage::uni(3, text)?),
         }
     }
 
@@ -43,12 +62,17 @@ impl Message for Request {
         match message.id() {
             1 => Ok(Self::UpdateSelf(message.value()?)),
             2 => Ok(Self::Shutdown),
+            3 => Ok(Self::Chat(message.value()?)),
             _ => Err(message.invalid_id_error()),
         }
     }
 
-    fn size_limit(_message_id: u32) -> usize {
-        0
+    fn size_limit(message_id: u32) -> usize {
+        match message_id {
+            // The length prefix and up to four bytes per character.
+            3 => 8 + 4 * MAX_CHAT_LENGTH,
+            _ => 0,
+        }
     }
 }
 
@@ -56,13 +80,26 @@ impl Message for Request {
 pub enum Push {
     Setup(Setup),
     Updates(UpdateBatch),
+    Chunks(ChunkBatch),
+    /// The position the server has for the player, after it rejected a move.
+    Correction(Position),
+    Chat(ChatMessage),
+    Saved(SaveReport),
 }
 
+/// The uuid of the player, the players it sees, including itself, with their names and the
+/// recent chat messages.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Setup(pub Uuid, pub Vec<(Player, String)>, pub Vec<ChatMessage>);
+
+/// The updates of a tick, with the number of the tick.
 #[derive(Serialize, Deserialize, Debug)]
-pub struct Setup(pub Uuid, pub Vec<Player>, pub Vec<Position>);
+pub struct UpdateBatch(pub u64, pub Vec<Update>);
 
+/// Chunks that came into range of the player, nearest first, and chunks to drop. The chunks to
+/// drop have to be removed before the new ones are added.
 #[derive(Serialize, Deserialize, Debug)]
-pub struct UpdateBatch(pub Arc<Vec<Update>>, pub usize);
+pub struct ChunkBatch(pub Vec<(ChunkCoord, Chunk)>, pub Vec<ChunkCoord>);
 
 impl Message for Push {
     fn into_outgoing(
@@ -71,6 +108,10 @@ impl Message for Push {
         match self {
             Push::Setup(setup) => Ok(OutgoingMessage::uni(1, setup)?),
             Push::Updates(updates) => Ok(OutgoingMessage::uni(2, updates)?),
+            Push::Chunks(chunks) => Ok(OutgoingMessage::uni(3, chunks)?),
+            Push::Correction(position) => Ok(OutgoingMessage::uni(4, position)?),
+            Push::Chat(message) => Ok(OutgoingMessage::uni(5, message)?),
+            Push::Saved(report) => Ok(OutgoingMessage::uni(6, report)?),
         }
     }
 
@@ -80,6 +121,10 @@ impl Message for Push {
         match message.id() {
             1 => Ok(Self::Setup(message.value()?)),
             2 => Ok(Self::Updates(message.value()?)),
+            3 => Ok(Self::Chunks(message.value()?)),
+            4 => Ok(Self::Correction(message.value()?)),
+            5 => Ok(Self::Chat(message.value()?)),
+            6 => Ok(Self::Saved(message.value()?)),
             _ => Err(message.invalid_id_error()),
         }
     }
@@ -0,0 +1,194 @@
+use std::time::Instant;
+
+use db::Tree;
+use thiserror::Error;
+
+use crate::{
+    Chunk, ChunkCoord, Position, RateLimit, System, TickContext, BLOCK_SPACING, CHUNK_SIZE,
+};
+
+/// Speed at which players move, in units per second.
+pub const MAX_SPEED: f32 = 10.0;
+
+/// Factor on the speed that is still accepted. Clients move at full speed along each horizontal
+/// axis at once, which is faster by a factor of √2.
+const SPEED_TOLERANCE: f32 = 1.5;
+
+/// Movement a player may save up while standing still, in seconds at full speed, so that updates
+/// delayed by the network and then delivered together are not taken as too fast.
+const BURST: f32 = 0.5;
+
+/// Invalid moves in a row after which a player is reported.
+const VIOLATION_LIMIT: u32 = 10;
+
+/// Distance in chunks by which players may leave the chunks of the world.
+const BOUNDS_MARGIN: i32 = 4;
+
+/// The box players have to stay in.
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl Bounds {
+    /// The box around all chunks of the world, extended by a margin.
+    pub fn of_world(chunks: &Tree<ChunkCoord, Chunk>) -> Self {
+        let chunks = chunks.read();
+        let mut min = ChunkCoord { x: 0, y: 0, z: 0 };
+        let mut max = min;
+        for (coord, _) in chunks.iter() {
+            min.x = min.x.min(coord.x);
+            min.y = min.y.min(coord.y);
+            min.z = min.z.min(coord.z);
+            max.x = max.x.max(coord.x + 1);
+            max.y = max.y.max(coord.y + 1);
+            max.z = max.z.max(coord.z + 1);
+        }
+        let position = |chunk: i32| (chunk * CHUNK_SIZE) as f32 * BLOCK_SPACING;
+        Self {
+            min: Position {
+                x: position(min.x - BOUNDS_MARGIN),
+                y: position(min.y - BOUNDS_MARGIN),
+                z: position(min.z - BOUNDS_MARGIN),
+            },
+            max: Position {
+                x: position(max.x + BOUNDS_MARGIN),
+                y: position(max.y + BOUNDS_MARGIN),
+                z: position(max.z + BOUNDS_MARGIN),
+            },
+        }
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        (self.min.x..=self.max.x).contains(&position.x)
+            && (self.min.y..=self.max.y).contains(&position.y)
+            && (self.min.z..=self.max.z).contains(&position.z)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MoveError {
+    #[error("moved {distance} units with {allowed} allowed")]
+    TooFast { distance: f32, allowed: f32 },
+    #[error("left the world bounds")]
+    OutOfBounds,
+}
+
+/// Checks the moves of a player against the time that passed on the server. The distance a
+/// player may move builds up over time at the maximum speed, up to a limit.
+pub struct Movement {
+    limit: RateLimit,
+    violations: u32,
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        let speed = MAX_SPEED * SPEED_TOLERANCE;
+        Self {
+            limit: RateLimit::new(speed, speed * BURST),
+            violations: 0,
+        }
+    }
+}
+
+impl Movement {
+    /// Checks a move, which is accepted if it returns `Ok`.
+    pub fn check(
+        &mut self,
+        from: Position,
+        to: Position,
+        bounds: &Bounds,
+    ) -> Result<(), MoveError> {
+        self.check_at(from, to, bounds, Instant::now())
+    }
+
+    fn check_at(
+        &mut self,
+        from: Position,
+        to: Position,
+        bounds: &Bounds,
+        now: Instant,
+    ) -> Result<(), MoveError> {
+        self.limit.update(now);
+        let result = if !bounds.contains(to) {
+            Err(MoveError::OutOfBounds)
+        } else {
+            let distance = from.distance(to);
+            if self.limit.spend(distance) {
+                Ok(())
+            } else {
+                Err(MoveError::TooFast {
+                    distance,
+                    allowed: self.limit.allowed(),
+                })
+            }
+        };
+        match result {
+            Ok(()) => self.violations = 0,
+            Err(_) => self.violations += 1,
+        }
+        result
+    }
+
+    /// Whether the invalid moves in a row just reached the limit at which they are reported.
+    pub fn is_repeated_violation(&self) -> bool {
+        self.violations == VIOLATION_LIMIT
+    }
+}
+
+/// Applies the moves that were accepted since the previous tick.
+pub(crate) struct MovementSystem;
+
+impl System for MovementSystem {
+    fn run(&mut self, context: &mut TickContext) {
+        for (uuid, (position, orientation)) in context.moves.drain() {
+            context
+                .world
+                .update_player(uuid, position, orientation, context.updates);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: Bounds = Bounds {
+        min: Position {
+            x: -1000.0,
+            y: -1000.0,
+            z: -1000.0,
+        },
+        max: Position {
+            x: 1000.0,
+            y: 1000.0,
+            z: 1000.0,
+        },
+    };
+
+    fn at(x: f32) -> Position {
+        Position { x, y: 0.0, z: 0.0 }
+    }
+
+    #[test]
+    fn reject_leaving_bounds() {
+        let mut movement = Movement::default();
+        let now = Instant::now();
+        let result = movement.check_at(at(999.0), at(1001.0), &BOUNDS, now);
+        assert!(matches!(result, Err(MoveError::OutOfBounds)));
+    }
+
+    #[test]
+    fn report_repeated_violations() {
+        let mut movement = Movement::default();
+        let now = Instant::now();
+        for _ in 0..VIOLATION_LIMIT {
+            assert!(!movement.is_repeated_violation());
+            assert!(movement.check_at(at(0.0), at(100.0), &BOUNDS, now).is_err());
+        }
+        assert!(movement.is_repeated_violation());
+        assert!(movement.check_at(at(0.0), at(0.0), &BOUNDS, now).is_ok());
+        assert!(!movement.is_repeated_violation());
+    }
+}
@@ -1,23 +1,54 @@
 use std::{
-    collections::HashMap, env::current_dir, fmt::Debug, io, string::FromUtf8Error, sync::Mutex,
-    time::Duration,
+    collections::HashMap,
+    env::current_dir,
+    fmt::Debug,
+    io,
+    string::FromUtf8Error,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
-use crate::{handle, ControlFlow, Push, Request, ServerMessage, State, User, PROTOCOL};
+use crate::{
+    drive_ticks, handle, open_world, AccessControl, AdminError, Authentication, Autosave, Bounds,
+    ControlFlow, DatabaseStats, MovementSystem, OnlinePlayer, Permission, Push, Request, Role,
+    ServerConfig, ServerMessage, State, System, TickMetrics, User, WorldDatabase, CLOSE_BANNED,
+    CLOSE_FULL, CLOSE_REJECTED, CLOSE_UNAUTHENTICATED, PROTOCOL,
+};
 use base64::DecodeError;
-use db::Database;
 use log::error;
-use net::{AuthToken, Connection};
+use net::{AuthToken, Connection, Rejection};
 use quinn::TransportConfig;
 use thiserror::Error;
-use tokio::{spawn, sync::mpsc, task::JoinHandle, time::interval};
-use uuid::Uuid;
+use tokio::{
+    spawn,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::{interval, sleep},
+};
 
-const CHANNEL_BOUND: usize = 16;
+/// Counts the users who are online against the maximum number of players.
+#[derive(Clone)]
+struct Slots {
+    max_players: Option<usize>,
+    /// Slots in addition to the maximum number of players for users with the permission.
+    reserved: usize,
+    /// Number of users connected or connecting, counted from their authentication until the
+    /// main loop knows they disconnected.
+    online: Arc<AtomicUsize>,
+}
 
 pub struct Service {
     name: String,
     description: String,
+    authentication: Authentication,
+    access: Arc<RwLock<AccessControl>>,
+    slots: Slots,
+    /// The session of the next user who authenticates.
+    sessions: AtomicU64,
+    channel_bound: usize,
     tx: mpsc::Sender<ServerMessage>,
     handle: Mutex<Option<JoinHandle<()>>>,
 }
@@ -30,23 +61,78 @@ pub enum CreateServiceError {
     CurrentDirIsRootDir,
     #[error("could not open database")]
     OpenDatabase(#[source] io::Error),
+    #[error("could not read access control")]
+    ReadAccessControl(#[source] io::Error),
+    #[error("no trusted keys are configured and insecure authentication is not enabled")]
+    NoTrustedKeys,
+    #[error("could not decode trusted key of issuer '{0}'")]
+    DecodeTrustedKey(String, #[source] DecodeError),
+    #[error("trusted key of issuer '{0}' has {1} bytes instead of 32")]
+    InvalidTrustedKey(String, usize),
+    #[error("push rate {0} does not divide the tick rate {1}")]
+    InvalidPushRate(u32, u32),
 }
 
 impl Service {
-    pub fn new() -> Result<Self, CreateServiceError> {
-        let path = current_dir().map_err(CreateServiceError::NoCurrentDir)?;
-        let name = path
-            .file_name()
-            .ok_or(CreateServiceError::CurrentDirIsRootDir)?
-            .to_string_lossy()
-            .to_string();
-        let (tx, mut rx) = mpsc::channel(CHANNEL_BOUND);
-        let database = Database::open("world.db").map_err(CreateServiceError::OpenDatabase)?;
+    /// Opens the world database of the configuration, which is encrypted if a secret is given,
+    /// and starts ticking.
+    pub fn new(secret: Option<&[u8]>, config: ServerConfig) -> Result<Self, CreateServiceError> {
+        Self::with_systems(secret, config, Vec::new())
+    }
+
+    /// Like `new`, but also runs the given systems each tick after the built-in ones.
+    pub fn with_systems(
+        secret: Option<&[u8]>,
+        config: ServerConfig,
+        systems: Vec<Box<dyn System>>,
+    ) -> Result<Self, CreateServiceError> {
+        let name = match config.name {
+            Some(name) => name,
+            None => current_dir()
+                .map_err(CreateServiceError::NoCurrentDir)?
+                .file_name()
+                .ok_or(CreateServiceError::CurrentDirIsRootDir)?
+                .to_string_lossy()
+                .to_string(),
+        };
+        let authentication = config.auth.authentication()?;
+        let push_interval = config.push_interval()?;
+        let channel_bound = config.channel_bound();
+        let view_distance = config.view_distance();
+        let log_interval = config.log_interval();
+        let (tx, mut rx) = mpsc::channel(channel_bound);
+        let mut database =
+            open_world(&config.world, secret).map_err(CreateServiceError::OpenDatabase)?;
+        database.set_retention(config.retention.retention());
+        database.reset_presence();
+        let access = database
+            .access()
+            .map_err(CreateServiceError::ReadAccessControl)?;
+        let access = Arc::new(RwLock::new(access));
+        let bounds = Bounds::of_world(&database.chunks);
+        let tick_period = Duration::from_secs(1) / config.tick_rate.max(1);
+        let motd = config.motd;
+        let autosave = config.autosave;
+        let mut all_systems: Vec<Box<dyn System>> = vec![Box::new(MovementSystem)];
+        all_systems.extend(systems);
         let handle = Mutex::new(Some(spawn(async move {
             let mut state = State {
-                database,
+                database: WorldDatabase::new(database),
                 updates: Vec::new(),
                 observers: HashMap::new(),
+                names: HashMap::new(),
+                bounds,
+                tick: 0,
+                last_tick: Instant::now(),
+                moves: HashMap::new(),
+                push_interval,
+                systems: all_systems,
+                tick_metrics: TickMetrics::new(tick_period),
+                access: access.clone(),
+                autosave: Autosave::new(autosave),
+                motd,
+                channel_bound,
+                view_distance,
             };
             while let Some(message) = rx.recv().await {
                 if let ControlFlow::Stop = handle(&mut state, message).await {
@@ -54,33 +140,177 @@ impl Service {
                 }
             }
         })));
-        {
-            let tx = tx.clone();
-            spawn(async move {
-                let mut interval = interval(Duration::from_millis(1000 / 30));
-                loop {
-                    interval.tick().await;
-                    if tx.send(ServerMessage::PushUpdates).await.is_err() {
-                        break;
-                    }
-                }
-            });
-        }
-        let description = "".to_owned();
+        drive_ticks(tx.clone(), tick_period);
+        repeat(tx.clone(), log_interval, || ServerMessage::Log);
         Ok(Self {
             name,
-            description,
+            description: config.description,
+            slots: Slots {
+                max_players: config.max_players,
+                reserved: config.reserved_slots,
+                online: Arc::new(AtomicUsize::new(0)),
+            },
+            sessions: AtomicU64::new(0),
+            channel_bound,
+            authentication,
+            access,
             tx,
             handle,
         })
     }
 
-    pub async fn stop(&self) {
-        self.tx.send(ServerMessage::Stop).await.unwrap();
-        self.handle.lock().unwrap().take().unwrap().await.unwrap();
+    /// Saves the world, then disconnects all players and stops the main loop. If saving fails,
+    /// the error is returned and the service keeps running with the players still connected, so
+    /// that stopping can be retried.
+    pub async fn stop(&self) -> Result<(), AdminError> {
+        self.ask(ServerMessage::Stop)
+            .await?
+            .map_err(AdminError::Database)?;
+        let handle = self.handle.lock().unwrap().take();
+        if let Some(handle) = handle {
+            handle.await.unwrap();
+        }
+        Ok(())
+    }
+
+    /// Announces the shutdown to the players while counting down, but does not stop the service.
+    pub async fn count_down(&self, countdown: Duration) -> Result<(), AdminError> {
+        let mut remaining = countdown.as_secs();
+        while remaining > 0 {
+            if remaining % 60 == 0 || remaining == 30 || remaining == 10 || remaining <= 5 {
+                let text = format!("The server shuts down in {} seconds", remaining);
+                self.broadcast(text).await?;
+            }
+            sleep(Duration::from_secs(1)).await;
+            remaining -= 1;
+        }
+        Ok(())
+    }
+
+    pub async fn online_players(&self) -> Result<Vec<OnlinePlayer>, AdminError> {
+        self.ask(ServerMessage::ListPlayers).await
+    }
+
+    /// Disconnects a player who is online, given by name or uuid.
+    pub async fn kick(&self, player: String, reason: String) -> Result<(), AdminError> {
+        self.ask(|tx| ServerMessage::Kick(player, reason, tx))
+            .await?
+    }
+
+    /// Bans a player for a number of seconds or forever and disconnects the player if online.
+    /// Players who are offline have to be given by uuid.
+    pub async fn ban(
+        &self,
+        player: String,
+        reason: String,
+        duration: Option<u64>,
+    ) -> Result<(), AdminError> {
+        self.ask(|tx| ServerMessage::Ban(player, reason, duration, tx))
+            .await?
+    }
+
+    /// Sends a chat message from the server to all players.
+    pub async fn broadcast(&self, text: String) -> Result<(), AdminError> {
+        self.tx
+            .send(ServerMessage::Broadcast(text))
+            .await
+            .map_err(|_| AdminError::Stopped)
+    }
+
+    /// Takes a snapshot of the world database and returns how long it took.
+    pub async fn snapshot(&self) -> Result<Duration, AdminError> {
+        self.ask(ServerMessage::Snapshot)
+            .await?
+            .map_err(AdminError::Database)
+    }
+
+    pub async fn database_stats(&self) -> Result<DatabaseStats, AdminError> {
+        self.ask(ServerMessage::DatabaseStats).await
+    }
+
+    /// Sends a message to the main loop and waits for its answer.
+    async fn ask<T>(
+        &self,
+        message: impl FnOnce(oneshot::Sender<T>) -> ServerMessage,
+    ) -> Result<T, AdminError> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send(message(tx))
+            .await
+            .map_err(|_| AdminError::Stopped)?;
+        rx.await.map_err(|_| AdminError::Stopped)
     }
 }
 
+/// Passes the requests of a user to the main loop, framed by the connect and disconnect messages.
+async fn forward_requests(
+    tx: mpsc::Sender<ServerMessage>,
+    mut rx: mpsc::Receiver<Request>,
+    user: User,
+) {
+    if let Err(error) = tx.send(ServerMessage::Connected(user.clone())).await {
+        error!("{}", error);
+        return;
+    }
+    while let Some(request) = rx.recv().await {
+        if let Request::Shutdown = request {
+            break;
+        }
+        if let Err(error) = tx.send(ServerMessage::Request(user.clone(), request)).await {
+            error!("{}", error);
+            return;
+        }
+    }
+    if let Err(error) = tx.send(ServerMessage::Disconnected(user)).await {
+        error!("{}", error)
+    }
+}
+
+impl Slots {
+    /// Counts a user as online unless the server is full. Users with the permission to use a
+    /// reserved slot may join until the reserved slots are taken as well.
+    fn take(&self, role: Role) -> Result<(), AuthenticationError> {
+        let limit = match self.max_players {
+            Some(max_players) if role.has(Permission::ReservedSlot) => {
+                max_players.saturating_add(self.reserved)
+            }
+            Some(max_players) => max_players,
+            None => usize::MAX,
+        };
+        self.online
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |online| {
+                if online < limit {
+                    Some(online + 1)
+                } else {
+                    None
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| AuthenticationError::ServerFull)
+    }
+
+    /// Frees the slot of a user who disconnected.
+    fn release(&self) {
+        self.online.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn repeat(
+    tx: mpsc::Sender<ServerMessage>,
+    period: Duration,
+    message: impl Fn() -> ServerMessage + Send + 'static,
+) {
+    spawn(async move {
+        let mut interval = interval(period);
+        loop {
+            interval.tick().await;
+            if tx.send(message()).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
 impl net::Service for Service {
     type AuthError = AuthenticationError;
     type Push = Push;
@@ -95,51 +325,41 @@ impl net::Service for Service {
             AuthToken::Local(token) => token,
             AuthToken::Remote(token) => token,
         };
-        let mut split = token.split('#');
-        let uuid = if let Some(uuid) = split.next() {
-            Uuid::parse_str(uuid).map_err(AuthenticationError::ParseUuid)?
-        } else {
-            return Err(AuthenticationError::EmptyToken);
-        };
-        let name = if let Some(username) = split.next() {
-            String::from_utf8(
-                base64::decode(username).map_err(AuthenticationError::DecodeUsername)?,
-            )
-            .map_err(AuthenticationError::IllformedUsername)?
-        } else {
-            return Err(AuthenticationError::MissingTokenSeparator);
-        };
+        let (uuid, name) = self.authentication.verify(token)?;
+        let role = self.access.read().unwrap().check(uuid)?;
+        self.slots.take(role)?;
         let user = User {
             uuid,
             name,
+            role,
             connection,
+            session: self.sessions.fetch_add(1, Ordering::Relaxed),
         };
-        let (tx, mut rx) = mpsc::channel(CHANNEL_BOUND);
+        let (tx, rx) = mpsc::channel(self.channel_bound);
         {
             let tx = self.tx.clone();
+            let slots = self.slots.clone();
             spawn(async move {
-                if let Err(error) = tx.send(ServerMessage::Connected(user.clone())).await {
-                    error!("{}", error);
-                    return;
-                }
-                while let Some(request) = rx.recv().await {
-                    if let Request::Shutdown = request {
-                        break;
-                    }
-                    if let Err(error) = tx.send(ServerMessage::Request(user.clone(), request)).await
-                    {
-                        error!("{}", error);
-                        return;
-                    }
-                }
-                if let Err(error) = tx.send(ServerMessage::Disconnected(user)).await {
-                    error!("{}", error)
-                }
+                forward_requests(tx, rx, user).await;
+                slots.release();
             });
         }
         Ok(tx)
     }
 
+    fn rejection(&self, error: &Self::AuthError) -> Rejection {
+        let code = match error {
+            AuthenticationError::Banned { .. } => CLOSE_BANNED,
+            AuthenticationError::ServerFull => CLOSE_FULL,
+            AuthenticationError::NotWhitelisted => CLOSE_REJECTED,
+            _ => CLOSE_UNAUTHENTICATED,
+        };
+        Rejection {
+            code,
+            reason: error.to_string(),
+        }
+    }
+
     fn token_size_limit(&self) -> usize {
         4096
     }
@@ -153,7 +373,7 @@ impl net::Service for Service {
     }
 
     fn authentication_type(&self) -> &str {
-        "none"
+        self.authentication.kind()
     }
 
     fn name(&self) -> &str {
@@ -173,7 +393,7 @@ impl net::Service for Service {
 
 #[derive(Debug, Error)]
 pub enum AuthenticationError {
-    #[error("separator '#' is missing")]
+    #[error("token separator is missing")]
     MissingTokenSeparator,
     #[error("could not parse uuid")]
     ParseUuid(#[source] uuid::Error),
@@ -183,4 +403,62 @@ pub enum AuthenticationError {
     IllformedUsername(#[source] FromUtf8Error),
     #[error("token is empty")]
     EmptyToken,
+    #[error("could not decode token")]
+    DecodeToken(#[source] DecodeError),
+    #[error("claims of token are ill-formed")]
+    IllformedClaims(#[source] bincode::Error),
+    #[error("issuer '{0}' is not trusted")]
+    UntrustedIssuer(String),
+    #[error("signature of token is invalid")]
+    InvalidSignature,
+    #[error("token has expired")]
+    Expired,
+    #[error("banned: {reason}")]
+    Banned { reason: String, until: Option<u64> },
+    #[error("not on the whitelist")]
+    NotWhitelisted,
+    #[error("the server is full")]
+    ServerFull,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slots(max_players: Option<usize>, reserved: usize) -> Slots {
+        Slots {
+            max_players,
+            reserved,
+            online: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    #[test]
+    fn reserved_slots_are_for_admins() {
+        let slots = slots(Some(2), 1);
+        assert!(slots.take(Role::Player).is_ok());
+        assert!(slots.take(Role::Moderator).is_ok());
+        assert!(matches!(
+            slots.take(Role::Player),
+            Err(AuthenticationError::ServerFull)
+        ));
+        assert!(slots.take(Role::Admin).is_ok());
+        assert!(matches!(
+            slots.take(Role::Admin),
+            Err(AuthenticationError::ServerFull)
+        ));
+        slots.release();
+        slots.release();
+        assert!(slots.take(Role::Player).is_ok());
+        assert!(slots.take(Role::Player).is_err());
+        assert_eq!(slots.online.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn unlimited_without_maximum() {
+        let slots = slots(None, 0);
+        for _ in 0..100 {
+            assert!(slots.take(Role::Player).is_ok());
+        }
+    }
 }
@@ -1,26 +1,66 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{Arc, RwLock},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 use bytemuck::{Pod, Zeroable};
-use db::{Database, Entry, Len, Object, Tree};
-use noise::{NoiseFn, Perlin};
+use db::{Blob, Entry, Layout, Len, Object, Tree};
+use net::Connection;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
-use crate::Push;
+use crate::{
+    AccessControl, Autosave, Bounds, ChatLimit, ChatMessage, Chunk, ChunkCoord, ChunkStream,
+    Interest, Movement, Push, Role, System, TickMetrics, ViewDistance, WorldDatabase, CHAT_HISTORY,
+};
+
+const ACCESS_KEY: u8 = 0;
 
 pub(super) struct State {
-    pub database: Database<World>,
+    pub database: WorldDatabase,
     pub updates: Vec<Update>,
     pub observers: HashMap<Uuid, Observer>,
+    /// Display names of the connected players.
+    pub names: HashMap<Uuid, String>,
+    pub bounds: Bounds,
+    /// Number of the current tick, counting from the start of the server.
+    pub tick: u64,
+    /// When the current tick started.
+    pub last_tick: Instant,
+    /// The last accepted move of each player since the previous tick, see `MovementSystem`.
+    pub moves: HashMap<Uuid, (Position, Orientation)>,
+    /// Ticks from one push of updates to the next.
+    pub push_interval: u64,
+    pub systems: Vec<Box<dyn System>>,
+    pub tick_metrics: TickMetrics,
+    /// The access control the service checks connecting users against.
+    pub access: Arc<RwLock<AccessControl>>,
+    pub autosave: Autosave,
+    /// Message of the day, sent to players when they join.
+    pub motd: Option<String>,
+    /// Capacity of the push channels of the observers.
+    pub channel_bound: usize,
+    pub view_distance: ViewDistance,
 }
 
 pub struct Observer {
     pub sync_push: mpsc::Sender<Push>,
     pub after_update: usize,
+    pub chunks: ChunkStream,
+    pub interest: Interest,
+    pub movement: Movement,
+    pub chat_limit: ChatLimit,
+    pub role: Role,
+    pub connection: Connection<Push>,
+    pub connected: Instant,
+    /// The session of the user this observer belongs to, see `User::session`.
+    pub session: u64,
 }
 
-#[derive(Clone, Copy, Debug, Pod, Zeroable, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, Layout, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Position {
     pub x: f32,
@@ -28,7 +68,14 @@ pub struct Position {
     pub z: f32,
 }
 
-#[derive(Clone, Copy, Debug, Pod, Zeroable, Serialize, Deserialize)]
+impl Position {
+    pub fn distance(self, other: Self) -> f32 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2))
+            .sqrt()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Pod, Zeroable, Layout, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Orientation {
     pub roll: f32,
@@ -36,58 +83,231 @@ pub struct Orientation {
     pub yaw: f32,
 }
 
+#[derive(Object)]
 pub struct World {
-    pub positions: db::Vec<Position>,
+    pub chunks: Tree<ChunkCoord, Chunk>,
     pub players: db::Vec<Player>,
     pub player_index: Tree<u128, usize>,
+    pub profiles: Blob<u128, Profile>,
+    /// The recent chat messages by sequence number.
+    pub chat: Blob<u64, ChatMessage>,
+    /// The access control of the server, stored under `ACCESS_KEY`.
+    pub access: Blob<u8, AccessControl>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, Zeroable, Pod)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Zeroable, Pod, Layout)]
 #[repr(C)]
 pub struct Player {
     pub uuid: u128,
     pub position: Position,
     pub orientation: Orientation,
-    pub _padding: [u8; 8],
+    /// 1 while the player is connected, 0 otherwise.
+    pub online: u8,
+    pub _padding: [u8; 7],
+}
+
+/// What is kept about a player across sessions. Times are in seconds, points in time since the
+/// Unix epoch.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    pub first_seen: u64,
+    /// When the player last connected while it is online, else when it last disconnected.
+    pub last_seen: u64,
+    pub play_time: u64,
+    pub logins: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Player {
+    pub fn is_online(&self) -> bool {
+        self.online != 0
+    }
+}
+
+/// The contents of a world outside of its database, used to export and import worlds.
+#[derive(Serialize, Deserialize)]
+pub struct WorldData {
+    pub chunks: Vec<(ChunkCoord, Chunk)>,
+    pub players: Vec<Player>,
+    pub player_index: Vec<(Uuid, usize)>,
+    #[serde(default)]
+    pub profiles: Vec<(Uuid, Profile)>,
+    #[serde(default)]
+    pub chat: Vec<ChatMessage>,
+    #[serde(default)]
+    pub access: AccessControl,
+}
+
+impl WorldData {
+    /// Checks that the player index refers to the players it is keyed by.
+    pub fn validate(&self) -> io::Result<()> {
+        for (uuid, index) in &self.player_index {
+            if self.players.get(*index).map(|player| player.uuid) != Some(uuid.as_u128()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("player index entry of {} does not match a player", uuid),
+                ));
+            }
+        }
+        for (uuid, _) in &self.profiles {
+            if !self.player_index.iter().any(|(player, _)| player == uuid) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("profile of {} does not belong to a player", uuid),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum Update {
-    NewPlayer(Player),
+    /// A player came into view, with its name.
+    NewPlayer(Player, String),
     Player(Uuid, Position, Orientation),
+    /// A player went out of view.
+    OutOfView(Uuid),
+    /// A player disconnected.
+    PlayerLeft(Uuid),
 }
 
 impl World {
-    pub fn new(database: db::DatabaseRef) -> Self {
-        let perlin = Perlin::new();
-        let mut positions = db::Vec::new(database.clone());
+    pub fn new(database: db::DatabaseRef, generated: BTreeMap<ChunkCoord, Chunk>) -> Self {
+        let mut chunks = db::Tree::new(database.clone());
         {
-            let mut positions = positions.write();
-            for x in -20..21 {
-                for y in -20..21 {
-                    for z in -20..21 {
-                        let v = perlin.get([x as f64 / 20.0, y as f64 / 20.0, z as f64 / 20.0]);
-                        if v >= 0.0 {
-                            positions.push(Position {
-                                x: x as f32 * 3.0,
-                                y: y as f32 * 3.0,
-                                z: z as f32 * 3.0,
-                            });
-                        }
-                    }
-                }
+            let mut chunks = chunks.write();
+            for (coord, chunk) in generated {
+                chunks.insert(coord, chunk);
             }
         }
         let players = db::Vec::new(database.clone());
-        let player_index = db::Tree::new(database);
+        let player_index = db::Tree::new(database.clone());
+        let profiles = Blob::new(database.clone());
+        let chat = Blob::new(database.clone());
+        let access = Blob::new(database);
+        Self {
+            chunks,
+            players,
+            player_index,
+            profiles,
+            chat,
+            access,
+        }
+    }
+
+    pub fn export(&self) -> io::Result<WorldData> {
+        Ok(WorldData {
+            chunks: self
+                .chunks
+                .read()
+                .iter()
+                .map(|(coord, chunk)| (*coord, *chunk))
+                .collect(),
+            players: self.players.read().iter().copied().collect(),
+            player_index: self
+                .player_index
+                .read()
+                .iter()
+                .map(|(uuid, index)| (Uuid::from_u128(*uuid), *index))
+                .collect(),
+            profiles: self
+                .profiles
+                .read()
+                .iter()
+                .map(|entry| entry.map(|(uuid, profile)| (Uuid::from_u128(uuid), profile)))
+                .collect::<io::Result<_>>()?,
+            chat: self.chat_history()?,
+            access: self.access()?,
+        })
+    }
+
+    /// Builds the world from exported collections except for the profiles, the chat and the
+    /// access control, which are stored separately with `import_records` because storing them
+    /// can fail.
+    pub fn import(database: db::DatabaseRef, data: WorldData) -> Self {
+        let mut chunks = db::Tree::new(database.clone());
+        let mut players = db::Vec::new(database.clone());
+        let mut player_index = db::Tree::new(database.clone());
+        let profiles = Blob::new(database.clone());
+        let chat = Blob::new(database.clone());
+        let access = Blob::new(database);
+        {
+            let mut chunks = chunks.write();
+            for (coord, chunk) in data.chunks {
+                chunks.insert(coord, chunk);
+            }
+        }
+        {
+            let mut players = players.write();
+            for player in data.players {
+                players.push(player);
+            }
+        }
+        {
+            let mut player_index = player_index.write();
+            for (uuid, index) in data.player_index {
+                player_index.insert(uuid.as_u128(), index);
+            }
+        }
         Self {
-            positions,
+            chunks,
             players,
             player_index,
+            profiles,
+            chat,
+            access,
         }
     }
 
-    pub fn register_player(&mut self, uuid: Uuid, updates: &mut Vec<Update>) {
+    pub fn import_records(
+        &mut self,
+        profiles: Vec<(Uuid, Profile)>,
+        chat: Vec<ChatMessage>,
+        access: &AccessControl,
+    ) -> io::Result<()> {
+        self.set_access(access)?;
+        {
+            let mut writer = self.profiles.write();
+            for (uuid, profile) in profiles {
+                writer.insert(uuid.as_u128(), &profile)?;
+            }
+        }
+        for message in chat {
+            self.add_chat_message(&message)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the player if it is new, marks it online and records the login in its profile. A
+    /// player who is still online from a previous session is only online once, so the time of
+    /// that session is added to the play time here.
+    pub fn register_player(&mut self, uuid: Uuid, name: &str) -> io::Result<()> {
+        let online = self.player(uuid).map_or(false, |player| player.is_online());
+        self.add_player(uuid);
+        let now = unix_time();
+        let mut profiles = self.profiles.write();
+        let mut profile = profiles.get(&uuid.as_u128())?.unwrap_or(Profile {
+            name: String::new(),
+            first_seen: now,
+            last_seen: now,
+            play_time: 0,
+            logins: 0,
+        });
+        if online {
+            profile.play_time += now.saturating_sub(profile.last_seen);
+        }
+        profile.name = name.to_owned();
+        profile.last_seen = now;
+        profile.logins += 1;
+        profiles.insert(uuid.as_u128(), &profile)
+    }
+
+    fn add_player(&mut self, uuid: Uuid) {
+        if let Some(index) = self.player_index.read().get(&uuid.as_u128()) {
+            self.players.write()[*index].online = 1;
+            return;
+        }
         if let Entry::Vacant(entry) = self.player_index.write().entry(&uuid.as_u128()) {
             let player = Player {
                 uuid: uuid.as_u128(),
@@ -101,16 +321,60 @@ impl World {
                     pitch: 0.0,
                     yaw: 0.0,
                 },
-                _padding: [0; 8],
+                online: 1,
+                _padding: [0; 7],
             };
             let mut writer = self.players.write();
             let index = writer.len();
             writer.push(player);
             entry.insert(index);
-            updates.push(Update::NewPlayer(player));
         }
     }
 
+    /// Marks the player offline and adds the time since it connected to its play time. Does
+    /// nothing if the player is not online.
+    pub fn unregister_player(&mut self, uuid: Uuid, updates: &mut Vec<Update>) -> io::Result<()> {
+        let index = match self.player_index.read().get(&uuid.as_u128()) {
+            Some(index) => *index,
+            None => return Ok(()),
+        };
+        {
+            let mut players = self.players.write();
+            if !players[index].is_online() {
+                return Ok(());
+            }
+            players[index].online = 0;
+        }
+        updates.push(Update::PlayerLeft(uuid));
+        self.record_play_time(uuid)
+    }
+
+    /// Adds the time since the player was last seen to its play time and sees it now.
+    pub fn record_play_time(&mut self, uuid: Uuid) -> io::Result<()> {
+        let now = unix_time();
+        let mut profiles = self.profiles.write();
+        if let Some(mut profile) = profiles.get(&uuid.as_u128())? {
+            profile.play_time += now.saturating_sub(profile.last_seen);
+            profile.last_seen = now;
+            profiles.insert(uuid.as_u128(), &profile)?;
+        }
+        Ok(())
+    }
+
+    /// Marks all players offline, since players still marked online after the world was opened
+    /// were connected when the server stopped.
+    pub fn reset_presence(&mut self) {
+        let mut players = self.players.write();
+        for index in 0..players.len() {
+            players[index].online = 0;
+        }
+    }
+
+    pub fn player(&self, uuid: Uuid) -> Option<Player> {
+        let index = *self.player_index.read().get(&uuid.as_u128())?;
+        Some(self.players.read()[index])
+    }
+
     pub fn update_player(
         &mut self,
         uuid: Uuid,
@@ -124,31 +388,126 @@ impl World {
         player.orientation = orientation;
         updates.push(Update::Player(uuid, pos, orientation))
     }
-}
 
-impl Object for World {
-    fn format() -> db::Format {
-        [64; 256]
+    /// Adds a chat message and drops the oldest ones beyond the history length.
+    pub fn add_chat_message(&mut self, message: &ChatMessage) -> io::Result<()> {
+        let mut chat = self.chat.write();
+        let keys: Vec<u64> = chat.keys().collect();
+        let next = keys.last().map_or(0, |last| last + 1);
+        chat.insert(next, message)?;
+        for key in keys
+            .iter()
+            .take((keys.len() + 1).saturating_sub(CHAT_HISTORY))
+        {
+            chat.remove(key);
+        }
+        Ok(())
     }
 
-    fn serialize(&mut self, mut writer: impl std::io::Write) -> std::io::Result<()> {
-        self.positions.serialize(&mut writer)?;
-        self.players.serialize(&mut writer)?;
-        self.player_index.serialize(&mut writer)?;
-        Ok(())
+    pub fn access(&self) -> io::Result<AccessControl> {
+        Ok(self.access.read().get(&ACCESS_KEY)?.unwrap_or_default())
     }
 
-    fn deserialize(
-        mut reader: impl std::io::Read,
-        database: db::DatabaseRef,
-    ) -> std::io::Result<Self> {
-        let positions = db::Vec::deserialize(&mut reader, database.clone())?;
-        let players = db::Vec::deserialize(&mut reader, database.clone())?;
-        let player_index = db::Tree::deserialize(&mut reader, database)?;
-        Ok(Self {
-            positions,
-            players,
-            player_index,
+    pub fn set_access(&mut self, access: &AccessControl) -> io::Result<()> {
+        self.access.write().insert(ACCESS_KEY, access)
+    }
+
+    /// The recent chat messages, oldest first.
+    pub fn chat_history(&self) -> io::Result<Vec<ChatMessage>> {
+        self.chat
+            .read()
+            .iter()
+            .map(|entry| entry.map(|(_, message)| message))
+            .collect()
+    }
+}
+
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use db::Database;
+
+    use super::*;
+
+    fn message(number: usize) -> ChatMessage {
+        ChatMessage {
+            sender: Uuid::nil(),
+            name: "test".to_owned(),
+            time: 0,
+            text: number.to_string(),
+        }
+    }
+
+    fn world(dir: &tempfile::TempDir) -> Database<World> {
+        Database::create(dir.path().join("world.db"), |database| {
+            World::new(database, BTreeMap::new())
         })
+        .unwrap()
+    }
+
+    fn profile(world: &World, uuid: Uuid) -> Profile {
+        world.profiles.read().get(&uuid.as_u128()).unwrap().unwrap()
+    }
+
+    /// Pretends that the player logged in or out `seconds` ago.
+    fn set_last_seen(world: &mut World, uuid: Uuid, seconds: u64) {
+        let mut profile = profile(world, uuid);
+        profile.last_seen = unix_time() - seconds;
+        world
+            .profiles
+            .write()
+            .insert(uuid.as_u128(), &profile)
+            .unwrap();
+    }
+
+    #[test]
+    fn chat_history_keeps_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut database = world(&dir);
+        let count = CHAT_HISTORY + 10;
+        for number in 0..count {
+            database.add_chat_message(&message(number)).unwrap();
+        }
+        let texts: Vec<_> = database
+            .chat_history()
+            .unwrap()
+            .into_iter()
+            .map(|message| message.text)
+            .collect();
+        let expected: Vec<_> = (count - CHAT_HISTORY..count)
+            .map(|number| number.to_string())
+            .collect();
+        assert_eq!(texts, expected);
+    }
+
+    #[test]
+    fn overlapping_sessions_count_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut database = world(&dir);
+        let uuid = Uuid::from_u128(1);
+        let mut updates = Vec::new();
+        database.register_player(uuid, "first").unwrap();
+        set_last_seen(&mut database, uuid, 100);
+        database.register_player(uuid, "second").unwrap();
+        let profile = profile(&database, uuid);
+        assert!((100..=101).contains(&profile.play_time));
+        assert_eq!(profile.logins, 2);
+        assert_eq!(profile.name, "second");
+        assert!(database.player(uuid).unwrap().is_online());
+        set_last_seen(&mut database, uuid, 50);
+        database.unregister_player(uuid, &mut updates).unwrap();
+        let play_time = self::profile(&database, uuid).play_time;
+        assert!((150..=152).contains(&play_time));
+        assert!(!database.player(uuid).unwrap().is_online());
+        assert_eq!(updates.len(), 1);
+        database.unregister_player(uuid, &mut updates).unwrap();
+        assert_eq!(self::profile(&database, uuid).play_time, play_time);
+        assert_eq!(updates.len(), 1);
     }
 }
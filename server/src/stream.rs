@@ -0,0 +1,170 @@
+use std::{collections::HashMap, mem::take};
+
+use db::Tree;
+
+use crate::{Chunk, ChunkBatch, ChunkCoord};
+
+/// Radius in chunks around a player within which chunks are sent.
+const VIEW_DISTANCE: i32 = 4;
+
+/// Radius in chunks beyond which sent chunks are unloaded. It is larger than the view distance
+/// so that moving back and forth over a chunk border does not resend chunks.
+const UNLOAD_DISTANCE: i32 = VIEW_DISTANCE + 1;
+
+/// Tracks which chunks a player has and which it still needs, nearest first.
+#[derive(Default)]
+pub struct ChunkStream {
+    center: Option<ChunkCoord>,
+    /// Chunks within the view distance that were handled, and whether they were sent, which
+    /// they are not if they are empty.
+    loaded: HashMap<ChunkCoord, bool>,
+    /// Chunks still to be handled, the nearest last.
+    pending: Vec<ChunkCoord>,
+    unload: Vec<ChunkCoord>,
+}
+
+impl ChunkStream {
+    /// Moves the center of the streamed area to the chunk the player is in.
+    pub fn move_to(&mut self, center: ChunkCoord) {
+        if self.center == Some(center) {
+            return;
+        }
+        self.center = Some(center);
+        let unload = UNLOAD_DISTANCE as i64 * UNLOAD_DISTANCE as i64;
+        let unload_list = &mut self.unload;
+        self.loaded.retain(|coord, sent| {
+            let keep = coord.distance_squared(center) <= unload;
+            if !keep && *sent {
+                unload_list.push(*coord);
+            }
+            keep
+        });
+        let view = VIEW_DISTANCE as i64 * VIEW_DISTANCE as i64;
+        self.pending.clear();
+        for x in -VIEW_DISTANCE..=VIEW_DISTANCE {
+            for y in -VIEW_DISTANCE..=VIEW_DISTANCE {
+                for z in -VIEW_DISTANCE..=VIEW_DISTANCE {
+                    let coord = ChunkCoord {
+                        x: center.x + x,
+                        y: center.y + y,
+                        z: center.z + z,
+                    };
+                    if coord.distance_squared(center) <= view && !self.loaded.contains_key(&coord) {
+                        self.pending.push(coord);
+                    }
+                }
+            }
+        }
+        self.pending
+            .sort_by_key(|coord| -coord.distance_squared(center));
+    }
+
+    /// Collects the chunks to unload and up to `limit` of the nearest chunks still to be sent.
+    /// Empty chunks are skipped and do not count towards the limit.
+    pub fn batch(&mut self, chunks: &Tree<ChunkCoord, Chunk>, limit: usize) -> Option<ChunkBatch> {
+        let unload = take(&mut self.unload);
+        let chunks = chunks.read();
+        let mut load = Vec::new();
+        while load.len() < limit {
+            let coord = match self.pending.pop() {
+                Some(coord) => coord,
+                None => break,
+            };
+            let chunk = chunks.get(&coord).copied();
+            self.loaded.insert(coord, chunk.is_some());
+            if let Some(chunk) = chunk {
+                load.push((coord, chunk));
+            }
+        }
+        if load.is_empty() && unload.is_empty() {
+            None
+        } else {
+            Some(ChunkBatch(load, unload))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bytemuck::Zeroable;
+    use db::Database;
+
+    use crate::World;
+
+    use super::*;
+
+    const ORIGIN: ChunkCoord = ChunkCoord { x: 0, y: 0, z: 0 };
+
+    /// A world with all chunks within the unload distance of the origin.
+    fn world(dir: &tempfile::TempDir) -> Database<World> {
+        let mut chunks = BTreeMap::new();
+        let range = -UNLOAD_DISTANCE..=UNLOAD_DISTANCE;
+        for x in range.clone() {
+            for y in range.clone() {
+                for z in range.clone() {
+                    chunks.insert(ChunkCoord { x, y, z }, Chunk::zeroed());
+                }
+            }
+        }
+        Database::create(dir.path().join("world.db"), |database| {
+            World::new(database, chunks)
+        })
+        .unwrap()
+    }
+
+    fn coords(batch: Option<ChunkBatch>) -> Vec<ChunkCoord> {
+        batch.map_or_else(Vec::new, |batch| {
+            batch.0.into_iter().map(|(coord, _)| coord).collect()
+        })
+    }
+
+    #[test]
+    fn nearest_chunks_come_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let world = world(&dir);
+        let mut stream = ChunkStream::default();
+        stream.move_to(ORIGIN);
+        let sent = coords(stream.batch(&world.chunks, usize::MAX));
+        assert_eq!(sent[0], ORIGIN);
+        let view = VIEW_DISTANCE as i64 * VIEW_DISTANCE as i64;
+        assert!(sent
+            .iter()
+            .all(|coord| coord.distance_squared(ORIGIN) <= view));
+        assert!(sent
+            .windows(2)
+            .all(|pair| pair[0].distance_squared(ORIGIN) <= pair[1].distance_squared(ORIGIN)));
+        assert!(stream.batch(&world.chunks, usize::MAX).is_none());
+    }
+
+    #[test]
+    fn moving_reprioritizes_unsent_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let world = world(&dir);
+        let mut stream = ChunkStream::default();
+        stream.move_to(ORIGIN);
+        assert_eq!(coords(stream.batch(&world.chunks, 1)), vec![ORIGIN]);
+        let center = ChunkCoord { x: 3, y: 0, z: 0 };
+        stream.move_to(center);
+        let sent = coords(stream.batch(&world.chunks, 7));
+        assert_eq!(sent[0], center);
+        assert!(sent.iter().all(|coord| coord.distance_squared(center) <= 1));
+    }
+
+    #[test]
+    fn sent_chunks_are_not_resent() {
+        let dir = tempfile::tempdir().unwrap();
+        let world = world(&dir);
+        let mut stream = ChunkStream::default();
+        stream.move_to(ORIGIN);
+        let first = coords(stream.batch(&world.chunks, usize::MAX));
+        let center = ChunkCoord { x: 1, y: 0, z: 0 };
+        stream.move_to(center);
+        let second = coords(stream.batch(&world.chunks, usize::MAX));
+        assert!(!second.is_empty());
+        assert!(second.iter().all(|coord| !first.contains(coord)));
+        stream.move_to(ORIGIN);
+        assert!(stream.batch(&world.chunks, usize::MAX).is_none());
+    }
+}
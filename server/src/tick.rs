@@ -0,0 +1,120 @@
+use std::{collections::HashMap, time::Duration};
+
+use log::warn;
+use tokio::{
+    spawn,
+    sync::mpsc,
+    time::{sleep_until, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::{Orientation, Position, ServerMessage, Update, World};
+
+/// Ticks per second unless configured otherwise.
+pub const DEFAULT_TICK_RATE: u32 = 30;
+
+/// What the systems work with during a tick.
+pub struct TickContext<'a> {
+    /// Number of the tick, counting from the start of the server.
+    pub tick: u64,
+    /// Time since the previous tick.
+    pub dt: Duration,
+    pub world: &'a mut World,
+    /// Updates of the tick, which are pushed to the players that see them.
+    pub updates: &'a mut Vec<Update>,
+    /// The last accepted move of each player since the previous tick.
+    pub(crate) moves: &'a mut HashMap<Uuid, (Position, Orientation)>,
+}
+
+/// Game logic that runs once per tick, before the updates of the tick are pushed. The built-in
+/// systems run first, then the ones given to `Service::with_systems` in their order.
+pub trait System: Send {
+    fn run(&mut self, context: &mut TickContext);
+}
+
+/// Counts the ticks that took longer than the tick period, and reports them at most once per
+/// second.
+pub(crate) struct TickMetrics {
+    period: Duration,
+    pub ticks: u64,
+    pub overruns: u64,
+    pub longest: Duration,
+    report_start: Instant,
+    report_ticks: u64,
+    report_overruns: u64,
+    report_longest: Duration,
+}
+
+impl TickMetrics {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            ticks: 0,
+            overruns: 0,
+            longest: Duration::default(),
+            report_start: Instant::now(),
+            report_ticks: 0,
+            report_overruns: 0,
+            report_longest: Duration::default(),
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        self.ticks += 1;
+        self.report_ticks += 1;
+        self.longest = self.longest.max(duration);
+        self.report_longest = self.report_longest.max(duration);
+        if duration > self.period {
+            self.overruns += 1;
+            self.report_overruns += 1;
+        }
+        if self.report_start.elapsed() >= Duration::from_secs(1) {
+            if self.report_overruns > 0 {
+                warn!(
+                    "{} of {} ticks took longer than {:?}, the longest {:?}",
+                    self.report_overruns, self.report_ticks, self.period, self.report_longest
+                );
+            }
+            self.report_start = Instant::now();
+            self.report_ticks = 0;
+            self.report_overruns = 0;
+            self.report_longest = Duration::default();
+        }
+    }
+}
+
+/// Sends a tick message every period. Ticks that are missed because the main loop is behind are
+/// skipped rather than sent in a burst.
+pub(crate) fn drive_ticks(tx: mpsc::Sender<ServerMessage>, period: Duration) {
+    spawn(async move {
+        let mut next = Instant::now() + period;
+        loop {
+            sleep_until(next).await;
+            if tx.send(ServerMessage::Tick).await.is_err() {
+                break;
+            }
+            next += period;
+            let now = Instant::now();
+            if next < now {
+                next = now + period;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_count_overruns() {
+        let mut metrics = TickMetrics::new(Duration::from_millis(10));
+        metrics.record(Duration::from_millis(5));
+        metrics.record(Duration::from_millis(20));
+        metrics.record(Duration::from_millis(10));
+        assert_eq!(metrics.ticks, 3);
+        assert_eq!(metrics.overruns, 1);
+        assert_eq!(metrics.longest, Duration::from_millis(20));
+    }
+}
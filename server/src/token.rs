@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{state::unix_time, AuthenticationError};
+
+/// What a signed token states about its holder.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub uuid: Uuid,
+    pub name: String,
+    /// Seconds since the Unix epoch after which the token is no longer accepted.
+    pub expires: u64,
+    /// Name of the issuer whose key signed the token.
+    pub issuer: String,
+}
+
+/// How the server checks who a connecting user is.
+pub enum Authentication {
+    /// Accepts tokens of the form `uuid#base64(name)` without any check, so that anyone can
+    /// claim to be anyone. Only meant for local play.
+    Insecure,
+    /// Accepts tokens signed by one of the issuers, given by name with their Ed25519 public key.
+    Signed(HashMap<String, Vec<u8>>),
+}
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("could not generate key")]
+    GenerateKey,
+    #[error("signing key is no valid Ed25519 key in PKCS#8 format")]
+    InvalidKey,
+    #[error("could not serialize claims")]
+    Serialize(#[source] bincode::Error),
+}
+
+impl Authentication {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Authentication::Insecure => "none",
+            Authentication::Signed(_) => "ed25519",
+        }
+    }
+
+    /// Checks a token and returns the uuid and name of its holder.
+    pub(crate) fn verify(&self, token: &str) -> Result<(Uuid, String), AuthenticationError> {
+        match self {
+            Authentication::Insecure => verify_insecure(token),
+            Authentication::Signed(issuers) => {
+                let claims = verify_signed(token, issuers)?;
+                Ok((claims.uuid, claims.name))
+            }
+        }
+    }
+}
+
+fn verify_insecure(token: &str) -> Result<(Uuid, String), AuthenticationError> {
+    let mut split = token.split('#');
+    let uuid = if let Some(uuid) = split.next() {
+        Uuid::parse_str(uuid).map_err(AuthenticationError::ParseUuid)?
+    } else {
+        return Err(AuthenticationError::EmptyToken);
+    };
+    let name = if let Some(username) = split.next() {
+        String::from_utf8(base64::decode(username).map_err(AuthenticationError::DecodeUsername)?)
+            .map_err(AuthenticationError::IllformedUsername)?
+    } else {
+        return Err(AuthenticationError::MissingTokenSeparator);
+    };
+    Ok((uuid, name))
+}
+
+fn verify_signed(
+    token: &str,
+    issuers: &HashMap<String, Vec<u8>>,
+) -> Result<TokenClaims, AuthenticationError> {
+    let (claims, signature) = match token.find('.') {
+        Some(index) => (&token[..index], &token[index + 1..]),
+        None => return Err(AuthenticationError::MissingTokenSeparator),
+    };
+    let decode = |part: &str| base64::decode_config(part, base64::URL_SAFE_NO_PAD);
+    let bytes = decode(claims).map_err(AuthenticationError::DecodeToken)?;
+    let signature = decode(signature).map_err(AuthenticationError::DecodeToken)?;
+    let claims: TokenClaims =
+        bincode::deserialize(&bytes).map_err(AuthenticationError::IllformedClaims)?;
+    let key = issuers
+        .get(&claims.issuer)
+        .ok_or_else(|| AuthenticationError::UntrustedIssuer(claims.issuer.clone()))?;
+    UnparsedPublicKey::new(&ED25519, key)
+        .verify(&bytes, &signature)
+        .map_err(|_| AuthenticationError::InvalidSignature)?;
+    if claims.expires <= unix_time() {
+        return Err(AuthenticationError::Expired);
+    }
+    Ok(claims)
+}
+
+/// Generates an Ed25519 signing key in PKCS#8 format.
+pub fn generate_signing_key() -> Result<Vec<u8>, TokenError> {
+    let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| TokenError::GenerateKey)?;
+    Ok(document.as_ref().to_vec())
+}
+
+/// The public key servers need to trust tokens signed with the given signing key.
+pub fn public_key(signing_key: &[u8]) -> Result<Vec<u8>, TokenError> {
+    let key_pair = Ed25519KeyPair::from_pkcs8(signing_key).map_err(|_| TokenError::InvalidKey)?;
+    Ok(key_pair.public_key().as_ref().to_vec())
+}
+
+/// Creates a token for the claims, signed with a key in PKCS#8 format.
+pub fn sign_token(signing_key: &[u8], claims: &TokenClaims) -> Result<String, TokenError> {
+    let key_pair = Ed25519KeyPair::from_pkcs8(signing_key).map_err(|_| TokenError::InvalidKey)?;
+    let bytes = bincode::serialize(claims).map_err(TokenError::Serialize)?;
+    let signature = key_pair.sign(&bytes);
+    let encode = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+    Ok(format!(
+        "{}.{}",
+        encode(bytes.as_slice()),
+        encode(signature.as_ref())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A signing key and the authentication that trusts it as `issuer`.
+    fn issuer(issuer: &str) -> (Vec<u8>, Authentication) {
+        let signing_key = generate_signing_key().unwrap();
+        let mut issuers = HashMap::new();
+        issuers.insert(issuer.to_owned(), public_key(&signing_key).unwrap());
+        (signing_key, Authentication::Signed(issuers))
+    }
+
+    fn claims(issuer: &str, expires: u64) -> TokenClaims {
+        TokenClaims {
+            uuid: Uuid::from_u128(1),
+            name: "player".to_owned(),
+            expires,
+            issuer: issuer.to_owned(),
+        }
+    }
+
+    #[test]
+    fn signed_token_round_trip() {
+        let (signing_key, authentication) = issuer("hub");
+        let token = sign_token(&signing_key, &claims("hub", unix_time() + 60)).unwrap();
+        let (uuid, name) = authentication.verify(&token).unwrap();
+        assert_eq!(uuid, Uuid::from_u128(1));
+        assert_eq!(name, "player");
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let (signing_key, authentication) = issuer("hub");
+        let token = sign_token(&signing_key, &claims("hub", unix_time() - 1)).unwrap();
+        assert!(matches!(
+            authentication.verify(&token),
+            Err(AuthenticationError::Expired)
+        ));
+    }
+
+    #[test]
+    fn tampered_token_is_rejected() {
+        let (signing_key, authentication) = issuer("hub");
+        let expires = unix_time() + 60;
+        let token = sign_token(&signing_key, &claims("hub", expires)).unwrap();
+        let (payload, signature) = token.split_at(token.find('.').unwrap());
+        let mut other = claims("hub", expires);
+        other.name = "admin".to_owned();
+        let other = sign_token(&signing_key, &other).unwrap();
+        let other_payload = &other[..other.find('.').unwrap()];
+        let tampered = format!("{}{}", other_payload, signature);
+        assert!(matches!(
+            authentication.verify(&tampered),
+            Err(AuthenticationError::InvalidSignature)
+        ));
+        let mut bytes = base64::decode_config(&signature[1..], base64::URL_SAFE_NO_PAD).unwrap();
+        bytes[0] ^= 1;
+        let tampered = format!(
+            "{}.{}",
+            payload,
+            base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+        );
+        assert!(matches!(
+            authentication.verify(&tampered),
+            Err(AuthenticationError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn unknown_issuer_is_rejected() {
+        let (_, authentication) = issuer("hub");
+        let (signing_key, _) = issuer("other");
+        let expires = unix_time() + 60;
+        let token = sign_token(&signing_key, &claims("other", expires)).unwrap();
+        assert!(matches!(
+            authentication.verify(&token),
+            Err(AuthenticationError::UntrustedIssuer(issuer)) if issuer == "other"
+        ));
+        let token = sign_token(&signing_key, &claims("hub", expires)).unwrap();
+        assert!(matches!(
+            authentication.verify(&token),
+            Err(AuthenticationError::InvalidSignature)
+        ));
+    }
+}
@@ -1,11 +1,15 @@
 use net::Connection;
 use uuid::Uuid;
 
-use crate::Push;
+use crate::{Push, Role};
 
 #[derive(Clone, Debug)]
 pub(super) struct User {
     pub(super) uuid: Uuid,
     pub(super) name: String,
+    pub(super) role: Role,
     pub(super) connection: Connection<Push>,
+    /// Tells apart the sessions of a user who connects again before the previous connection
+    /// is known to be closed.
+    pub(super) session: u64,
 }